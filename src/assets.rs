@@ -0,0 +1,156 @@
+use crate::config::Config;
+use crate::constants::HttpClient;
+use chrono::Weekday;
+use rand::seq::SliceRandom;
+use rand::Rng;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use tracing::{error, info};
+
+pub const DEFAULT_CAPYBARA_URL: &str = "https://karei.dev/files/capybara-gifs/";
+pub const DEFAULT_PLACEHOLDER_IMG: &str = "https://karei.dev/files/capybara-default.jpg";
+const FILE_PREFIX: &str = "cp_";
+const FILE_EXTENSION: &str = ".gif";
+const WEEKDAYS: [Weekday; 7] = [
+  Weekday::Mon,
+  Weekday::Tue,
+  Weekday::Wed,
+  Weekday::Thu,
+  Weekday::Fri,
+  Weekday::Sat,
+  Weekday::Sun,
+];
+
+/// Where a capybara gif is posted from
+pub enum Gif {
+  /// Linked for Discord to fetch
+  Url(String),
+  /// Uploaded as an attachment
+  File(PathBuf),
+}
+
+fn day_name(weekday: Weekday) -> &'static str {
+  match weekday {
+    Weekday::Mon => "monday",
+    Weekday::Tue => "tuesday",
+    Weekday::Wed => "wednesday",
+    Weekday::Thu => "thursday",
+    Weekday::Fri => "friday",
+    Weekday::Sat => "saturday",
+    Weekday::Sun => "sunday",
+  }
+}
+
+/// `cp_monday.gif` for the first gif of a day, `cp_monday_2.gif` and so on for the rest
+fn file_name(weekday: Weekday, n: usize) -> String {
+  match n {
+    1 => format!("{}{}{}", FILE_PREFIX, day_name(weekday), FILE_EXTENSION),
+    _ => format!(
+      "{}{}_{}{}",
+      FILE_PREFIX,
+      day_name(weekday),
+      n,
+      FILE_EXTENSION
+    ),
+  }
+}
+
+fn is_gif_for(name: &str, weekday: Weekday) -> bool {
+  let stem = match name
+    .strip_prefix(FILE_PREFIX)
+    .and_then(|n| n.strip_suffix(FILE_EXTENSION))
+    .and_then(|n| n.strip_prefix(day_name(weekday)))
+  {
+    Some(s) => s,
+    None => return false,
+  };
+  match stem.strip_prefix('_') {
+    Some(n) => !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()),
+    None => stem.is_empty(),
+  }
+}
+
+/// Gifs for a weekday in a directory, read each time so files can be added while running
+async fn gifs_in(dir: &Path, weekday: Weekday) -> Vec<PathBuf> {
+  let mut entries = match tokio::fs::read_dir(dir).await {
+    Ok(e) => e,
+    Err(e) => {
+      error!("Error reading CAPYBARA_GIF_DIR({}): {}", dir.display(), e);
+      return Vec::new();
+    }
+  };
+  let mut gifs = Vec::new();
+  while let Ok(Some(entry)) = entries.next_entry().await {
+    let path = entry.path();
+    if path
+      .file_name()
+      .and_then(|n| n.to_str())
+      .is_some_and(|n| is_gif_for(n, weekday))
+    {
+      gifs.push(path);
+    }
+  }
+  gifs.sort();
+  gifs
+}
+
+/// One of the gifs for a weekday, picked at random
+pub async fn gif_for(config: &Config, weekday: Weekday) -> Option<Gif> {
+  match &config.capybara_dir {
+    Some(dir) => {
+      let gifs = gifs_in(dir, weekday).await;
+      gifs.choose(&mut rand::thread_rng()).cloned().map(Gif::File)
+    }
+    None => {
+      let n = rand::thread_rng().gen_range(1..=config.capybara_gifs_per_day);
+      Some(Gif::Url(format!(
+        "{}{}",
+        config.capybara_url,
+        file_name(weekday, n)
+      )))
+    }
+  }
+}
+
+/// Logs the gifs and placeholder image that can't be found, so missing files show up at startup
+/// instead of when someone asks for them
+pub fn check(config: &Config, client: HttpClient) -> impl Future<Output = ()> {
+  let mut urls = vec![config.placeholder_img.clone()];
+  if config.capybara_dir.is_none() {
+    urls.extend(WEEKDAYS.iter().flat_map(|d| {
+      (1..=config.capybara_gifs_per_day)
+        .map(|n| format!("{}{}", config.capybara_url, file_name(*d, n)))
+    }));
+  }
+  let dir = config.capybara_dir.clone();
+
+  async move {
+    let mut missing = Vec::new();
+    if let Some(dir) = dir {
+      for weekday in WEEKDAYS {
+        if gifs_in(&dir, weekday).await.is_empty() {
+          missing.push(format!("{}", dir.join(file_name(weekday, 1)).display()));
+        }
+      }
+    }
+
+    for url in urls {
+      match client.head(&url).send().await {
+        Ok(r) if r.status().is_success() => (),
+        Ok(r) => {
+          error!("{} returned {}", url, r.status());
+          missing.push(url);
+        }
+        Err(e) => {
+          error!("Error checking {}: {}", url, e);
+          missing.push(url);
+        }
+      }
+    }
+
+    match missing.is_empty() {
+      true => info!("All capybara assets found"),
+      false => error!("Missing capybara assets: {}", missing.join(", ")),
+    }
+  }
+}
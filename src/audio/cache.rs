@@ -0,0 +1,293 @@
+use crate::constants::YTDL_COMMAND;
+use crate::storage::Persisted;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::input::{AudioStream, AudioStreamError, AuxMetadata, Compose, File};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+use std::sync::Arc;
+use symphonia::core::io::MediaSource;
+use tokio::process::Command;
+use tracing::{error, info};
+
+pub const DEFAULT_MIN_PLAYS: u32 = 3;
+const FILE_EXTENSION: &str = "opus";
+/// Tracks whose plays are counted before the ones played once are forgotten
+const MAX_TRACKED_PLAYS: usize = 5000;
+
+pub struct AudioCacheStorage;
+
+impl TypeMapKey for AudioCacheStorage {
+  type Value = Arc<RwLock<AudioCache>>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedFile {
+  file: String,
+  size: u64,
+  last_used: u64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct CacheIndex {
+  files: HashMap<String, CachedFile>,
+  /// Plays of tracks that aren't cached yet, by URL
+  plays: HashMap<String, u32>,
+  hits: u64,
+  misses: u64,
+  uses: u64,
+}
+
+pub struct CacheStats {
+  pub max_size: u64,
+  pub min_plays: u32,
+  pub files: usize,
+  pub size: u64,
+  pub hits: u64,
+  pub misses: u64,
+  pub downloading: usize,
+}
+
+/// Size-bounded least recently used cache of downloaded audio for tracks played often
+pub struct AudioCache {
+  dir: PathBuf,
+  /// Bytes the files can take up, disabled if zero
+  max_size: u64,
+  min_plays: u32,
+  index: Persisted<CacheIndex>,
+  downloading: HashSet<String>,
+}
+
+impl AudioCache {
+  pub fn new(dir: PathBuf, max_size: u64, min_plays: u32) -> Self {
+    if max_size > 0 {
+      if let Err(e) = std::fs::create_dir_all(&dir) {
+        error!("Error creating {}: {}", dir.display(), e);
+      }
+    }
+
+    let mut index: Persisted<CacheIndex> = Persisted::load(dir.join("index.json"));
+    index.files.retain(|_, f| dir.join(&f.file).exists());
+
+    Self {
+      dir,
+      max_size,
+      min_plays,
+      index,
+      downloading: HashSet::new(),
+    }
+  }
+
+  /// Finds the cached file for a URL, plays are counted separately once the track starts
+  pub fn cached(&self, url: &str) -> Option<PathBuf> {
+    if self.max_size == 0 {
+      return None;
+    }
+    self.index.files.get(url).map(|f| self.dir.join(&f.file))
+  }
+
+  /// Counts a play of a URL, returning whether its audio should be downloaded now
+  pub fn record_play(&mut self, url: &str) -> bool {
+    if self.max_size == 0 {
+      return false;
+    }
+
+    self.index.uses += 1;
+    let uses = self.index.uses;
+    let cached = match self.index.files.get_mut(url) {
+      Some(f) => {
+        f.last_used = uses;
+        Some(self.dir.join(&f.file))
+      }
+      None => None,
+    };
+
+    match cached {
+      // Hits only change counters and recency, those are saved with the next miss or download
+      Some(path) if path.exists() => {
+        self.index.hits += 1;
+        false
+      }
+      _ => {
+        self.index.files.remove(url);
+        self.index.misses += 1;
+
+        let plays = self.index.plays.entry(url.to_string()).or_default();
+        *plays += 1;
+        let download = *plays >= self.min_plays && !self.downloading.contains(url);
+        if download {
+          self.downloading.insert(url.to_string());
+        }
+
+        if self.index.plays.len() > MAX_TRACKED_PLAYS {
+          self.index.plays.retain(|_, p| *p > 1);
+        }
+        self.index.save();
+        download
+      }
+    }
+  }
+
+  pub fn stats(&self) -> CacheStats {
+    CacheStats {
+      max_size: self.max_size,
+      min_plays: self.min_plays,
+      files: self.index.files.len(),
+      size: self.size(),
+      hits: self.index.hits,
+      misses: self.index.misses,
+      downloading: self.downloading.len(),
+    }
+  }
+
+  fn size(&self) -> u64 {
+    self.index.files.values().map(|f| f.size).sum()
+  }
+
+  fn finish_download(&mut self, url: &str, file: Option<(String, u64)>) {
+    self.downloading.remove(url);
+    let (file, size) = match file {
+      Some(f) => f,
+      None => return,
+    };
+
+    self.index.uses += 1;
+    let uses = self.index.uses;
+    self.index.plays.remove(url);
+    self.index.files.insert(
+      url.to_string(),
+      CachedFile {
+        file,
+        size,
+        last_used: uses,
+      },
+    );
+
+    while self.size() > self.max_size {
+      let oldest = self
+        .index
+        .files
+        .iter()
+        .min_by_key(|(_, f)| f.last_used)
+        .map(|(url, _)| url.clone());
+      let removed = match oldest.and_then(|url| self.index.files.remove(&url)) {
+        Some(f) => f,
+        None => break,
+      };
+      if let Err(e) = std::fs::remove_file(self.dir.join(&removed.file)) {
+        error!("Error removing cached audio {}: {}", removed.file, e);
+      }
+    }
+
+    self.index.save();
+  }
+}
+
+/// Downloads a track's audio into the cache, for when `record_play` says it should be
+pub async fn download(cache: Arc<RwLock<AudioCache>>, url: String) {
+  let dir = cache.read().await.dir.clone();
+  let name = file_name(&url);
+  let path = dir.join(format!("{}.{}", name, FILE_EXTENSION));
+
+  let output = Command::new(YTDL_COMMAND)
+    .args([
+      "-f",
+      "ba[abr>0][vcodec=none]/best",
+      "--no-playlist",
+      "--quiet",
+      "-x",
+      "--audio-format",
+      FILE_EXTENSION,
+      "-o",
+    ])
+    .arg(dir.join(format!("{}.%(ext)s", name)))
+    .arg(&url)
+    .output()
+    .await;
+
+  let file = match output {
+    Ok(o) if o.status.success() => match tokio::fs::metadata(&path).await {
+      Ok(m) => {
+        info!("Cached audio for {}", url);
+        Some((format!("{}.{}", name, FILE_EXTENSION), m.len()))
+      }
+      Err(e) => {
+        error!("Error reading cached audio for {}: {}", url, e);
+        None
+      }
+    },
+    Ok(o) => {
+      error!(
+        "Error caching audio for {}: {}",
+        url,
+        String::from_utf8_lossy(&o.stderr)
+      );
+      None
+    }
+    Err(e) => {
+      error!("Error caching audio for {}: {}", url, e);
+      None
+    }
+  };
+
+  if file.is_none() {
+    let _ = tokio::fs::remove_file(&path).await;
+  }
+  cache.write().await.finish_download(&url, file);
+}
+
+/// Plays a cached file, or the fallback if the file was evicted since the track was queued
+pub struct CachedAudio {
+  path: PathBuf,
+  fallback: Box<dyn Compose>,
+}
+
+impl CachedAudio {
+  pub fn new(path: PathBuf, fallback: Box<dyn Compose>) -> Self {
+    Self { path, fallback }
+  }
+}
+
+#[async_trait]
+impl Compose for CachedAudio {
+  fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+    self.fallback.create()
+  }
+
+  async fn create_async(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+    match File::new(self.path.clone()).create_async().await {
+      Ok(stream) => return Ok(stream),
+      Err(e) => info!("Cached audio {} is gone: {}", self.path.display(), e),
+    }
+    match self.fallback.should_create_async() {
+      true => self.fallback.create_async().await,
+      false => self.fallback.create(),
+    }
+  }
+
+  fn should_create_async(&self) -> bool {
+    true
+  }
+
+  async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+    self.fallback.aux_metadata().await
+  }
+}
+
+fn file_name(url: &str) -> String {
+  let mut hasher = DefaultHasher::new();
+  url.hash(&mut hasher);
+  format!("{:016x}", hasher.finish())
+}
+
+pub async fn get_audio_cache(ctx: &Context) -> Arc<RwLock<AudioCache>> {
+  let data = ctx.data.read().await;
+  data
+    .get::<AudioCacheStorage>()
+    .cloned()
+    .expect("AudioCacheStorage did not exist")
+}
@@ -0,0 +1,546 @@
+use crate::audio::filters::Filters;
+use std::f64::consts::PI;
+
+/// Rate everything leaving the DSP stage is resampled to, songbird's native mixing rate
+pub const SAMPLE_RATE: u32 = 48_000;
+
+pub type Frame = [f32; 2];
+
+const BASS_FREQUENCY: f64 = 100.0;
+const TREBLE_FREQUENCY: f64 = 8_000.0;
+const KARAOKE_CUTOFF: f64 = 150.0;
+
+const STRETCH_WINDOW: usize = 2048;
+const STRETCH_HOP: usize = STRETCH_WINDOW / 2;
+const STRETCH_TOLERANCE: usize = 256;
+
+/// Every filter in order, fed stereo frames at the source rate and producing frames at
+/// [`SAMPLE_RATE`]
+pub struct Chain {
+  input_rate: u32,
+  gain: f32,
+  karaoke: Option<[Biquad; 1]>,
+  bass: Option<[Biquad; 2]>,
+  treble: Option<[Biquad; 2]>,
+  resampler: Resampler,
+  stretcher: Option<Stretcher>,
+  panner: Option<Panner>,
+  scratch: Vec<Frame>,
+}
+
+impl Chain {
+  pub fn new(filters: &Filters, input_rate: u32) -> Self {
+    let mut chain = Self {
+      input_rate,
+      gain: 1.0,
+      karaoke: None,
+      bass: None,
+      treble: None,
+      resampler: Resampler::new(1.0),
+      stretcher: None,
+      panner: None,
+      scratch: vec![],
+    };
+    chain.configure(filters, input_rate);
+    chain
+  }
+
+  /// Updates the filter parameters, keeping as much state as possible so the change doesn't click
+  pub fn configure(&mut self, filters: &Filters, input_rate: u32) {
+    self.input_rate = input_rate;
+    let rate = input_rate as f64;
+
+    // Leave headroom for boosted frequencies instead of clipping
+    let boost = filters.bass.max(filters.treble).max(0.0);
+    self.gain = 10f64.powf(-boost / 40.0) as f32;
+
+    self.karaoke = match filters.karaoke {
+      true => Some(keep_coefficients(
+        self.karaoke,
+        [Biquad::low_pass(KARAOKE_CUTOFF, rate)],
+      )),
+      false => None,
+    };
+    self.bass = match filters.bass != 0.0 {
+      true => Some(keep_coefficients(
+        self.bass,
+        [Biquad::low_shelf(BASS_FREQUENCY, filters.bass, rate); 2],
+      )),
+      false => None,
+    };
+    self.treble = match filters.treble != 0.0 {
+      true => Some(keep_coefficients(
+        self.treble,
+        [Biquad::high_shelf(TREBLE_FREQUENCY, filters.treble, rate); 2],
+      )),
+      false => None,
+    };
+
+    // Resampling shifts speed and pitch together, the stretcher then corrects the speed
+    self.resampler.step = rate * filters.pitch / SAMPLE_RATE as f64;
+    let stretch = filters.speed / filters.pitch;
+    self.stretcher = match (stretch - 1.0).abs() > f64::EPSILON {
+      true => match self.stretcher.take() {
+        Some(mut s) => {
+          s.ratio = stretch;
+          Some(s)
+        }
+        None => Some(Stretcher::new(stretch)),
+      },
+      false => None,
+    };
+
+    self.panner = match filters.rotation > 0.0 {
+      true => {
+        let phase = self.panner.as_ref().map_or(0.0, |p| p.phase);
+        Some(Panner {
+          phase,
+          step: 2.0 * PI * filters.rotation / SAMPLE_RATE as f64,
+        })
+      }
+      false => None,
+    };
+  }
+
+  pub fn input_rate(&self) -> u32 {
+    self.input_rate
+  }
+
+  pub fn process(&mut self, input: &mut [Frame], out: &mut Vec<Frame>) {
+    for frame in input.iter_mut() {
+      let [mut l, mut r] = *frame;
+      if let Some([low_pass]) = &mut self.karaoke {
+        let mid = (l + r) * 0.5;
+        let side = (l - r) * 0.5;
+        let low = low_pass.process(mid);
+        l = low + side;
+        r = low - side;
+      }
+      if let Some([bl, br]) = &mut self.bass {
+        l = bl.process(l);
+        r = br.process(r);
+      }
+      if let Some([tl, tr]) = &mut self.treble {
+        l = tl.process(l);
+        r = tr.process(r);
+      }
+      *frame = [l * self.gain, r * self.gain];
+    }
+
+    match &mut self.stretcher {
+      Some(stretcher) => {
+        self.scratch.clear();
+        self.resampler.process(input, &mut self.scratch);
+        stretcher.process(&self.scratch, out);
+      }
+      None => self.resampler.process(input, out),
+    }
+
+    if let Some(panner) = &mut self.panner {
+      for frame in out.iter_mut() {
+        panner.process(frame);
+      }
+    }
+  }
+
+  /// Pushes out audio still held in the stretcher's window at the end of a track
+  pub fn flush(&mut self, out: &mut Vec<Frame>) {
+    let mut silence = vec![[0.0; 2]; STRETCH_WINDOW + STRETCH_TOLERANCE];
+    self.process(&mut silence, out);
+  }
+
+  /// Drops all buffered audio, used after seeking
+  pub fn reset(&mut self) {
+    for biquad in self
+      .karaoke
+      .iter_mut()
+      .flatten()
+      .chain(self.bass.iter_mut().flatten())
+      .chain(self.treble.iter_mut().flatten())
+    {
+      biquad.z1 = 0.0;
+      biquad.z2 = 0.0;
+    }
+    self.resampler = Resampler::new(self.resampler.step);
+    if let Some(stretcher) = &mut self.stretcher {
+      *stretcher = Stretcher::new(stretcher.ratio);
+    }
+  }
+}
+
+fn keep_coefficients<const N: usize>(old: Option<[Biquad; N]>, new: [Biquad; N]) -> [Biquad; N] {
+  match old {
+    Some(mut old) => {
+      for (o, n) in old.iter_mut().zip(new) {
+        o.b = n.b;
+        o.a = n.a;
+      }
+      old
+    }
+    None => new,
+  }
+}
+
+/// RBJ cookbook biquad in transposed direct form II
+#[derive(Clone, Copy)]
+pub(super) struct Biquad {
+  b: [f64; 3],
+  a: [f64; 2],
+  z1: f64,
+  z2: f64,
+}
+
+impl Biquad {
+  pub(super) fn new(b: [f64; 3], a: [f64; 3]) -> Self {
+    Self {
+      b: [b[0] / a[0], b[1] / a[0], b[2] / a[0]],
+      a: [a[1] / a[0], a[2] / a[0]],
+      z1: 0.0,
+      z2: 0.0,
+    }
+  }
+
+  fn low_pass(frequency: f64, rate: f64) -> Self {
+    let w0 = 2.0 * PI * frequency / rate;
+    let (sin, cos) = w0.sin_cos();
+    let alpha = sin / 2.0 * 2f64.sqrt();
+    Self::new(
+      [(1.0 - cos) / 2.0, 1.0 - cos, (1.0 - cos) / 2.0],
+      [1.0 + alpha, -2.0 * cos, 1.0 - alpha],
+    )
+  }
+
+  fn low_shelf(frequency: f64, gain: f64, rate: f64) -> Self {
+    let (a, cos, beta) = Self::shelf(frequency, gain, rate);
+    Self::new(
+      [
+        a * ((a + 1.0) - (a - 1.0) * cos + beta),
+        2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+        a * ((a + 1.0) - (a - 1.0) * cos - beta),
+      ],
+      [
+        (a + 1.0) + (a - 1.0) * cos + beta,
+        -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+        (a + 1.0) + (a - 1.0) * cos - beta,
+      ],
+    )
+  }
+
+  fn high_shelf(frequency: f64, gain: f64, rate: f64) -> Self {
+    let (a, cos, beta) = Self::shelf(frequency, gain, rate);
+    Self::new(
+      [
+        a * ((a + 1.0) + (a - 1.0) * cos + beta),
+        -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+        a * ((a + 1.0) + (a - 1.0) * cos - beta),
+      ],
+      [
+        (a + 1.0) - (a - 1.0) * cos + beta,
+        2.0 * ((a - 1.0) - (a + 1.0) * cos),
+        (a + 1.0) - (a - 1.0) * cos - beta,
+      ],
+    )
+  }
+
+  /// Shared shelf terms with a slope of 1
+  fn shelf(frequency: f64, gain: f64, rate: f64) -> (f64, f64, f64) {
+    let a = 10f64.powf(gain / 40.0);
+    let w0 = 2.0 * PI * frequency.min(rate * 0.45) / rate;
+    let (sin, cos) = w0.sin_cos();
+    let alpha = sin / 2.0 * 2f64.sqrt();
+    (a, cos, 2.0 * a.sqrt() * alpha)
+  }
+
+  pub(super) fn process(&mut self, x: f32) -> f32 {
+    let x = x as f64;
+    let y = self.b[0] * x + self.z1;
+    self.z1 = self.b[1] * x - self.a[0] * y + self.z2;
+    self.z2 = self.b[2] * x - self.a[1] * y;
+    y as f32
+  }
+}
+
+/// Cubic Hermite resampler, `step` is input frames consumed per output frame
+struct Resampler {
+  step: f64,
+  position: f64,
+  buffer: Vec<Frame>,
+}
+
+impl Resampler {
+  fn new(step: f64) -> Self {
+    Self {
+      step,
+      position: 1.0,
+      buffer: vec![[0.0; 2]],
+    }
+  }
+
+  fn process(&mut self, input: &[Frame], out: &mut Vec<Frame>) {
+    if (self.step - 1.0).abs() < f64::EPSILON && self.position == 1.0 && self.buffer.len() == 1 {
+      out.extend_from_slice(input);
+      return;
+    }
+
+    self.buffer.extend_from_slice(input);
+    while (self.position as usize) + 2 < self.buffer.len() {
+      let i = self.position as usize;
+      let t = (self.position - i as f64) as f32;
+      let [y0, y1, y2, y3] = [
+        self.buffer[i - 1],
+        self.buffer[i],
+        self.buffer[i + 1],
+        self.buffer[i + 2],
+      ];
+      out.push([0, 1].map(|c| hermite(y0[c], y1[c], y2[c], y3[c], t)));
+      self.position += self.step;
+    }
+
+    let consumed = (self.position as usize)
+      .saturating_sub(1)
+      .min(self.buffer.len());
+    self.buffer.drain(..consumed);
+    self.position -= consumed as f64;
+  }
+}
+
+fn hermite(y0: f32, y1: f32, y2: f32, y3: f32, t: f32) -> f32 {
+  let c1 = 0.5 * (y2 - y0);
+  let c2 = y0 - 2.5 * y1 + 2.0 * y2 - 0.5 * y3;
+  let c3 = 0.5 * (y3 - y0) + 1.5 * (y1 - y2);
+  ((c3 * t + c2) * t + c1) * t + y1
+}
+
+/// WSOLA time stretcher, changes tempo without changing pitch. `ratio` is input frames consumed
+/// per output frame
+struct Stretcher {
+  ratio: f64,
+  position: f64,
+  buffer: Vec<Frame>,
+  overlap: Vec<Frame>,
+  target: Vec<Frame>,
+  window: Vec<f32>,
+}
+
+impl Stretcher {
+  fn new(ratio: f64) -> Self {
+    Self {
+      ratio,
+      position: STRETCH_TOLERANCE as f64,
+      buffer: vec![[0.0; 2]; STRETCH_TOLERANCE],
+      overlap: vec![[0.0; 2]; STRETCH_HOP],
+      target: vec![],
+      window: (0..STRETCH_WINDOW)
+        .map(|n| (0.5 - 0.5 * (2.0 * PI * n as f64 / STRETCH_WINDOW as f64).cos()) as f32)
+        .collect(),
+    }
+  }
+
+  fn process(&mut self, input: &[Frame], out: &mut Vec<Frame>) {
+    self.buffer.extend_from_slice(input);
+
+    while (self.position as usize) + STRETCH_TOLERANCE + STRETCH_WINDOW <= self.buffer.len() {
+      let nominal = self.position as usize;
+      let start = self.best_start(nominal - STRETCH_TOLERANCE, nominal + STRETCH_TOLERANCE);
+      let segment = &self.buffer[start..start + STRETCH_WINDOW];
+
+      for (i, frame) in segment[..STRETCH_HOP].iter().enumerate() {
+        let w = self.window[i];
+        let o = self.overlap[i];
+        out.push([o[0] + frame[0] * w, o[1] + frame[1] * w]);
+      }
+      for (i, frame) in segment[STRETCH_HOP..].iter().enumerate() {
+        let w = self.window[STRETCH_HOP + i];
+        self.overlap[i] = [frame[0] * w, frame[1] * w];
+      }
+      // The next segment should line up with how this one would have carried on
+      self.target.clear();
+      self
+        .target
+        .extend_from_slice(&self.buffer[start + STRETCH_HOP..start + STRETCH_WINDOW]);
+
+      self.position += STRETCH_HOP as f64 * self.ratio;
+    }
+
+    let consumed = (self.position as usize)
+      .saturating_sub(STRETCH_TOLERANCE)
+      .min(self.buffer.len());
+    self.buffer.drain(..consumed);
+    self.position -= consumed as f64;
+  }
+
+  /// Offset in the search range whose start correlates best with the target
+  fn best_start(&self, from: usize, to: usize) -> usize {
+    if self.target.is_empty() {
+      return (from + to) / 2;
+    }
+
+    let mut best = (from + to) / 2;
+    let mut best_score = f32::MIN;
+    for start in (from..=to).step_by(2) {
+      let score: f32 = self
+        .target
+        .iter()
+        .zip(&self.buffer[start..])
+        .step_by(4)
+        .map(|(t, b)| (t[0] + t[1]) * (b[0] + b[1]))
+        .sum();
+      if score > best_score {
+        best_score = score;
+        best = start;
+      }
+    }
+    best
+  }
+}
+
+/// Sweeps the centre of the mix around the listener
+struct Panner {
+  phase: f64,
+  step: f64,
+}
+
+impl Panner {
+  fn process(&mut self, frame: &mut Frame) {
+    let [l, r] = *frame;
+    let mid = (l + r) * 0.5;
+    let side = (l - r) * 0.5;
+    let angle = (self.phase.sin() + 1.0) * PI / 4.0;
+    let (gr, gl) = angle.sin_cos();
+    let (gl, gr) = (
+      (gl * std::f64::consts::SQRT_2) as f32,
+      (gr * std::f64::consts::SQRT_2) as f32,
+    );
+    *frame = [mid * gl + side * 0.5, mid * gr - side * 0.5];
+    self.phase = (self.phase + self.step) % (2.0 * PI);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const TONE_SECONDS: usize = 3;
+
+  /// A 440Hz tone, different in each channel
+  fn tone(rate: u32) -> Vec<Frame> {
+    (0..rate as usize * TONE_SECONDS)
+      .map(|n| {
+        let t = n as f64 / rate as f64;
+        let l = (2.0 * PI * 440.0 * t).sin() * 0.5;
+        [l as f32, (l * 0.25) as f32]
+      })
+      .collect()
+  }
+
+  fn run(filters: &Filters, input_rate: u32, input: &[Frame]) -> Vec<Frame> {
+    let mut chain = Chain::new(filters, input_rate);
+    let mut input = input.to_vec();
+    let mut out = vec![];
+    chain.process(&mut input, &mut out);
+    out
+  }
+
+  /// The stretcher holds back a few windows of audio until it's flushed
+  fn near_length(len: usize, expected: f64) -> bool {
+    len as f64 <= expected && len as f64 > expected * 0.95
+  }
+
+  #[test]
+  fn identity_biquad_passes_samples_through() {
+    let mut biquad = Biquad::new([2.0, 0.0, 0.0], [2.0, 0.0, 0.0]);
+    for x in [0.0, 1.0, -0.5, 0.25, 0.0] {
+      assert_eq!(biquad.process(x), x);
+    }
+  }
+
+  #[test]
+  fn flat_shelves_pass_samples_through() {
+    let rate = SAMPLE_RATE as f64;
+    let mut low = Biquad::low_shelf(BASS_FREQUENCY, 0.0, rate);
+    let mut high = Biquad::high_shelf(TREBLE_FREQUENCY, 0.0, rate);
+    for [x, _] in tone(SAMPLE_RATE).into_iter().take(1000) {
+      assert!((low.process(x) - x).abs() < 1e-5);
+      assert!((high.process(x) - x).abs() < 1e-5);
+    }
+  }
+
+  #[test]
+  fn low_pass_keeps_dc_and_removes_high_frequencies() {
+    let rate = SAMPLE_RATE as f64;
+    let mut dc = Biquad::low_pass(KARAOKE_CUTOFF, rate);
+    let last = (0..10_000).map(|_| dc.process(1.0)).last().unwrap();
+    assert!((last - 1.0).abs() < 1e-3);
+
+    let mut high = Biquad::low_pass(KARAOKE_CUTOFF, rate);
+    let peak = tone(SAMPLE_RATE)
+      .into_iter()
+      .map(|[l, _]| high.process(l).abs())
+      .skip(1000)
+      .fold(0.0, f32::max);
+    assert!(peak < 0.1);
+  }
+
+  #[test]
+  fn unit_step_resampler_passes_frames_through() {
+    let input = tone(SAMPLE_RATE);
+    let mut resampler = Resampler::new(1.0);
+    let mut out = vec![];
+    resampler.process(&input, &mut out);
+    assert_eq!(out, input);
+  }
+
+  #[test]
+  fn resampler_output_length_follows_the_step() {
+    let input = tone(SAMPLE_RATE);
+    for step in [0.5, 2.0] {
+      let expected = input.len() as f64 / step;
+      let mut resampler = Resampler::new(step);
+      let mut out = vec![];
+      for chunk in input.chunks(1000) {
+        resampler.process(chunk, &mut out);
+      }
+      assert!((out.len() as f64 - expected).abs() < 8.0, "{}", out.len());
+    }
+  }
+
+  #[test]
+  fn default_chain_passes_audio_through() {
+    let input = tone(SAMPLE_RATE);
+    assert_eq!(run(&Filters::default(), SAMPLE_RATE, &input), input);
+  }
+
+  #[test]
+  fn chain_resamples_to_the_output_rate() {
+    let out = run(&Filters::default(), 44_100, &tone(44_100));
+    let expected = (SAMPLE_RATE as usize * TONE_SECONDS) as f64;
+    assert!((out.len() as f64 - expected).abs() < 8.0, "{}", out.len());
+  }
+
+  #[test]
+  fn speed_changes_the_output_length() {
+    let input = tone(SAMPLE_RATE);
+    for speed in [0.5, 2.0] {
+      let filters = Filters {
+        speed,
+        ..Filters::default()
+      };
+      let out = run(&filters, SAMPLE_RATE, &input);
+      let expected = input.len() as f64 / speed;
+      assert!(near_length(out.len(), expected), "{}", out.len());
+    }
+  }
+
+  #[test]
+  fn pitch_keeps_the_output_length() {
+    let input = tone(SAMPLE_RATE);
+    for pitch in [0.5, 2.0] {
+      let filters = Filters {
+        pitch,
+        ..Filters::default()
+      };
+      let out = run(&filters, SAMPLE_RATE, &input);
+      assert!(near_length(out.len(), input.len() as f64), "{}", out.len());
+    }
+  }
+}
@@ -0,0 +1,207 @@
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::prelude::{Mutex, TypeMapKey};
+use songbird::tracks::TrackHandle;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+pub const MIN_SPEED: f64 = 0.5;
+pub const MAX_SPEED: f64 = 2.0;
+pub const MIN_PITCH: f64 = 0.5;
+pub const MAX_PITCH: f64 = 2.0;
+pub const MAX_GAIN: f64 = 12.0;
+
+/// Filters shared between a guild's `/filter` command and every track it plays, read by the DSP
+/// stage on each decoded packet so changes apply to the current track too
+pub type SharedFilters = Arc<RwLock<Filters>>;
+
+pub struct FilterStorage;
+
+impl TypeMapKey for FilterStorage {
+  type Value = Arc<Mutex<HashMap<GuildId, SharedFilters>>>;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Preset {
+  BassBoost,
+  Nightcore,
+  Vaporwave,
+  EightD,
+  Karaoke,
+}
+
+impl Preset {
+  pub const ALL: [Preset; 5] = [
+    Self::BassBoost,
+    Self::Nightcore,
+    Self::Vaporwave,
+    Self::EightD,
+    Self::Karaoke,
+  ];
+
+  pub fn id(&self) -> &'static str {
+    match self {
+      Self::BassBoost => "bassboost",
+      Self::Nightcore => "nightcore",
+      Self::Vaporwave => "vaporwave",
+      Self::EightD => "8d",
+      Self::Karaoke => "karaoke",
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::BassBoost => "Bass boost",
+      Self::Nightcore => "Nightcore",
+      Self::Vaporwave => "Vaporwave",
+      Self::EightD => "8D",
+      Self::Karaoke => "Karaoke",
+    }
+  }
+
+  pub fn from_id(id: &str) -> Option<Self> {
+    Self::ALL.into_iter().find(|p| p.id() == id)
+  }
+
+  pub fn filters(&self) -> Filters {
+    let filters = match self {
+      Self::BassBoost => Filters {
+        bass: 8.0,
+        ..Default::default()
+      },
+      Self::Nightcore => Filters {
+        speed: 1.25,
+        pitch: 1.25,
+        ..Default::default()
+      },
+      Self::Vaporwave => Filters {
+        speed: 0.8,
+        pitch: 0.8,
+        treble: -3.0,
+        ..Default::default()
+      },
+      Self::EightD => Filters {
+        rotation: 0.2,
+        ..Default::default()
+      },
+      Self::Karaoke => Filters {
+        karaoke: true,
+        ..Default::default()
+      },
+    };
+    Filters {
+      preset: Some(*self),
+      ..filters
+    }
+  }
+}
+
+#[derive(Clone, PartialEq)]
+pub struct Filters {
+  /// Preset the values came from, cleared once they're customised
+  pub preset: Option<Preset>,
+  /// Tempo multiplier
+  pub speed: f64,
+  /// Pitch multiplier, independent of speed
+  pub pitch: f64,
+  /// Low shelf gain in dB
+  pub bass: f64,
+  /// High shelf gain in dB
+  pub treble: f64,
+  /// Auto-pan rotation in Hz, 0 to disable
+  pub rotation: f64,
+  /// Remove centre-panned vocals
+  pub karaoke: bool,
+}
+
+impl Default for Filters {
+  fn default() -> Self {
+    Self {
+      preset: None,
+      speed: 1.0,
+      pitch: 1.0,
+      bass: 0.0,
+      treble: 0.0,
+      rotation: 0.0,
+      karaoke: false,
+    }
+  }
+}
+
+impl Filters {
+  pub fn is_active(&self) -> bool {
+    *self != Self::default()
+  }
+}
+
+impl std::fmt::Display for Filters {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    if let Some(preset) = self.preset {
+      return write!(f, "{}", preset.name());
+    }
+
+    let mut parts = vec![];
+    if self.speed != 1.0 {
+      parts.push(format!("Speed {:.2}x", self.speed));
+    }
+    if self.pitch != 1.0 {
+      parts.push(format!("Pitch {:.2}x", self.pitch));
+    }
+    if self.bass != 0.0 {
+      parts.push(format!("Bass {:+.1}dB", self.bass));
+    }
+    if self.treble != 0.0 {
+      parts.push(format!("Treble {:+.1}dB", self.treble));
+    }
+    if self.rotation != 0.0 {
+      parts.push(format!("8D {:.2}Hz", self.rotation));
+    }
+    if self.karaoke {
+      parts.push("Karaoke".to_string());
+    }
+
+    match parts.is_empty() {
+      true => write!(f, "None"),
+      false => write!(f, "{}", parts.join(", ")),
+    }
+  }
+}
+
+pub async fn current_filters(ctx: &Context, guild_id: GuildId) -> Filters {
+  let shared = guild_filters(ctx, guild_id).await;
+  let filters = match shared.read() {
+    Ok(f) => f.clone(),
+    Err(e) => e.into_inner().clone(),
+  };
+  filters
+}
+
+/// Where a track is at in its own time. Songbird counts the time played, which the speed filter
+/// stretches, so this assumes the current speed applied since the start. Zero once it has ended
+pub async fn track_position(ctx: &Context, guild_id: GuildId, handle: &TrackHandle) -> Duration {
+  match handle.get_info().await {
+    Ok(state) => state
+      .position
+      .mul_f64(current_filters(ctx, guild_id).await.speed),
+    Err(_) => Duration::ZERO,
+  }
+}
+
+/// Played time songbird has to seek to for a position in the track, the inverse of
+/// `track_position`
+pub async fn played_time(ctx: &Context, guild_id: GuildId, position: Duration) -> Duration {
+  position.div_f64(current_filters(ctx, guild_id).await.speed)
+}
+
+pub async fn guild_filters(ctx: &Context, guild_id: GuildId) -> SharedFilters {
+  let storage = {
+    let data = ctx.data.read().await;
+    data
+      .get::<FilterStorage>()
+      .cloned()
+      .expect("FilterStorage did not exist")
+  };
+  let mut filters = storage.lock().await;
+  filters.entry(guild_id).or_default().clone()
+}
@@ -0,0 +1,51 @@
+use songbird::{
+  tracks::{TrackHandle, TrackResult},
+  typemap::TypeMapKey,
+};
+use std::sync::{Arc, Mutex};
+
+/// Parts of a track's volume set by different features, multiplied together
+#[derive(Clone, Copy)]
+pub struct Gain {
+  pub normalization: f32,
+  pub fade: f32,
+}
+
+impl Default for Gain {
+  fn default() -> Self {
+    Self {
+      normalization: 1.0,
+      fade: 1.0,
+    }
+  }
+}
+
+pub type SharedGain = Arc<Mutex<Gain>>;
+
+pub struct GainKey;
+
+impl TypeMapKey for GainKey {
+  type Value = SharedGain;
+}
+
+/// Changes one part of the gain and applies the result to the track
+pub fn update_gain(
+  handle: &TrackHandle,
+  gain: &SharedGain,
+  update: impl FnOnce(&mut Gain),
+) -> TrackResult<()> {
+  let volume = {
+    let mut gain = match gain.lock() {
+      Ok(g) => g,
+      Err(e) => e.into_inner(),
+    };
+    update(&mut gain);
+    gain.normalization * gain.fade
+  };
+  handle.set_volume(volume)
+}
+
+pub async fn track_gain(handle: &TrackHandle) -> SharedGain {
+  let mut data = handle.typemap().write().await;
+  data.entry::<GainKey>().or_default().clone()
+}
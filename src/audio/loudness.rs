@@ -0,0 +1,255 @@
+use crate::audio::dsp::{Biquad, Frame};
+use crate::audio::gain::{update_gain, SharedGain};
+use crate::storage::Persisted;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::prelude::{RwLock, TypeMapKey};
+use songbird::{events::Event, typemap, EventContext, EventHandler};
+use std::collections::HashMap;
+use std::f64::consts::PI;
+use std::sync::{Arc, Mutex};
+
+pub const DEFAULT_TARGET: f64 = -14.0;
+pub const MIN_TARGET: f64 = -30.0;
+pub const MAX_TARGET: f64 = -5.0;
+/// Most a quiet track gets boosted by, in dB
+const MAX_BOOST: f64 = 6.0;
+/// Most a loud track gets attenuated by, in dB
+const MAX_CUT: f64 = 20.0;
+
+/// Blocks of 400ms every 100ms, as in BS.1770
+const BLOCK_STEPS: usize = 4;
+const STEP_SECONDS: f64 = 0.1;
+/// Blocks needed before the measurement is used, so the first seconds don't swing the volume
+const MIN_BLOCKS: usize = 30;
+/// Blocks between updates of the shared measurement
+const PUBLISH_BLOCKS: usize = 10;
+const ABSOLUTE_GATE: f64 = -70.0;
+const RELATIVE_GATE: f64 = -10.0;
+
+pub struct LoudnessStorage;
+
+impl TypeMapKey for LoudnessStorage {
+  type Value = Arc<RwLock<Persisted<LoudnessCache>>>;
+}
+
+/// Integrated loudness of fully measured tracks in LUFS, by URL
+#[derive(Default, Serialize, Deserialize)]
+pub struct LoudnessCache {
+  tracks: HashMap<String, f64>,
+}
+
+impl LoudnessCache {
+  pub fn get(&self, url: &str) -> Option<f64> {
+    self.tracks.get(url).copied()
+  }
+
+  pub fn insert(&mut self, url: String, loudness: f64) {
+    self.tracks.insert(url, loudness);
+  }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Measurement {
+  /// Integrated loudness so far in LUFS
+  pub loudness: Option<f64>,
+  /// Whether the whole track has been measured
+  pub complete: bool,
+}
+
+/// Measurement updated by the DSP stage as the track decodes
+pub type SharedLoudness = Arc<Mutex<Measurement>>;
+
+pub struct LoudnessKey;
+
+impl typemap::TypeMapKey for LoudnessKey {
+  type Value = SharedLoudness;
+}
+
+/// Track volume that brings `loudness` to `target`
+pub fn normalized_volume(loudness: f64, target: f64) -> f32 {
+  let gain = (target - loudness).clamp(-MAX_CUT, MAX_BOOST);
+  10f64.powf(gain / 20.0) as f32
+}
+
+/// Progressive EBU R128 integrated loudness meter
+pub struct Meter {
+  rate: u32,
+  filters: [[Biquad; 2]; 2],
+  step_len: usize,
+  step_pos: usize,
+  step_energy: f64,
+  /// Mean square of the last steps, a block is the sum of `BLOCK_STEPS` of them
+  steps: Vec<f64>,
+  blocks: Vec<f64>,
+  unpublished: usize,
+  /// Seeking skips audio, so the result only covers part of the track
+  partial: bool,
+  shared: SharedLoudness,
+}
+
+impl Meter {
+  pub fn new(rate: u32, shared: SharedLoudness) -> Self {
+    Self {
+      rate,
+      filters: [k_weighting(rate as f64); 2],
+      step_len: (rate as f64 * STEP_SECONDS) as usize,
+      step_pos: 0,
+      step_energy: 0.0,
+      steps: vec![],
+      blocks: vec![],
+      unpublished: 0,
+      partial: false,
+      shared,
+    }
+  }
+
+  pub fn rate(&self) -> u32 {
+    self.rate
+  }
+
+  pub fn process(&mut self, frames: &[Frame]) {
+    for frame in frames {
+      for (channel, [shelf, high_pass]) in frame.iter().zip(self.filters.iter_mut()) {
+        let weighted = high_pass.process(shelf.process(*channel)) as f64;
+        self.step_energy += weighted * weighted;
+      }
+      self.step_pos += 1;
+
+      if self.step_pos >= self.step_len {
+        self.steps.push(self.step_energy / self.step_len as f64);
+        self.step_energy = 0.0;
+        self.step_pos = 0;
+
+        if self.steps.len() >= BLOCK_STEPS {
+          let start = self.steps.len() - BLOCK_STEPS;
+          self
+            .blocks
+            .push(self.steps[start..].iter().sum::<f64>() / BLOCK_STEPS as f64);
+          self.steps.drain(..=start);
+          self.unpublished += 1;
+          if self.unpublished >= PUBLISH_BLOCKS {
+            self.unpublished = 0;
+            self.publish(false);
+          }
+        }
+      }
+    }
+  }
+
+  pub fn skip(&mut self) {
+    self.partial = true;
+    self.step_energy = 0.0;
+    self.step_pos = 0;
+    self.steps.clear();
+  }
+
+  pub fn finish(&mut self) {
+    self.publish(!self.partial);
+  }
+
+  fn publish(&self, complete: bool) {
+    let loudness = match self.blocks.len() >= MIN_BLOCKS {
+      true => integrated(&self.blocks),
+      false => None,
+    };
+    let mut shared = match self.shared.lock() {
+      Ok(s) => s,
+      Err(e) => e.into_inner(),
+    };
+    *shared = Measurement { loudness, complete };
+  }
+}
+
+fn loudness(energy: f64) -> f64 {
+  -0.691 + 10.0 * energy.log10()
+}
+
+/// Gated mean of the block energies
+fn integrated(blocks: &[f64]) -> Option<f64> {
+  let mean = |blocks: &mut dyn Iterator<Item = &f64>| {
+    let (sum, count) = blocks.fold((0.0, 0), |(s, c), b| (s + b, c + 1));
+    match count {
+      0 => None,
+      _ => Some(sum / count as f64),
+    }
+  };
+
+  let above_absolute = |b: &&f64| loudness(**b) > ABSOLUTE_GATE;
+  let relative_gate = loudness(mean(&mut blocks.iter().filter(above_absolute))?) + RELATIVE_GATE;
+  mean(
+    &mut blocks
+      .iter()
+      .filter(above_absolute)
+      .filter(|b| loudness(**b) > relative_gate),
+  )
+  .map(loudness)
+}
+
+/// K-weighting pre-filter and high-pass, from the BS.1770 reference coefficients generalised to
+/// any sample rate
+fn k_weighting(rate: f64) -> [Biquad; 2] {
+  let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+  let k = (PI * f0 / rate).tan();
+  let vh = 10f64.powf(gain / 20.0);
+  let vb = vh.powf(0.4996667741545416);
+  let shelf = Biquad::new(
+    [
+      vh + vb * k / q + k * k,
+      2.0 * (k * k - vh),
+      vh - vb * k / q + k * k,
+    ],
+    [
+      1.0 + k / q + k * k,
+      2.0 * (k * k - 1.0),
+      1.0 - k / q + k * k,
+    ],
+  );
+
+  let (f0, q) = (38.13547087602444, 0.5003270373238773);
+  let k = (PI * f0 / rate).tan();
+  // The reference numerator isn't normalised like the denominator
+  let a0 = 1.0 + k / q + k * k;
+  let high_pass = Biquad::new(
+    [a0, -2.0 * a0, a0],
+    [a0, 2.0 * (k * k - 1.0), 1.0 - k / q + k * k],
+  );
+
+  [shelf, high_pass]
+}
+
+/// Adjusts the track's volume as its loudness measurement improves
+pub struct NormalizeVolume {
+  pub loudness: SharedLoudness,
+  pub gain: SharedGain,
+  pub target: f64,
+}
+
+#[async_trait]
+impl EventHandler for NormalizeVolume {
+  async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+    let handle = if let EventContext::Track(track_ctx) = ctx {
+      let (_state, handle) = track_ctx[0];
+      handle
+    } else {
+      return Some(Event::Cancel);
+    };
+
+    let measurement = match self.loudness.lock() {
+      Ok(m) => *m,
+      Err(e) => *e.into_inner(),
+    };
+
+    if let Some(loudness) = measurement.loudness {
+      let volume = normalized_volume(loudness, self.target);
+      if update_gain(handle, &self.gain, |g| g.normalization = volume).is_err() {
+        return Some(Event::Cancel);
+      }
+    }
+
+    match measurement.complete {
+      true => Some(Event::Cancel),
+      false => None,
+    }
+  }
+}
@@ -0,0 +1,8 @@
+pub mod cache;
+mod dsp;
+pub mod filters;
+pub mod gain;
+pub mod loudness;
+mod source;
+
+pub use source::Filtered;
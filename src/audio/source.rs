@@ -0,0 +1,340 @@
+use crate::audio::dsp::{Chain, Frame, SAMPLE_RATE};
+use crate::audio::filters::{Filters, SharedFilters};
+use crate::audio::loudness::{Meter, SharedLoudness};
+use serenity::async_trait;
+use songbird::input::{
+  codecs::{get_codec_registry, get_probe},
+  AudioStream, AudioStreamError, AuxMetadata, Compose, RawAdapter,
+};
+use std::io::{self, Read, Seek, SeekFrom};
+use symphonia::core::{
+  audio::SampleBuffer,
+  codecs::{Decoder, DecoderOptions},
+  errors::Error as SymphoniaError,
+  formats::{FormatOptions, FormatReader, SeekMode, SeekTo},
+  io::{MediaSource, MediaSourceStream},
+  meta::MetadataOptions,
+  units::Time,
+};
+use tracing::warn;
+
+/// Length of the header `RawAdapter` puts in front of the samples
+const RAW_HEADER_LEN: u64 = 16;
+const FRAME_BYTES: u64 = std::mem::size_of::<Frame>() as u64;
+
+/// Wraps a source so its audio goes through the guild's filters before reaching songbird,
+/// measuring its loudness on the way if asked to
+pub struct Filtered {
+  inner: Box<dyn Compose>,
+  filters: SharedFilters,
+  loudness: Option<SharedLoudness>,
+}
+
+impl Filtered {
+  pub fn new(
+    inner: Box<dyn Compose>,
+    filters: SharedFilters,
+    loudness: Option<SharedLoudness>,
+  ) -> Self {
+    Self {
+      inner,
+      filters,
+      loudness,
+    }
+  }
+
+  fn wrap(&self, stream: AudioStream<Box<dyn MediaSource>>) -> AudioStream<Box<dyn MediaSource>> {
+    let source = DspSource::new(stream, self.filters.clone(), self.loudness.clone());
+    AudioStream {
+      input: Box::new(RawAdapter::new(source, SAMPLE_RATE, 2)),
+      hint: None,
+    }
+  }
+}
+
+#[async_trait]
+impl Compose for Filtered {
+  fn create(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+    self.inner.create().map(|s| self.wrap(s))
+  }
+
+  async fn create_async(&mut self) -> Result<AudioStream<Box<dyn MediaSource>>, AudioStreamError> {
+    self.inner.create_async().await.map(|s| self.wrap(s))
+  }
+
+  fn should_create_async(&self) -> bool {
+    self.inner.should_create_async()
+  }
+
+  async fn aux_metadata(&mut self) -> Result<AuxMetadata, AudioStreamError> {
+    self.inner.aux_metadata().await
+  }
+}
+
+struct Decoding {
+  format: Box<dyn FormatReader>,
+  decoder: Box<dyn Decoder>,
+  track_id: u32,
+  samples: Option<SampleBuffer<f32>>,
+}
+
+enum State {
+  /// Probing reads from the network, so it waits for the first read on songbird's decode thread
+  Pending(Option<AudioStream<Box<dyn MediaSource>>>),
+  Decoding(Box<Decoding>),
+}
+
+/// Decodes the source and outputs filtered 48kHz stereo f32 samples for `RawAdapter`
+struct DspSource {
+  state: State,
+  seekable: bool,
+  finished: bool,
+  filters: SharedFilters,
+  current: Filters,
+  chain: Option<Chain>,
+  loudness: Option<SharedLoudness>,
+  meter: Option<Meter>,
+  frames: Vec<Frame>,
+  processed: Vec<Frame>,
+  output: Vec<u8>,
+  output_pos: usize,
+}
+
+impl DspSource {
+  fn new(
+    stream: AudioStream<Box<dyn MediaSource>>,
+    filters: SharedFilters,
+    loudness: Option<SharedLoudness>,
+  ) -> Self {
+    Self {
+      seekable: stream.input.is_seekable(),
+      state: State::Pending(Some(stream)),
+      finished: false,
+      current: Filters::default(),
+      filters,
+      chain: None,
+      loudness,
+      meter: None,
+      frames: vec![],
+      processed: vec![],
+      output: vec![],
+      output_pos: 0,
+    }
+  }
+
+  fn decoding(&mut self) -> io::Result<&mut Decoding> {
+    if let State::Pending(stream) = &mut self.state {
+      let stream = stream.take().ok_or(io::ErrorKind::BrokenPipe)?;
+      self.state = State::Decoding(Box::new(probe(stream)?));
+    }
+
+    match &mut self.state {
+      State::Decoding(d) => Ok(d),
+      State::Pending(_) => Err(io::ErrorKind::BrokenPipe.into()),
+    }
+  }
+
+  /// Decodes and filters the next packet, returns false once the source has ended
+  fn fill(&mut self) -> io::Result<bool> {
+    self.output.clear();
+    self.output_pos = 0;
+    self.processed.clear();
+
+    if self.finished {
+      return Ok(false);
+    }
+
+    let filters = match self.filters.read() {
+      Ok(f) => f.clone(),
+      Err(e) => e.into_inner().clone(),
+    };
+
+    let mut frames = std::mem::take(&mut self.frames);
+    match next_frames(self.decoding()?, &mut frames)? {
+      Some(rate) => {
+        // Measured before filtering, filters shouldn't change how loud the track is made
+        if let Some(shared) = &self.loudness {
+          let meter = self
+            .meter
+            .get_or_insert_with(|| Meter::new(rate, shared.clone()));
+          if meter.rate() == rate {
+            meter.process(&frames);
+          }
+        }
+
+        let chain = self.chain.get_or_insert_with(|| Chain::new(&filters, rate));
+        if filters != self.current || chain.input_rate() != rate {
+          chain.configure(&filters, rate);
+        }
+        chain.process(&mut frames, &mut self.processed);
+        self.current = filters;
+      }
+      None => {
+        self.finished = true;
+        if let Some(meter) = &mut self.meter {
+          meter.finish();
+        }
+        if let Some(chain) = &mut self.chain {
+          chain.flush(&mut self.processed);
+        }
+      }
+    }
+    self.frames = frames;
+
+    self.output.extend(
+      self
+        .processed
+        .iter()
+        .flatten()
+        .flat_map(|s| s.to_le_bytes()),
+    );
+    Ok(!self.finished || !self.output.is_empty())
+  }
+}
+
+fn probe(stream: AudioStream<Box<dyn MediaSource>>) -> io::Result<Decoding> {
+  let mss = MediaSourceStream::new(stream.input, Default::default());
+  let probed = get_probe()
+    .format(
+      &stream.hint.unwrap_or_default(),
+      mss,
+      &FormatOptions {
+        enable_gapless: true,
+        ..Default::default()
+      },
+      &MetadataOptions::default(),
+    )
+    .map_err(to_io_error)?;
+
+  let format = probed.format;
+  let track = format
+    .default_track()
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "No audio track in source"))?;
+  let decoder = get_codec_registry()
+    .make(&track.codec_params, &DecoderOptions::default())
+    .map_err(to_io_error)?;
+
+  Ok(Decoding {
+    track_id: track.id,
+    format,
+    decoder,
+    samples: None,
+  })
+}
+
+/// Decodes the next packet as stereo frames, returning its sample rate or `None` at the end
+fn next_frames(decoding: &mut Decoding, frames: &mut Vec<Frame>) -> io::Result<Option<u32>> {
+  frames.clear();
+  loop {
+    let packet = match decoding.format.next_packet() {
+      Ok(p) => p,
+      Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {
+        return Ok(None)
+      }
+      Err(e) => return Err(to_io_error(e)),
+    };
+    if packet.track_id() != decoding.track_id {
+      continue;
+    }
+
+    let decoded = match decoding.decoder.decode(&packet) {
+      Ok(d) => d,
+      Err(SymphoniaError::DecodeError(e)) => {
+        warn!("Skipping undecodable packet: {}", e);
+        continue;
+      }
+      Err(e) => return Err(to_io_error(e)),
+    };
+
+    let spec = *decoded.spec();
+    let samples = match &mut decoding.samples {
+      Some(s) if s.capacity() >= decoded.capacity() * spec.channels.count() => s,
+      s => s.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+    };
+    samples.copy_interleaved_ref(decoded);
+
+    let channels = spec.channels.count();
+    match channels {
+      0 => continue,
+      1 => frames.extend(samples.samples().iter().map(|s| [*s, *s])),
+      _ => frames.extend(
+        samples
+          .samples()
+          .chunks_exact(channels)
+          .map(|c| [c[0], c[1]]),
+      ),
+    }
+    return Ok(Some(spec.rate));
+  }
+}
+
+fn to_io_error(e: SymphoniaError) -> io::Error {
+  match e {
+    SymphoniaError::IoError(e) => e,
+    e => io::Error::other(e),
+  }
+}
+
+impl Read for DspSource {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    while self.output_pos >= self.output.len() {
+      if !self.fill()? {
+        return Ok(0);
+      }
+    }
+
+    let n = buf.len().min(self.output.len() - self.output_pos);
+    buf[..n].copy_from_slice(&self.output[self.output_pos..self.output_pos + n]);
+    self.output_pos += n;
+    Ok(n)
+  }
+}
+
+impl Seek for DspSource {
+  fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+    let offset = match pos {
+      SeekFrom::Start(offset) => offset,
+      _ => return Err(io::ErrorKind::Unsupported.into()),
+    };
+
+    // RawAdapter passes offsets including its header and expects one without it back
+    let frame = offset.saturating_sub(RAW_HEADER_LEN) / FRAME_BYTES;
+    // The output is sped up or slowed down, so map back to a time in the source
+    let time = frame as f64 / SAMPLE_RATE as f64 * self.current.speed;
+
+    let decoding = self.decoding()?;
+    decoding
+      .format
+      .seek(
+        SeekMode::Accurate,
+        SeekTo::Time {
+          time: Time::from(time),
+          track_id: Some(decoding.track_id),
+        },
+      )
+      .map_err(to_io_error)?;
+    decoding.decoder.reset();
+
+    if let Some(chain) = &mut self.chain {
+      chain.reset();
+    }
+    if let Some(meter) = &mut self.meter {
+      meter.skip();
+    }
+    self.output.clear();
+    self.output_pos = 0;
+    self.finished = false;
+
+    Ok(frame * FRAME_BYTES)
+  }
+}
+
+impl MediaSource for DspSource {
+  fn is_seekable(&self) -> bool {
+    self.seekable
+  }
+
+  fn byte_len(&self) -> Option<u64> {
+    None
+  }
+}
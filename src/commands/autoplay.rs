@@ -0,0 +1,198 @@
+use crate::commands::{
+  events::playing_message,
+  playback::{enqueue_song, Requester, SongMetadata},
+  resolve::{resolve, stored_source},
+  ticker,
+};
+use crate::constants::{YTDL_COMMAND, YTDL_TIMEOUT};
+use crate::history::HistoryStorage;
+use crate::playlists::{PlaylistScope, PlaylistStorage};
+use crate::segments::youtube_id;
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+use serenity::model::id::GuildId;
+use std::collections::HashSet;
+use tokio::process::Command;
+use tracing::{error, info};
+
+/// Tracks played this recently aren't picked again
+const RECENT_TRACKS: usize = 20;
+/// Entries of a YouTube mix that are considered
+const MIX_LENGTH: usize = 25;
+/// Related tracks a pick is made from, the mix gets less related further down
+const MIX_PICKS: usize = 5;
+
+#[derive(Deserialize)]
+struct MixEntry {
+  url: Option<String>,
+  id: Option<String>,
+}
+
+/// Queues a track related to `last` now that the queue has run out
+pub async fn autoplay(
+  ctx: &Context,
+  command: &CommandInteraction,
+  guild_id: GuildId,
+  last: &SongMetadata,
+) {
+  let handler_lock = match songbird::get(ctx).await.and_then(|m| m.get(guild_id)) {
+    Some(h) => h,
+    None => return,
+  };
+
+  let recent = recent_urls(ctx, guild_id).await;
+  let mix = match last.url.as_deref().and_then(youtube_id) {
+    Some(id) => related_tracks(&id).await,
+    None => vec![],
+  };
+  let mix = mix
+    .into_iter()
+    .filter(|url| !recent.contains(url))
+    .take(MIX_PICKS)
+    .collect::<Vec<_>>();
+
+  let (source, metadata) = match pick(&mix) {
+    Some(url) => resolve(ctx, url).await,
+    None => match pick(&library(ctx, guild_id, &recent).await) {
+      Some(metadata) => (stored_source(ctx, metadata).await, metadata.clone()),
+      None => {
+        info!("Nothing to autoplay in Guild({})", guild_id);
+        return;
+      }
+    },
+  };
+
+  let requester = Requester {
+    id: ctx.cache.current_user().id,
+    name: "Autoplay".to_string(),
+    avatar: ctx.cache.current_user().face(),
+  };
+
+  let handle = {
+    let mut handler = handler_lock.lock().await;
+    // Someone queued something while the track was being found
+    if !handler.queue().is_empty() {
+      return;
+    }
+    let title = metadata.title.clone();
+    match enqueue_song(ctx, command, &mut handler, source, metadata, requester).await {
+      Ok(h) => h,
+      Err(e) => {
+        info!("Couldn't autoplay {}: {}", title, e);
+        return;
+      }
+    }
+  };
+
+  let message = playing_message(ctx, guild_id, "Autoplaying", &handle).await;
+  match command.channel_id.send_message(&ctx.http, message).await {
+    Ok(m) => ticker::watch_now_playing(ctx, guild_id, m, handle, "Autoplaying").await,
+    Err(e) => error!("{}", e),
+  }
+}
+
+/// Tracks from the video's YouTube mix
+async fn related_tracks(id: &str) -> Vec<String> {
+  let output = Command::new(YTDL_COMMAND)
+    .args([
+      "-j",
+      "--flat-playlist",
+      "--playlist-end",
+      &MIX_LENGTH.to_string(),
+      &format!("https://www.youtube.com/watch?v={id}&list=RD{id}", id = id),
+    ])
+    .kill_on_drop(true)
+    .output();
+
+  let output = match tokio::time::timeout(YTDL_TIMEOUT, output).await {
+    Err(_) => {
+      error!("Error getting related tracks: yt-dlp took too long");
+      return vec![];
+    }
+    Ok(Ok(o)) if o.status.success() => o.stdout,
+    Ok(Ok(o)) => {
+      error!(
+        "Error getting related tracks: {}",
+        String::from_utf8_lossy(&o.stderr)
+      );
+      return vec![];
+    }
+    Ok(Err(e)) => {
+      error!("Error getting related tracks: {}", e);
+      return vec![];
+    }
+  };
+
+  output
+    .split(|b| *b == b'\n')
+    .filter_map(|l| serde_json::from_slice::<MixEntry>(l).ok())
+    .filter_map(|e| {
+      e.url.filter(|u| u.contains("https://")).or_else(|| {
+        e.id
+          .map(|id| format!("https://www.youtube.com/watch?v={}", id))
+      })
+    })
+    .collect()
+}
+
+async fn recent_urls(ctx: &Context, guild_id: GuildId) -> HashSet<String> {
+  let history_lock = {
+    let data = ctx.data.read().await;
+    data
+      .get::<HistoryStorage>()
+      .cloned()
+      .expect("HistoryStorage did not exist")
+  };
+  let history = history_lock.read().await;
+  history
+    .get(guild_id)
+    .take(RECENT_TRACKS)
+    .filter_map(|e| e.metadata.url.clone())
+    .collect()
+}
+
+/// Tracks the guild has played before or saved in its playlists
+async fn library(ctx: &Context, guild_id: GuildId, recent: &HashSet<String>) -> Vec<SongMetadata> {
+  let (history_lock, playlists_lock) = {
+    let data = ctx.data.read().await;
+    (
+      data
+        .get::<HistoryStorage>()
+        .cloned()
+        .expect("HistoryStorage did not exist"),
+      data
+        .get::<PlaylistStorage>()
+        .cloned()
+        .expect("PlaylistStorage did not exist"),
+    )
+  };
+
+  let mut tracks = history_lock
+    .read()
+    .await
+    .get(guild_id)
+    .map(|e| e.metadata.clone())
+    .collect::<Vec<_>>();
+  tracks.extend(
+    playlists_lock
+      .read()
+      .await
+      .list(PlaylistScope::Guild(guild_id))
+      .into_iter()
+      .flat_map(|(_, p)| p.tracks.iter().cloned()),
+  );
+
+  let mut seen = HashSet::new();
+  tracks.retain(|t| match &t.url {
+    Some(url) => !recent.contains(url) && seen.insert(url.clone()),
+    None => false,
+  });
+  tracks
+}
+
+/// Keeps the thread's generator out of the async fns, it can't be held across an await
+fn pick<T>(items: &[T]) -> Option<&T> {
+  items.choose(&mut rand::thread_rng())
+}
@@ -0,0 +1,53 @@
+use crate::commands::{text_response, utils::get_bool_option, Command};
+use crate::settings::get_storage;
+use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateCommandOption};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+use serenity::Error;
+
+pub struct Autoplay;
+
+const ENABLED_OPTION_NAME: &str = "enabled";
+
+#[async_trait]
+impl Command for Autoplay {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let guild_id = match command.guild_id {
+      Some(g) => g,
+      None => return text_response(ctx, command, "Error getting guild information").await,
+    };
+
+    let enabled = {
+      let storage = get_storage(ctx).await;
+      let mut settings = storage.write().await;
+      let guild_settings = settings.get_mut(guild_id);
+      guild_settings.autoplay = get_bool_option(&command.data.options(), ENABLED_OPTION_NAME)
+        .unwrap_or(!guild_settings.autoplay);
+      let enabled = guild_settings.autoplay;
+      settings.save();
+      enabled
+    };
+
+    let text = match enabled {
+      true => "Autoplay on, related tracks play when the queue runs out",
+      false => "Autoplay off",
+    };
+    text_response(ctx, command, text).await
+  }
+
+  fn name() -> &'static str {
+    "autoplay"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("Keep playing related tracks when the queue runs out")
+      .dm_permission(false)
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::Boolean,
+        ENABLED_OPTION_NAME,
+        "Turn autoplay on or off, toggles it if left out",
+      ))
+  }
+}
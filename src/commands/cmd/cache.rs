@@ -0,0 +1,89 @@
+use crate::audio::cache::get_audio_cache;
+use crate::commands::Command;
+use crate::constants::EMBED_COLOUR;
+use crate::metadata_cache::{get_cache, CACHE_CAPACITY};
+use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateEmbed, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+use serenity::model::Permissions;
+use serenity::Error;
+
+pub struct Cache;
+
+#[async_trait]
+impl Command for Cache {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let stats = get_audio_cache(ctx).await.read().await.stats();
+    let metadata_entries = get_cache(ctx).await.read().await.len();
+
+    let lookups = stats.hits + stats.misses;
+    let hit_rate = match lookups {
+      0 => "n/a".to_string(),
+      _ => format!("{:.1}%", stats.hits as f64 * 100.0 / lookups as f64),
+    };
+
+    let mut embed = CreateEmbed::new()
+      .title("Cache")
+      .colour(EMBED_COLOUR)
+      .field(
+        "Metadata",
+        format!("{}/{} tracks", metadata_entries, CACHE_CAPACITY),
+        false,
+      );
+
+    embed = match stats.max_size {
+      0 => embed.field("Audio", "Disabled", false),
+      max_size => embed.fields(vec![
+        (
+          "Audio",
+          format!(
+            "{} tracks, {} of {}",
+            stats.files,
+            format_size(stats.size),
+            format_size(max_size)
+          ),
+          false,
+        ),
+        ("Hits", stats.hits.to_string(), true),
+        ("Misses", stats.misses.to_string(), true),
+        ("Hit rate", hit_rate, true),
+        (
+          "Caching",
+          format!(
+            "After {} plays, {} downloading",
+            stats.min_plays, stats.downloading
+          ),
+          false,
+        ),
+      ]),
+    };
+
+    match command
+      .edit_response(&ctx.http, EditInteractionResponse::new().embed(embed))
+      .await
+    {
+      Ok(_m) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn name() -> &'static str {
+    "cache"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("Show track cache usage and hit rate")
+      .default_member_permissions(Permissions::MANAGE_GUILD)
+      .dm_permission(false)
+  }
+}
+
+fn format_size(bytes: u64) -> String {
+  let mb = bytes as f64 / (1024.0 * 1024.0);
+  match mb >= 1024.0 {
+    true => format!("{:.2} GB", mb / 1024.0),
+    false => format!("{:.1} MB", mb),
+  }
+}
@@ -1,45 +1,48 @@
-use crate::commands::Command;
+use crate::assets::{gif_for, Gif};
+use crate::commands::{text_response, Command};
+use crate::config::ConfigStorage;
 use crate::constants::EMBED_COLOUR;
 use chrono::prelude::*;
 use serenity::async_trait;
-use serenity::builder::{CreateCommand, CreateEmbed, EditInteractionResponse};
+use serenity::builder::{CreateAttachment, CreateCommand, CreateEmbed, EditInteractionResponse};
 use serenity::client::Context;
 use serenity::model::application::CommandInteraction;
 use serenity::Error;
+use tracing::error;
 
 pub struct Capybara;
 
-const FILE_URL: &str = "https://karei.dev/files/capybara-gifs/";
-const FILE_PREFIX: &str = "cp_";
-
 #[async_trait]
 impl Command for Capybara {
   async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
-    let filename = match Local::now().weekday() {
-      Weekday::Mon => "monday",
-      Weekday::Tue => "tuesday",
-      Weekday::Wed => "wednesday",
-      Weekday::Thu => "thursday",
-      Weekday::Fri => "friday",
-      Weekday::Sat => "saturday",
-      Weekday::Sun => "sunday",
+    let config = {
+      let data = ctx.data.read().await;
+      data
+        .get::<ConfigStorage>()
+        .cloned()
+        .expect("No config in global storage")
+    };
+    let response = match gif_for(&config, Local::now().weekday()).await {
+      Some(Gif::Url(url)) => {
+        EditInteractionResponse::new().embed(CreateEmbed::new().image(url).colour(EMBED_COLOUR))
+      }
+      Some(Gif::File(path)) => match CreateAttachment::path(&path).await {
+        Ok(attachment) => EditInteractionResponse::new()
+          .embed(
+            CreateEmbed::new()
+              .image(format!("attachment://{}", attachment.filename))
+              .colour(EMBED_COLOUR),
+          )
+          .new_attachment(attachment),
+        Err(e) => {
+          error!("Error reading {}: {}", path.display(), e);
+          return text_response(ctx, command, "Couldn't find today's capybara").await;
+        }
+      },
+      None => return text_response(ctx, command, "No capybara today").await,
     };
-    match command
-      .edit_response(
-        &ctx.http,
-        EditInteractionResponse::new().embed(
-          CreateEmbed::new()
-            .image(format!(
-              "{url}{prefix}{filename}.gif",
-              url = FILE_URL,
-              prefix = FILE_PREFIX,
-              filename = filename
-            ))
-            .colour(EMBED_COLOUR),
-        ),
-      )
-      .await
-    {
+
+    match command.edit_response(&ctx.http, response).await {
       Ok(_) => Ok(()),
       Err(e) => Err(e),
     }
@@ -0,0 +1,205 @@
+use crate::audio::filters::{played_time, track_position};
+use crate::commands::{
+  playback::{format_duration, SongMetadata, VOIPData},
+  text_response,
+  utils::{get_integer_option, get_subcommand, remove_md_characters},
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateCommandOption, CreateEmbed, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+use serenity::Error;
+use tracing::error;
+
+pub struct Chapter;
+
+const NUMBER_OPTION_NAME: &str = "number";
+/// Chapters shown by `list`, so the embed stays under Discord's length limits
+const LIST_MAX_CHAPTERS: usize = 25;
+
+#[async_trait]
+impl Command for Chapter {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let (subcommand, options) = match get_subcommand(command) {
+      Some(s) => s,
+      None => {
+        error!("No subcommand provided");
+        return text_response(ctx, command, "No subcommand in request").await;
+      }
+    };
+
+    let voip_data = match VOIPData::from(ctx, command).await {
+      Ok(v) => v,
+      Err(s) => return text_response(ctx, command, s).await,
+    };
+    let guild_id = voip_data.guild_id;
+
+    let manager = match songbird::get(ctx).await {
+      Some(arc) => arc.clone(),
+      None => {
+        error!("Error with songbird client");
+        return text_response(ctx, command, "Error getting voice client").await;
+      }
+    };
+
+    let handler_lock = match manager.get(guild_id) {
+      Some(h) => {
+        if voip_data.compare_to_call(&h).await {
+          h
+        } else {
+          return text_response(ctx, command, "You're not in the voice channel").await;
+        }
+      }
+      None => return text_response(ctx, command, "Not in a voice channel").await,
+    };
+
+    let current = match handler_lock.lock().await.queue().current() {
+      Some(t) => t,
+      None => return text_response(ctx, command, "Nothing playing").await,
+    };
+
+    let metadata = SongMetadata::from_handle(&current).await;
+    if metadata.chapters.is_empty() {
+      return text_response(ctx, command, "The track has no chapters").await;
+    }
+
+    let position = track_position(ctx, guild_id, &current).await;
+    let playing = metadata.chapter_at(position).unwrap_or(0);
+
+    let target = match subcommand {
+      "list" => return list(ctx, command, &metadata, playing).await,
+      "next" => playing + 1,
+      "prev" => playing.saturating_sub(1),
+      "goto" => match get_integer_option(&options, NUMBER_OPTION_NAME) {
+        Some(n) => (n.max(1) - 1) as usize,
+        None => return text_response(ctx, command, "No chapter number in request").await,
+      },
+      _ => return text_response(ctx, command, "Invalid subcommand").await,
+    };
+
+    let chapter = match metadata.chapters.get(target) {
+      Some(c) => c,
+      None => {
+        return text_response(
+          ctx,
+          command,
+          format!(
+            "No chapter {} (max: {})",
+            target + 1,
+            metadata.chapters.len()
+          ),
+        )
+        .await
+      }
+    };
+
+    match current
+      .seek(played_time(ctx, guild_id, chapter.start).await)
+      .result_async()
+      .await
+    {
+      Ok(_) => {
+        text_response(
+          ctx,
+          command,
+          remove_md_characters(metadata.format_chapter(target)),
+        )
+        .await
+      }
+      Err(e) => {
+        error!("Error while seeking: {}", e);
+        text_response(ctx, command, "Unknown error seeking").await
+      }
+    }
+  }
+
+  fn name() -> &'static str {
+    "chapter"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("Jump between chapters of the current track")
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "list",
+        "List the track's chapters",
+      ))
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "next",
+        "Skip to the next chapter",
+      ))
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "prev",
+        "Go back to the previous chapter",
+      ))
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "goto",
+          "Jump to a chapter by its number",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            NUMBER_OPTION_NAME,
+            "Chapter number",
+          )
+          .min_int_value(1)
+          .required(true),
+        ),
+      )
+  }
+}
+
+async fn list(
+  ctx: &Context,
+  command: &CommandInteraction,
+  metadata: &SongMetadata,
+  playing: usize,
+) -> Result<(), Error> {
+  let start = playing.saturating_sub(LIST_MAX_CHAPTERS / 2);
+  let description = metadata
+    .chapters
+    .iter()
+    .enumerate()
+    .skip(start)
+    .take(LIST_MAX_CHAPTERS)
+    .map(|(i, c)| {
+      let line = format!(
+        "{}. {} ({})",
+        i + 1,
+        remove_md_characters(&c.title),
+        format_duration(c.start)
+      );
+      match i == playing {
+        true => format!("**{}**", line),
+        false => line,
+      }
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new().embed(
+        CreateEmbed::new()
+          .title(format!(
+            "Chapters - {}",
+            remove_md_characters(&metadata.title)
+          ))
+          .description(description)
+          .colour(EMBED_COLOUR),
+      ),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
@@ -1,61 +1,173 @@
-use crate::commands::utils::remove_md_characters;
-use crate::commands::{text_response, Command};
+use crate::commands::utils::{
+  get_integer_option, get_string_option, get_subcommand, remove_md_characters,
+};
+use crate::commands::{ephemeral_response, text_response, Command};
 use crate::constants::EMBED_COLOUR;
-use evalexpr::eval;
+use crate::eval::{
+  self, evaluate, format_number, get_contexts, DEFAULT_PRECISION, MAX_EXPRESSION_LENGTH,
+  MAX_PRECISION, MAX_VARIABLES, SERIES_COLOURS,
+};
 use serenity::async_trait;
-use serenity::builder::{CreateCommand, CreateCommandOption, CreateEmbed, EditInteractionResponse};
+use serenity::builder::{
+  CreateActionRow, CreateAttachment, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed,
+  CreateEmbedFooter, CreateInteractionResponse, CreateInteractionResponseFollowup,
+  CreateInteractionResponseMessage, EditInteractionResponse,
+};
 use serenity::client::Context;
-use serenity::model::application::{CommandInteraction, CommandOptionType, ResolvedValue};
+use serenity::model::application::{
+  ButtonStyle, CommandInteraction, CommandOptionType, ComponentInteraction, ResolvedOption,
+};
+use serenity::model::id::UserId;
 use serenity::Error;
 use tracing::error;
 
 pub struct Eval;
 
 const EXPRESSION_OPTION_NAME: &str = "expression";
+const PRECISION_OPTION_NAME: &str = "precision";
+/// Followed by the user the history belongs to and the entry's id
+const RERUN_BUTTON_PREFIX: &str = "eval_rerun_";
+/// Buttons that fit in an action row
+const BUTTONS_PER_ROW: usize = 5;
+const PLOT_FILE_NAME: &str = "plot.png";
+/// Characters of each value shown in /eval vars, so every variable fits in the list
+const VALUE_PREVIEW_LENGTH: usize = 60;
 
 #[async_trait]
 impl Command for Eval {
   async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
-    let expr = match command
-      .data
-      .options()
-      .iter()
-      .find(|e| e.name == EXPRESSION_OPTION_NAME)
-    {
-      Some(o) => {
-        if let ResolvedValue::String(expr) = o.value {
-          expr.to_string()
-        } else {
-          error!("Invalid option type");
-          return text_response(ctx, command, "Malformed expression provided").await;
-        }
+    match get_subcommand(command) {
+      Some(("vars", _)) => vars(ctx, command).await,
+      Some(("reset", _)) => reset(ctx, command).await,
+      Some(("history", _)) => history(ctx, command).await,
+      Some(("plot", options)) => plot(ctx, command, &options).await,
+      Some((_, options)) => run(ctx, command, &options).await,
+      None => {
+        error!("No subcommand provided");
+        text_response(ctx, command, "No subcommand in request").await
       }
+    }
+  }
+
+  fn name() -> &'static str {
+    "eval"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("Evaluate expressions, with variables kept between them")
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "run",
+          "Evaluate an expression or convert units like 5 km to mi, assignments like x = 5 are kept",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            EXPRESSION_OPTION_NAME,
+            "Expression to evaluate (use \"help\" to get a cheatsheet of available functions)",
+          )
+          .max_length(MAX_EXPRESSION_LENGTH as u16)
+          .required(true),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            PRECISION_OPTION_NAME,
+            format!("Decimals to round results to (default: {})", DEFAULT_PRECISION),
+          )
+          .min_int_value(0)
+          .max_int_value(MAX_PRECISION as u64),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "plot",
+          "Draw a chart of functions of x, like sin(x), x^2 from -5 to 5 (default: -10 to 10)",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            EXPRESSION_OPTION_NAME,
+            "Functions of x separated by commas, optionally followed by from a to b",
+          )
+          .max_length(MAX_EXPRESSION_LENGTH as u16)
+          .required(true),
+        ),
+      )
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "vars",
+        "List your variables",
+      ))
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "reset",
+        "Forget all your variables",
+      ))
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "history",
+        "List your recent expressions to run again",
+      ))
+  }
+}
+
+impl Eval {
+  pub fn handles(custom_id: &str) -> bool {
+    custom_id.starts_with(RERUN_BUTTON_PREFIX)
+  }
+
+  /// Runs an expression from /eval history again, for the user whose history it is
+  pub async fn component(ctx: &Context, component: &ComponentInteraction) -> Result<(), Error> {
+    let ids = component
+      .data
+      .custom_id
+      .trim_start_matches(RERUN_BUTTON_PREFIX)
+      .split_once('_')
+      .and_then(|(user, id)| Some((UserId::new(user.parse().ok()?), id.parse::<u64>().ok()?)));
+    let (owner, id) = match ids {
+      Some(i) => i,
       None => {
-        error!("No options provided");
-        return text_response(ctx, command, "No expression in request").await;
+        error!("Invalid re-run button {}", component.data.custom_id);
+        return ephemeral_response(ctx, component, "Couldn't find that expression").await;
       }
     };
+    if owner != component.user.id {
+      return ephemeral_response(ctx, component, "Only your own history can be run again").await;
+    }
 
-    let desc = match expr.trim() == "help" {
-      false => match eval(&expr) {
-        Err(e) => {
-          error!("Evaluation error: {}", e);
-          format!("{}", e)
-        }
-        Ok(v) => format!("{}", v),
-      },
-      true => "https://github.com/ISibboI/evalexpr/blob/main/README.md".to_string(),
+    let entry = get_contexts(ctx)
+      .await
+      .lock()
+      .await
+      .history_entry(owner, id);
+    let entry = match entry {
+      Some(e) => e,
+      None => {
+        return ephemeral_response(
+          ctx,
+          component,
+          "That expression isn't in your history anymore",
+        )
+        .await
+      }
     };
 
-    match command
-      .edit_response(
+    // Evaluation can take up to its timeout, the result is sent as a follow-up
+    component
+      .create_response(
         &ctx.http,
-        EditInteractionResponse::new().embed(
-          CreateEmbed::new()
-            .title(remove_md_characters(expr))
-            .colour(EMBED_COLOUR)
-            .description(desc),
-        ),
+        CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new()),
+      )
+      .await?;
+    let embed = answer(ctx, owner, &entry.expression, entry.precision).await;
+    match component
+      .create_followup(
+        &ctx.http,
+        CreateInteractionResponseFollowup::new().embed(embed),
       )
       .await
     {
@@ -63,21 +175,229 @@ impl Command for Eval {
       Err(e) => Err(e),
     }
   }
+}
 
-  fn name() -> &'static str {
-    "eval"
+async fn run(
+  ctx: &Context,
+  command: &CommandInteraction,
+  options: &[ResolvedOption<'_>],
+) -> Result<(), Error> {
+  let expr = match get_string_option(options, EXPRESSION_OPTION_NAME) {
+    Some(e) => e.to_string(),
+    None => {
+      error!("No options provided");
+      return text_response(ctx, command, "No expression in request").await;
+    }
+  };
+  let precision = get_integer_option(options, PRECISION_OPTION_NAME)
+    .map(|p| p.clamp(0, MAX_PRECISION as i64) as usize)
+    .unwrap_or(DEFAULT_PRECISION);
+
+  let embed = match expr.trim() == "help" {
+    false => answer(ctx, command.user.id, &expr, precision).await,
+    true => CreateEmbed::new()
+      .title(remove_md_characters(expr))
+      .colour(EMBED_COLOUR)
+      .description("https://github.com/ISibboI/evalexpr/blob/main/README.md"),
+  };
+
+  match command
+    .edit_response(&ctx.http, EditInteractionResponse::new().embed(embed))
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
   }
+}
 
-  fn info() -> CreateCommand {
-    CreateCommand::new(Self::name())
-      .description("Evaluate an expression")
-      .add_option(
-        CreateCommandOption::new(
-          CommandOptionType::String,
-          EXPRESSION_OPTION_NAME,
-          "Expression to evaluate (use \"help\" to get a cheatsheet of available functions)",
-        )
-        .required(true),
+/// Evaluates an expression for the user, keeping it in their history if it worked
+async fn answer(ctx: &Context, user_id: UserId, expr: &str, precision: usize) -> CreateEmbed {
+  let contexts = get_contexts(ctx).await;
+  let desc = match evaluate(&contexts, user_id, expr).await {
+    Err(e) => {
+      error!("Evaluation error: {}", e);
+      e
+    }
+    Ok(v) => {
+      contexts.lock().await.record(user_id, expr, precision);
+      format!("{:.*}", precision, v)
+    }
+  };
+
+  CreateEmbed::new()
+    .title(remove_md_characters(expr))
+    .colour(EMBED_COLOUR)
+    .description(desc)
+}
+
+async fn plot(
+  ctx: &Context,
+  command: &CommandInteraction,
+  options: &[ResolvedOption<'_>],
+) -> Result<(), Error> {
+  let input = match get_string_option(options, EXPRESSION_OPTION_NAME) {
+    Some(e) => e.to_string(),
+    None => {
+      error!("No options provided");
+      return text_response(ctx, command, "No expression in request").await;
+    }
+  };
+
+  let contexts = get_contexts(ctx).await;
+  let plot = match eval::plot(&contexts, command.user.id, &input).await {
+    Ok(p) => p,
+    Err(e) => {
+      error!("Plot error: {}", e);
+      return text_response(ctx, command, e).await;
+    }
+  };
+
+  let legend = plot
+    .series
+    .iter()
+    .zip(SERIES_COLOURS)
+    .map(|(function, colour)| format!("{} {}", colour, remove_md_characters(function)))
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new()
+        .new_attachment(CreateAttachment::bytes(plot.png, PLOT_FILE_NAME))
+        .embed(
+          CreateEmbed::new()
+            .title("Plot")
+            .colour(EMBED_COLOUR)
+            .description(legend)
+            .image(format!("attachment://{}", PLOT_FILE_NAME))
+            .footer(CreateEmbedFooter::new(format!(
+              "x from {} to {}",
+              format_number(plot.from),
+              format_number(plot.to)
+            ))),
+        ),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+async fn vars(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+  let variables = get_contexts(ctx)
+    .await
+    .lock()
+    .await
+    .variables(command.user.id);
+  if variables.is_empty() {
+    return text_response(ctx, command, "No variables, assign some like x = 5").await;
+  }
+
+  let list = variables
+    .iter()
+    .map(|(name, value)| {
+      let value = value.to_string();
+      let preview = match value.chars().count() > VALUE_PREVIEW_LENGTH {
+        true => format!(
+          "{}...",
+          value.chars().take(VALUE_PREVIEW_LENGTH).collect::<String>()
+        ),
+        false => value,
+      };
+      format!("`{}` = {}", name, remove_md_characters(preview))
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new().embed(
+        CreateEmbed::new()
+          .title("Variables")
+          .colour(EMBED_COLOUR)
+          .description(list)
+          .footer(CreateEmbedFooter::new(format!(
+            "{} / {} variables",
+            variables.len(),
+            MAX_VARIABLES
+          ))),
+      ),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+async fn history(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+  let history = get_contexts(ctx)
+    .await
+    .lock()
+    .await
+    .history(command.user.id);
+  if history.is_empty() {
+    return text_response(
+      ctx,
+      command,
+      "No history, evaluate something with /eval run",
+    )
+    .await;
+  }
+
+  let list = history
+    .iter()
+    .enumerate()
+    .map(|(i, entry)| {
+      format!(
+        "{}. {}",
+        i + 1,
+        remove_md_characters(entry.expression.as_str())
       )
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+  let buttons = history
+    .iter()
+    .enumerate()
+    .map(|(i, entry)| {
+      CreateButton::new(format!(
+        "{}{}_{}",
+        RERUN_BUTTON_PREFIX, command.user.id, entry.id
+      ))
+      .label((i + 1).to_string())
+      .style(ButtonStyle::Secondary)
+    })
+    .collect::<Vec<_>>();
+  let components = buttons
+    .chunks(BUTTONS_PER_ROW)
+    .map(|row| CreateActionRow::Buttons(row.to_vec()))
+    .collect::<Vec<_>>();
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new()
+        .embed(
+          CreateEmbed::new()
+            .title("History")
+            .colour(EMBED_COLOUR)
+            .description(list)
+            .footer(CreateEmbedFooter::new("Press a number to run it again")),
+        )
+        .components(components),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
   }
 }
+
+async fn reset(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+  let removed = get_contexts(ctx).await.lock().await.reset(command.user.id);
+  text_response(ctx, command, format!("Forgot {} variables", removed)).await
+}
@@ -0,0 +1,221 @@
+use crate::audio::filters::{
+  guild_filters, Filters, Preset, MAX_GAIN, MAX_PITCH, MAX_SPEED, MIN_PITCH, MIN_SPEED,
+};
+use crate::commands::{
+  text_response,
+  utils::{get_bool_option, get_number_option, get_string_option, get_subcommand},
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateCommandOption, CreateEmbed, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType, ResolvedOption};
+use serenity::Error;
+use tracing::error;
+
+pub struct Filter;
+
+const PRESET_OPTION_NAME: &str = "preset";
+const SPEED_OPTION_NAME: &str = "speed";
+const PITCH_OPTION_NAME: &str = "pitch";
+const BASS_OPTION_NAME: &str = "bass";
+const TREBLE_OPTION_NAME: &str = "treble";
+const ROTATION_OPTION_NAME: &str = "rotation";
+const KARAOKE_OPTION_NAME: &str = "karaoke";
+
+#[async_trait]
+impl Command for Filter {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let guild_id = match command.guild_id {
+      Some(g) => g,
+      None => return text_response(ctx, command, "Error getting guild information").await,
+    };
+
+    let (subcommand, options) = match get_subcommand(command) {
+      Some(s) => s,
+      None => {
+        error!("No subcommand provided");
+        return text_response(ctx, command, "No subcommand in request").await;
+      }
+    };
+
+    let shared = guild_filters(ctx, guild_id).await;
+    let updated = {
+      let mut filters = match shared.write() {
+        Ok(f) => f,
+        Err(e) => e.into_inner(),
+      };
+
+      match subcommand {
+        "show" => Ok(()),
+        "preset" => match get_string_option(&options, PRESET_OPTION_NAME).and_then(Preset::from_id)
+        {
+          Some(p) => {
+            *filters = p.filters();
+            Ok(())
+          }
+          None => Err("Invalid preset in request"),
+        },
+        "custom" => {
+          set_custom(&mut filters, &options);
+          Ok(())
+        }
+        "reset" => {
+          *filters = Filters::default();
+          Ok(())
+        }
+        _ => Err("Invalid subcommand"),
+      }
+      .map(|_| filters.clone())
+    };
+
+    let filters = match updated {
+      Ok(f) => f,
+      Err(e) => return text_response(ctx, command, e).await,
+    };
+
+    let title = match subcommand {
+      "show" => "Filters",
+      "reset" => "Filters cleared",
+      _ => "Filters updated",
+    };
+
+    match command
+      .edit_response(
+        &ctx.http,
+        EditInteractionResponse::new().embed(
+          CreateEmbed::new()
+            .title(title)
+            .colour(EMBED_COLOUR)
+            .description(filters.to_string()),
+        ),
+      )
+      .await
+    {
+      Ok(_m) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn name() -> &'static str {
+    "filter"
+  }
+
+  fn info() -> CreateCommand {
+    let mut preset = CreateCommandOption::new(
+      CommandOptionType::String,
+      PRESET_OPTION_NAME,
+      "Preset to apply",
+    )
+    .required(true);
+    for p in Preset::ALL {
+      preset = preset.add_string_choice(p.name(), p.id());
+    }
+
+    CreateCommand::new(Self::name())
+      .description("Apply audio filters to the current and upcoming tracks")
+      .dm_permission(false)
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "show",
+        "Show the active filters",
+      ))
+      .add_option(
+        CreateCommandOption::new(CommandOptionType::SubCommand, "preset", "Apply a preset")
+          .add_sub_option(preset),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "custom",
+          "Adjust individual filters, keeping the rest",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            SPEED_OPTION_NAME,
+            "Speed multiplier, e.g. 1.25",
+          )
+          .min_number_value(MIN_SPEED)
+          .max_number_value(MAX_SPEED)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            PITCH_OPTION_NAME,
+            "Pitch multiplier, e.g. 1.1",
+          )
+          .min_number_value(MIN_PITCH)
+          .max_number_value(MAX_PITCH)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            BASS_OPTION_NAME,
+            "Bass gain in dB",
+          )
+          .min_number_value(-MAX_GAIN)
+          .max_number_value(MAX_GAIN)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            TREBLE_OPTION_NAME,
+            "Treble gain in dB",
+          )
+          .min_number_value(-MAX_GAIN)
+          .max_number_value(MAX_GAIN)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            ROTATION_OPTION_NAME,
+            "8D rotations per second, 0 to disable",
+          )
+          .min_number_value(0.0)
+          .max_number_value(2.0)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            KARAOKE_OPTION_NAME,
+            "Remove vocals",
+          )
+          .required(false),
+        ),
+      )
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "reset",
+        "Remove all filters",
+      ))
+  }
+}
+
+fn set_custom(filters: &mut Filters, options: &[ResolvedOption<'_>]) {
+  filters.preset = None;
+  if let Some(speed) = get_number_option(options, SPEED_OPTION_NAME) {
+    filters.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+  }
+  if let Some(pitch) = get_number_option(options, PITCH_OPTION_NAME) {
+    filters.pitch = pitch.clamp(MIN_PITCH, MAX_PITCH);
+  }
+  if let Some(bass) = get_number_option(options, BASS_OPTION_NAME) {
+    filters.bass = bass.clamp(-MAX_GAIN, MAX_GAIN);
+  }
+  if let Some(treble) = get_number_option(options, TREBLE_OPTION_NAME) {
+    filters.treble = treble.clamp(-MAX_GAIN, MAX_GAIN);
+  }
+  if let Some(rotation) = get_number_option(options, ROTATION_OPTION_NAME) {
+    filters.rotation = rotation.clamp(0.0, 2.0);
+  }
+  if let Some(karaoke) = get_bool_option(options, KARAOKE_OPTION_NAME) {
+    filters.karaoke = karaoke;
+  }
+}
@@ -0,0 +1,88 @@
+use crate::commands::{
+  playback::format_duration, text_response, utils::remove_md_characters, Command,
+};
+use crate::constants::EMBED_COLOUR;
+use crate::history::HistoryStorage;
+use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateEmbed, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+use serenity::Error;
+
+pub struct History;
+
+const HISTORY_MAX_ENTRIES: usize = 10;
+
+#[async_trait]
+impl Command for History {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let guild_id = match command.guild_id {
+      Some(g) => g,
+      None => return text_response(ctx, command, "Error getting guild information").await,
+    };
+
+    let history_lock = {
+      let data = ctx.data.read().await;
+      data
+        .get::<HistoryStorage>()
+        .cloned()
+        .expect("HistoryStorage did not exist")
+    };
+
+    let description = {
+      let history = history_lock.read().await;
+      history
+        .get(guild_id)
+        .take(HISTORY_MAX_ENTRIES)
+        .map(|entry| {
+          let title = remove_md_characters(&entry.metadata.title);
+          let title = match &entry.metadata.url {
+            Some(url) => format!("[{}]({})", title, url),
+            None => title,
+          };
+          let requester = entry
+            .requester
+            .as_ref()
+            .map(|r| format!(" - {}", remove_md_characters(&r.name)))
+            .unwrap_or_default();
+          format!(
+            "<t:{}:R> {} ({}){}",
+            entry.played_at,
+            title,
+            format_duration(entry.metadata.duration),
+            requester
+          )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+    };
+
+    if description.is_empty() {
+      return text_response(ctx, command, "Nothing has been played yet").await;
+    }
+
+    match command
+      .edit_response(
+        &ctx.http,
+        EditInteractionResponse::new().embed(
+          CreateEmbed::new()
+            .title("Recently played")
+            .colour(EMBED_COLOUR)
+            .description(description),
+        ),
+      )
+      .await
+    {
+      Ok(_m) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn name() -> &'static str {
+    "history"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name()).description("View recently played songs")
+  }
+}
@@ -0,0 +1,220 @@
+use crate::audio::filters::track_position;
+use crate::commands::{
+  playback::{SongMetadata, VOIPData},
+  text_response,
+  utils::remove_md_characters,
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use crate::lyrics::{find_lyrics, Lyrics as TrackLyrics, LyricsQuery, LyricsSessions};
+use serenity::async_trait;
+use serenity::builder::{
+  CreateCommand, CreateEmbed, CreateEmbedFooter, EditInteractionResponse, EditMessage,
+};
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+use serenity::model::id::GuildId;
+use serenity::Error;
+use songbird::tracks::TrackHandle;
+use std::time::Duration;
+use tracing::error;
+
+pub struct Lyrics;
+
+/// How often synced lyrics check whether the line changed
+const UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+const LINES_BEFORE: usize = 2;
+const LINES_AFTER: usize = 5;
+const MAX_DESCRIPTION_LENGTH: usize = 4096;
+
+#[async_trait]
+impl Command for Lyrics {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let voip_data = match VOIPData::from(ctx, command).await {
+      Ok(v) => v,
+      Err(s) => return text_response(ctx, command, s).await,
+    };
+    let guild_id = voip_data.guild_id;
+
+    let manager = match songbird::get(ctx).await {
+      Some(arc) => arc.clone(),
+      None => {
+        error!("Error with songbird client");
+        return text_response(ctx, command, "Error getting voice client").await;
+      }
+    };
+
+    let current = match manager.get(guild_id) {
+      Some(h) => match h.lock().await.queue().current() {
+        Some(t) => t,
+        None => return text_response(ctx, command, "Nothing playing").await,
+      },
+      None => return text_response(ctx, command, "Not in a voice channel").await,
+    };
+
+    let metadata = SongMetadata::from_handle(&current).await;
+    let query = LyricsQuery::from_metadata(&metadata);
+    let lyrics = match find_lyrics(ctx, &query).await {
+      Some(l) => l,
+      None => return text_response(ctx, command, format!("No lyrics found for {}", query)).await,
+    };
+
+    let title = format!("Lyrics - {}", remove_md_characters(&query));
+    if !lyrics.is_synced() {
+      return match command
+        .edit_response(
+          &ctx.http,
+          EditInteractionResponse::new().embed(plain_embed(&title, &lyrics)),
+        )
+        .await
+      {
+        Ok(_m) => Ok(()),
+        Err(e) => Err(e),
+      };
+    }
+
+    let line = current_line(ctx, guild_id, &current, &lyrics).await;
+    let message = match command
+      .edit_response(
+        &ctx.http,
+        EditInteractionResponse::new().embed(synced_embed(&title, &lyrics, line)),
+      )
+      .await
+    {
+      Ok(m) => m,
+      Err(e) => return Err(e),
+    };
+
+    let sessions = {
+      let data = ctx.data.read().await;
+      data
+        .get::<LyricsSessions>()
+        .cloned()
+        .expect("LyricsSessions did not exist")
+    };
+    let task = tokio::spawn(follow(
+      ctx.clone(),
+      guild_id,
+      current,
+      lyrics,
+      title,
+      line,
+      message,
+    ));
+    if let Some(previous) = sessions.lock().await.insert(guild_id, task.abort_handle()) {
+      previous.abort();
+    }
+
+    Ok(())
+  }
+
+  fn name() -> &'static str {
+    "lyrics"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name()).description("Show the lyrics of the current track")
+  }
+}
+
+/// Keeps a synced lyrics message on the line being sung until the track stops playing
+async fn follow(
+  ctx: Context,
+  guild_id: GuildId,
+  handle: TrackHandle,
+  lyrics: TrackLyrics,
+  title: String,
+  mut line: Option<usize>,
+  mut message: serenity::model::channel::Message,
+) {
+  loop {
+    tokio::time::sleep(UPDATE_INTERVAL).await;
+
+    let playing = match songbird::get(&ctx).await.and_then(|m| m.get(guild_id)) {
+      Some(h) => h.lock().await.queue().current().map(|t| t.uuid()) == Some(handle.uuid()),
+      None => false,
+    };
+    if !playing {
+      break;
+    }
+
+    let current = current_line(&ctx, guild_id, &handle, &lyrics).await;
+    if current == line {
+      continue;
+    }
+    line = current;
+
+    let embed = synced_embed(&title, &lyrics, line);
+    if let Err(e) = message
+      .edit(&ctx.http, EditMessage::new().embed(embed))
+      .await
+    {
+      error!("Error updating lyrics: {}", e);
+      break;
+    }
+  }
+}
+
+async fn current_line(
+  ctx: &Context,
+  guild_id: GuildId,
+  handle: &TrackHandle,
+  lyrics: &TrackLyrics,
+) -> Option<usize> {
+  match handle.get_info().await {
+    Ok(_) => lyrics.line_at(track_position(ctx, guild_id, handle).await),
+    Err(_) => None,
+  }
+}
+
+fn synced_embed(title: &str, lyrics: &TrackLyrics, line: Option<usize>) -> CreateEmbed {
+  let start = line.unwrap_or(0).saturating_sub(LINES_BEFORE);
+  let end = line.map_or(LINES_AFTER, |l| l + LINES_AFTER + 1);
+
+  let description = lyrics.lines[start..end.min(lyrics.lines.len())]
+    .iter()
+    .enumerate()
+    .map(|(i, l)| {
+      let text = match l.text.is_empty() {
+        true => "♪".to_string(),
+        false => remove_md_characters(&l.text),
+      };
+      match Some(start + i) == line {
+        true => format!("**{}**", text),
+        false => text,
+      }
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  CreateEmbed::new()
+    .title(title)
+    .description(description)
+    .colour(EMBED_COLOUR)
+    .footer(CreateEmbedFooter::new(format!(
+      "Synced lyrics from {}",
+      lyrics.source
+    )))
+}
+
+fn plain_embed(title: &str, lyrics: &TrackLyrics) -> CreateEmbed {
+  let mut description = String::new();
+  for line in &lyrics.lines {
+    let line = remove_md_characters(&line.text);
+    if description.chars().count() + line.chars().count() + 1 > MAX_DESCRIPTION_LENGTH - 1 {
+      description.push('…');
+      break;
+    }
+    description.push_str(&line);
+    description.push('\n');
+  }
+
+  CreateEmbed::new()
+    .title(title)
+    .description(description)
+    .colour(EMBED_COLOUR)
+    .footer(CreateEmbedFooter::new(format!(
+      "Lyrics from {}",
+      lyrics.source
+    )))
+}
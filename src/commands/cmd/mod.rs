@@ -36,3 +36,36 @@ pub use pause::Pause;
 
 mod resume;
 pub use resume::Resume;
+
+mod playlist;
+pub use playlist::Playlist;
+
+mod history;
+pub use history::History;
+
+mod settings;
+pub use settings::Settings;
+
+mod filter;
+pub use filter::Filter;
+
+mod cache;
+pub use cache::Cache;
+
+mod autoplay;
+pub use autoplay::Autoplay;
+
+mod chapter;
+pub use chapter::Chapter;
+
+mod lyrics;
+pub use lyrics::Lyrics;
+
+mod nowplaying;
+pub use nowplaying::NowPlaying;
+
+mod replay;
+pub use replay::Replay;
+
+mod requeue;
+pub use requeue::Requeue;
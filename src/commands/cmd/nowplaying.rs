@@ -0,0 +1,301 @@
+use crate::audio::filters::{current_filters, track_position};
+use crate::commands::{
+  ephemeral_response,
+  events::{pause_playback, resume_playback},
+  permissions::is_dj,
+  playback::{
+    format_duration, format_duration_live, format_timestamp, get_queue_length_and_duration,
+    progress_bar, Requester, SongMetadata, VOIPData,
+  },
+  text_response, ticker,
+  utils::remove_md_characters,
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use crate::settings::guild_settings;
+use serenity::async_trait;
+use serenity::builder::{
+  CreateActionRow, CreateButton, CreateCommand, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter,
+  CreateInteractionResponse, CreateInteractionResponseMessage, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{ButtonStyle, CommandInteraction, ComponentInteraction};
+use serenity::model::id::GuildId;
+use serenity::Error;
+use songbird::tracks::{LoopState, PlayMode, TrackHandle};
+use tracing::error;
+
+pub struct NowPlaying;
+
+const PAUSE_BUTTON_ID: &str = "nowplaying_pause";
+const RESUME_BUTTON_ID: &str = "nowplaying_resume";
+const SKIP_BUTTON_ID: &str = "nowplaying_skip";
+const LOOP_BUTTON_ID: &str = "nowplaying_loop";
+const STOP_BUTTON_ID: &str = "nowplaying_stop";
+
+#[async_trait]
+impl Command for NowPlaying {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let guild_id = match command.guild_id {
+      Some(g) => g,
+      None => return text_response(ctx, command, "Error getting guild information").await,
+    };
+
+    let queue = match songbird::get(ctx).await.and_then(|m| m.get(guild_id)) {
+      Some(h) => h.lock().await.queue().current_queue(),
+      None => return text_response(ctx, command, "Not in a voice channel").await,
+    };
+    if queue.is_empty() {
+      return text_response(ctx, command, "Nothing is playing").await;
+    }
+
+    let (embed, components) = now_playing(ctx, guild_id, queue).await;
+    match command
+      .edit_response(
+        &ctx.http,
+        EditInteractionResponse::new()
+          .embed(embed)
+          .components(components),
+      )
+      .await
+    {
+      Ok(_m) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn name() -> &'static str {
+    "nowplaying"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name()).description("Show the current track with playback controls")
+  }
+}
+
+impl NowPlaying {
+  pub fn handles(custom_id: &str) -> bool {
+    [
+      PAUSE_BUTTON_ID,
+      RESUME_BUTTON_ID,
+      SKIP_BUTTON_ID,
+      LOOP_BUTTON_ID,
+      STOP_BUTTON_ID,
+    ]
+    .contains(&custom_id)
+  }
+
+  /// Buttons follow the rules of the command doing the same
+  pub fn command_name(custom_id: &str) -> &'static str {
+    match custom_id {
+      PAUSE_BUTTON_ID => "pause",
+      RESUME_BUTTON_ID => "resume",
+      SKIP_BUTTON_ID => "skip",
+      STOP_BUTTON_ID => "stop",
+      _ => Self::name(),
+    }
+  }
+
+  /// Runs a playback control button and updates the message it's on
+  pub async fn component(ctx: &Context, component: &ComponentInteraction) -> Result<(), Error> {
+    let id = component.data.custom_id.as_str();
+
+    let voip_data = match VOIPData::from_user(ctx, component.guild_id, component.user.id) {
+      Ok(v) => v,
+      Err(s) => return ephemeral_response(ctx, component, s).await,
+    };
+    let guild_id = voip_data.guild_id;
+    let settings = guild_settings(ctx, guild_id).await;
+    let member = component.member.as_ref();
+
+    let handler_lock = match songbird::get(ctx).await.and_then(|m| m.get(guild_id)) {
+      Some(h) => {
+        if voip_data.compare_to_call(&h).await {
+          h
+        } else {
+          return ephemeral_response(ctx, component, "You're not in the voice channel").await;
+        }
+      }
+      None => return ephemeral_response(ctx, component, "Not in a voice channel").await,
+    };
+
+    let queue = {
+      let handler = handler_lock.lock().await;
+      let current = match handler.queue().current() {
+        Some(t) => t,
+        None => return ephemeral_response(ctx, component, "Nothing is playing").await,
+      };
+
+      let result = match id {
+        PAUSE_BUTTON_ID => pause_playback(handler.queue()).await,
+        RESUME_BUTTON_ID => resume_playback(handler.queue()).await,
+        SKIP_BUTTON_ID => {
+          let is_requester = Requester::from_handle(&current)
+            .await
+            .is_some_and(|r| r.id == component.user.id);
+          if settings.vote_skip && !is_requester && !is_dj(member, &settings) {
+            return ephemeral_response(ctx, component, "Use /skip to vote to skip").await;
+          }
+          handler.queue().skip()
+        }
+        LOOP_BUTTON_ID => match current.get_info().await.map(|s| s.loops) {
+          Ok(LoopState::Finite(0)) => current.enable_loop(),
+          _ => current.disable_loop(),
+        },
+        STOP_BUTTON_ID => {
+          handler.queue().stop();
+          Ok(())
+        }
+        _ => return Ok(()),
+      };
+      if let Err(e) = result {
+        error!("Error controlling playback: {}", e);
+        return ephemeral_response(ctx, component, "Couldn't do that right now").await;
+      }
+
+      let queue = handler.queue().current_queue();
+      // Skipping only stops the track, the queue drops it once its end event runs
+      match id {
+        SKIP_BUTTON_ID => queue.into_iter().skip(1).collect::<Vec<_>>(),
+        _ => queue,
+      }
+    };
+    if id == RESUME_BUTTON_ID {
+      ticker::resume(ctx, guild_id).await;
+    }
+
+    let message = match queue.is_empty() {
+      true => CreateInteractionResponseMessage::new()
+        .embed(
+          CreateEmbed::new()
+            .title(match id {
+              SKIP_BUTTON_ID => "Skipped, nothing else is queued",
+              _ => "Stopped playback and cleared the queue",
+            })
+            .colour(EMBED_COLOUR),
+        )
+        .components(vec![]),
+      false => {
+        let (embed, components) = now_playing(ctx, guild_id, queue).await;
+        CreateInteractionResponseMessage::new()
+          .embed(embed)
+          .components(components)
+      }
+    };
+    component
+      .create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(message))
+      .await
+  }
+}
+
+/// Embed about the first track of `queue` and the buttons controlling it, `queue` can't be empty
+async fn now_playing(
+  ctx: &Context,
+  guild_id: GuildId,
+  queue: Vec<TrackHandle>,
+) -> (CreateEmbed, Vec<CreateActionRow>) {
+  let current = &queue[0];
+  let metadata = SongMetadata::from_handle(current).await;
+  let requester = Requester::from_handle(current).await;
+  let state = current.get_info().await.ok();
+  let filters = current_filters(ctx, guild_id).await;
+  let (count, duration) = get_queue_length_and_duration(&queue).await;
+
+  let paused = state
+    .as_ref()
+    .is_some_and(|s| matches!(s.playing, PlayMode::Pause));
+  let looping = state
+    .as_ref()
+    .is_some_and(|s| !matches!(s.loops, LoopState::Finite(0)));
+  let position = track_position(ctx, guild_id, current).await;
+  let length = format_duration_live(metadata.duration, &metadata.title);
+
+  let mut description = match &metadata.url {
+    Some(url) => format!("[{}]({})", remove_md_characters(&metadata.title), url),
+    None => remove_md_characters(&metadata.title),
+  };
+  if let Some(chapter) = metadata.chapter_at(position) {
+    description.push_str(&format!(
+      "\n{}",
+      remove_md_characters(metadata.format_chapter(chapter))
+    ));
+  }
+
+  let progress = match bool::from(&length) || metadata.duration.is_zero() {
+    true => length.to_string(),
+    false => format!(
+      "`{}` {} `{}`",
+      format_timestamp(position),
+      progress_bar(position, metadata.duration),
+      format_timestamp(metadata.duration)
+    ),
+  };
+  let loop_mode = match state.as_ref().map(|s| s.loops) {
+    Some(LoopState::Infinite) => "Forever".to_string(),
+    Some(LoopState::Finite(0)) | None => "Off".to_string(),
+    Some(LoopState::Finite(n)) => format!("{} more times", n),
+  };
+
+  let mut embed = CreateEmbed::new()
+    .title(match paused {
+      true => "Paused",
+      false => "Now playing",
+    })
+    .colour(EMBED_COLOUR)
+    .description(description)
+    .thumbnail(metadata.thumbnail.clone())
+    .fields(vec![
+      ("Progress", progress, false),
+      ("Loop", loop_mode, true),
+    ])
+    .footer(CreateEmbedFooter::new(format!(
+      "{} songs in queue - {}",
+      count,
+      format_duration(duration)
+    )));
+  if filters.is_active() {
+    embed = embed.field("Filters", filters.to_string(), true);
+  }
+  if let Some(next) = queue.get(1) {
+    let next = SongMetadata::from_handle(next).await;
+    embed = embed.field(
+      "Up next",
+      match &next.url {
+        Some(url) => format!("[{}]({})", remove_md_characters(&next.title), url),
+        None => remove_md_characters(&next.title),
+      },
+      false,
+    );
+  }
+  if let Some(requester) = requester {
+    embed = embed.author(
+      CreateEmbedAuthor::new(remove_md_characters(requester.name)).icon_url(requester.avatar),
+    );
+  }
+
+  let play_button = match paused {
+    true => CreateButton::new(RESUME_BUTTON_ID)
+      .label("Resume")
+      .style(ButtonStyle::Success),
+    false => CreateButton::new(PAUSE_BUTTON_ID)
+      .label("Pause")
+      .style(ButtonStyle::Secondary),
+  };
+  let components = vec![CreateActionRow::Buttons(vec![
+    play_button,
+    CreateButton::new(SKIP_BUTTON_ID)
+      .label("Skip")
+      .style(ButtonStyle::Secondary),
+    CreateButton::new(LOOP_BUTTON_ID)
+      .label(match looping {
+        true => "Stop looping",
+        false => "Loop",
+      })
+      .style(ButtonStyle::Secondary),
+    CreateButton::new(STOP_BUTTON_ID)
+      .label("Stop")
+      .style(ButtonStyle::Danger),
+  ])];
+
+  (embed, components)
+}
@@ -1,4 +1,6 @@
+use crate::audio::filters::track_position;
 use crate::commands::{
+  events::pause_playback,
   playback::{format_duration_live, SongMetadata, VOIPData},
   text_response, Command,
 };
@@ -54,7 +56,7 @@ impl Command for Pause {
       None => return text_response(ctx, command, "Nothing is playing").await,
     };
 
-    match current.pause() {
+    match pause_playback(handler.queue()).await {
       Err(e) => {
         error!("Error pausing track: {}", e);
         text_response(ctx, command, "Could not pause").await
@@ -63,11 +65,7 @@ impl Command for Pause {
         let metadata = SongMetadata::from_handle(&current).await;
         let title = metadata.title.clone();
 
-        let current_time = current
-          .get_info()
-          .await
-          .map(|info| info.position)
-          .unwrap_or_default();
+        let current_time = track_position(ctx, guild_id, &current).await;
         let current_time = format_duration_live(current_time, &title);
         let duration = format_duration_live(metadata.duration, &title);
 
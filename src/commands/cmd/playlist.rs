@@ -0,0 +1,633 @@
+use crate::commands::{
+  permissions::is_dj,
+  playback::{enqueue_song, format_duration, get_call, Requester, SongMetadata, VOIPData},
+  resolve::{resolve_metadata, stored_source},
+  text_response,
+  utils::{get_integer_option, get_string_option, get_subcommand, remove_md_characters},
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use crate::playlists::{self, PlaylistScope, PlaylistStorage};
+use crate::settings::guild_settings;
+use serenity::async_trait;
+use serenity::builder::{
+  CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType, ResolvedOption};
+use serenity::Error;
+use std::time::Duration;
+use tracing::error;
+
+pub struct Playlist;
+
+const NAME_OPTION_NAME: &str = "name";
+const SCOPE_OPTION_NAME: &str = "scope";
+const SEARCH_OPTION_NAME: &str = "search";
+const POSITION_OPTION_NAME: &str = "position";
+
+const SCOPE_USER: &str = "user";
+const SCOPE_GUILD: &str = "server";
+
+const LIST_MAX_ENTRIES: usize = 20;
+
+#[async_trait]
+impl Command for Playlist {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let (subcommand, options) = match get_subcommand(command) {
+      Some(s) => s,
+      None => {
+        error!("No subcommand provided");
+        return text_response(ctx, command, "No subcommand in request").await;
+      }
+    };
+
+    let name = get_string_option(&options, NAME_OPTION_NAME);
+
+    let scope = match get_string_option(&options, SCOPE_OPTION_NAME) {
+      Some(SCOPE_GUILD) => match command.guild_id {
+        Some(g) => PlaylistScope::Guild(g),
+        None => return text_response(ctx, command, "Server playlists need a server").await,
+      },
+      _ => PlaylistScope::User(command.user.id),
+    };
+
+    match (subcommand, name) {
+      ("list", name) => list(ctx, command, scope, name).await,
+      (_, None) => text_response(ctx, command, "No playlist name in request").await,
+      ("save", Some(name)) => save(ctx, command, scope, name).await,
+      ("load", Some(name)) => load(ctx, command, scope, name).await,
+      ("add", Some(name)) => add(ctx, command, &options, scope, name).await,
+      ("remove", Some(name)) => remove(ctx, command, &options, scope, name).await,
+      ("share", Some(name)) => share(ctx, command, name).await,
+      _ => text_response(ctx, command, "Invalid subcommand").await,
+    }
+  }
+
+  fn name() -> &'static str {
+    "playlist"
+  }
+
+  fn info() -> CreateCommand {
+    let name_option = |description: &str| {
+      CreateCommandOption::new(CommandOptionType::String, NAME_OPTION_NAME, description)
+        .required(true)
+    };
+    let scope_option = || {
+      CreateCommandOption::new(
+        CommandOptionType::String,
+        SCOPE_OPTION_NAME,
+        "Use your own playlists or the server's, defaults to your own",
+      )
+      .add_string_choice("Personal", SCOPE_USER)
+      .add_string_choice("Server", SCOPE_GUILD)
+      .required(false)
+    };
+
+    CreateCommand::new(Self::name())
+      .description("Save and load playlists")
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "save",
+          "Save the current queue as a playlist",
+        )
+        .add_sub_option(name_option("Name of the playlist"))
+        .add_sub_option(scope_option()),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "load",
+          "Add every track of a playlist to the queue",
+        )
+        .add_sub_option(name_option("Name of the playlist"))
+        .add_sub_option(scope_option()),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "add",
+          "Add a track to a playlist",
+        )
+        .add_sub_option(name_option("Name of the playlist"))
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            SEARCH_OPTION_NAME,
+            "Search term or a link to a Youtube video or a file",
+          )
+          .required(true),
+        )
+        .add_sub_option(scope_option()),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "remove",
+          "Remove a track from a playlist, or the whole playlist",
+        )
+        .add_sub_option(name_option("Name of the playlist"))
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            POSITION_OPTION_NAME,
+            "Position of the track to remove, removes the playlist if left out",
+          )
+          .min_int_value(1)
+          .required(false),
+        )
+        .add_sub_option(scope_option()),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "list",
+          "List playlists or the tracks in one",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            NAME_OPTION_NAME,
+            "Playlist to show the tracks of",
+          )
+          .required(false),
+        )
+        .add_sub_option(scope_option()),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "share",
+          "Copy one of your playlists to the server's playlists",
+        )
+        .add_sub_option(name_option("Name of your playlist")),
+      )
+  }
+}
+
+async fn save(
+  ctx: &Context,
+  command: &CommandInteraction,
+  scope: PlaylistScope,
+  name: &str,
+) -> Result<(), Error> {
+  let guild_id = match command.guild_id {
+    Some(g) => g,
+    None => return text_response(ctx, command, "Error getting guild information").await,
+  };
+
+  let manager = match songbird::get(ctx).await {
+    Some(arc) => arc.clone(),
+    None => {
+      error!("Error with songbird client");
+      return text_response(ctx, command, "Error getting voice client").await;
+    }
+  };
+
+  let queue = match manager.get(guild_id) {
+    Some(h) => h.lock().await.queue().current_queue(),
+    None => return text_response(ctx, command, "Not in a voice channel").await,
+  };
+
+  if queue.is_empty() {
+    return text_response(ctx, command, "Queue is empty").await;
+  }
+
+  let mut tracks = Vec::with_capacity(queue.len());
+  for handle in &queue {
+    tracks.push(SongMetadata::from_handle(handle).await);
+  }
+
+  let storage = get_storage(ctx).await;
+  let mut playlists = storage.write().await;
+
+  if let Some(existing) = playlists.get(scope, name) {
+    if !can_modify(ctx, existing, scope, command).await {
+      return text_response(
+        ctx,
+        command,
+        format!("Playlist {} belongs to someone else", name),
+      )
+      .await;
+    }
+  }
+
+  playlists.insert(
+    scope,
+    name,
+    playlists::Playlist {
+      owner: command.user.id,
+      tracks: tracks.clone(),
+    },
+  );
+  playlists.save();
+  drop(playlists);
+
+  playlist_response(ctx, command, "Saved playlist", name, &tracks).await
+}
+
+async fn load(
+  ctx: &Context,
+  command: &CommandInteraction,
+  scope: PlaylistScope,
+  name: &str,
+) -> Result<(), Error> {
+  let tracks = {
+    let storage = get_storage(ctx).await;
+    let playlists = storage.read().await;
+    match playlists.get(scope, name) {
+      Some(p) => p.tracks.clone(),
+      None => {
+        return text_response(
+          ctx,
+          command,
+          format!("No playlist named {} in {} playlists", name, scope),
+        )
+        .await
+      }
+    }
+  };
+
+  if tracks.is_empty() {
+    return text_response(ctx, command, format!("Playlist {} is empty", name)).await;
+  }
+
+  let voip_data = match VOIPData::from(ctx, command).await {
+    Ok(v) => v,
+    Err(s) => return text_response(ctx, command, s).await,
+  };
+
+  let manager = match songbird::get(ctx).await {
+    Some(arc) => arc.clone(),
+    None => {
+      error!("Error with songbird client");
+      return text_response(ctx, command, "Error getting voice client").await;
+    }
+  };
+
+  let handler_lock = match get_call(manager, voip_data).await {
+    Ok(h) => h,
+    Err(e) => return text_response(ctx, command, e).await,
+  };
+
+  let requester = Requester::from_command(command);
+  let mut handler = handler_lock.lock().await;
+  let mut loaded = Vec::with_capacity(tracks.len());
+  let mut skipped = vec![];
+  for metadata in tracks {
+    let source = stored_source(ctx, &metadata).await;
+    match enqueue_song(
+      ctx,
+      command,
+      &mut handler,
+      source,
+      metadata.clone(),
+      requester.clone(),
+    )
+    .await
+    {
+      Ok(_) => loaded.push(metadata),
+      Err(reason) => skipped.push(reason),
+    }
+  }
+  drop(handler);
+
+  let mut embed = playlist_embed("Loaded playlist", name, &loaded);
+  if let Some(reason) = skipped.first() {
+    embed = embed.field(
+      "Skipped",
+      format!("{} tracks: {}", skipped.len(), reason),
+      false,
+    );
+  }
+
+  match command
+    .edit_response(&ctx.http, EditInteractionResponse::new().embed(embed))
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+async fn add(
+  ctx: &Context,
+  command: &CommandInteraction,
+  options: &[ResolvedOption<'_>],
+  scope: PlaylistScope,
+  name: &str,
+) -> Result<(), Error> {
+  let search = match get_string_option(options, SEARCH_OPTION_NAME) {
+    Some(s) => s.to_string(),
+    None => return text_response(ctx, command, "No search term or URL in request").await,
+  };
+
+  {
+    let storage = get_storage(ctx).await;
+    let playlists = storage.read().await;
+    if let Some(existing) = playlists.get(scope, name) {
+      if !can_modify(ctx, existing, scope, command).await {
+        return text_response(
+          ctx,
+          command,
+          format!("Playlist {} belongs to someone else", name),
+        )
+        .await;
+      }
+    }
+  }
+
+  let metadata = resolve_metadata(ctx, &search).await;
+
+  if metadata.url.is_none() {
+    return text_response(ctx, command, "Couldn't find the track").await;
+  }
+
+  let storage = get_storage(ctx).await;
+  let mut playlists = storage.write().await;
+  let tracks = match playlists.get_mut(scope, name) {
+    Some(p) => {
+      p.tracks.push(metadata.clone());
+      p.tracks.clone()
+    }
+    None => {
+      let tracks = vec![metadata.clone()];
+      playlists.insert(
+        scope,
+        name,
+        playlists::Playlist {
+          owner: command.user.id,
+          tracks: tracks.clone(),
+        },
+      );
+      tracks
+    }
+  };
+  playlists.save();
+  drop(playlists);
+
+  playlist_response(
+    ctx,
+    command,
+    &format!("Added {}", remove_md_characters(metadata.title)),
+    name,
+    &tracks,
+  )
+  .await
+}
+
+async fn remove(
+  ctx: &Context,
+  command: &CommandInteraction,
+  options: &[ResolvedOption<'_>],
+  scope: PlaylistScope,
+  name: &str,
+) -> Result<(), Error> {
+  let storage = get_storage(ctx).await;
+  let mut playlists = storage.write().await;
+
+  let playlist = match playlists.get(scope, name) {
+    Some(p) => p,
+    None => {
+      return text_response(
+        ctx,
+        command,
+        format!("No playlist named {} in {} playlists", name, scope),
+      )
+      .await
+    }
+  };
+
+  if !can_modify(ctx, playlist, scope, command).await {
+    return text_response(
+      ctx,
+      command,
+      format!("Playlist {} belongs to someone else", name),
+    )
+    .await;
+  }
+
+  let response = match get_integer_option(options, POSITION_OPTION_NAME) {
+    Some(position) => {
+      let tracks = &mut playlists
+        .get_mut(scope, name)
+        .expect("Playlist removed while locked")
+        .tracks;
+      if position < 1 || position as usize > tracks.len() {
+        return text_response(
+          ctx,
+          command,
+          format!("No track at position {} (max: {})", position, tracks.len()),
+        )
+        .await;
+      }
+      let removed = tracks.remove(position as usize - 1);
+      format!(
+        "Removed {} from playlist {}",
+        remove_md_characters(removed.title),
+        name
+      )
+    }
+    None => {
+      playlists.remove(scope, name);
+      format!("Removed playlist {}", name)
+    }
+  };
+  playlists.save();
+  drop(playlists);
+
+  text_response(ctx, command, response).await
+}
+
+async fn list(
+  ctx: &Context,
+  command: &CommandInteraction,
+  scope: PlaylistScope,
+  name: Option<&str>,
+) -> Result<(), Error> {
+  let storage = get_storage(ctx).await;
+  let playlists = storage.read().await;
+
+  let (title, description) = match name {
+    Some(name) => match playlists.get(scope, name) {
+      Some(p) => (name.to_string(), format_track_list(&p.tracks)),
+      None => {
+        return text_response(
+          ctx,
+          command,
+          format!("No playlist named {} in {} playlists", name, scope),
+        )
+        .await
+      }
+    },
+    None => {
+      let list = playlists.list(scope);
+      if list.is_empty() {
+        return text_response(ctx, command, format!("No playlists in {} playlists", scope)).await;
+      }
+      let mut description = list
+        .iter()
+        .take(LIST_MAX_ENTRIES)
+        .map(|(name, p)| {
+          let duration: Duration = p.tracks.iter().map(|t| t.duration).sum();
+          format!(
+            "**{}** - {} tracks - {}",
+            remove_md_characters(name),
+            p.tracks.len(),
+            format_duration(duration)
+          )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+      if list.len() > LIST_MAX_ENTRIES {
+        description.push_str(&format!("\n...and {} more", list.len() - LIST_MAX_ENTRIES));
+      }
+      ("Playlists".to_string(), description)
+    }
+  };
+  drop(playlists);
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new().embed(
+        CreateEmbed::new()
+          .title(title)
+          .colour(EMBED_COLOUR)
+          .description(description),
+      ),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+async fn share(ctx: &Context, command: &CommandInteraction, name: &str) -> Result<(), Error> {
+  let guild_scope = match command.guild_id {
+    Some(g) => PlaylistScope::Guild(g),
+    None => return text_response(ctx, command, "Server playlists need a server").await,
+  };
+  let user_scope = PlaylistScope::User(command.user.id);
+
+  let storage = get_storage(ctx).await;
+  let mut playlists = storage.write().await;
+
+  let playlist = match playlists.get(user_scope, name) {
+    Some(p) => p.clone(),
+    None => {
+      return text_response(
+        ctx,
+        command,
+        format!("No playlist named {} in {} playlists", name, user_scope),
+      )
+      .await
+    }
+  };
+
+  if let Some(existing) = playlists.get(guild_scope, name) {
+    if !can_modify(ctx, existing, guild_scope, command).await {
+      return text_response(
+        ctx,
+        command,
+        format!("The server already has a playlist named {}", name),
+      )
+      .await;
+    }
+  }
+
+  playlists.insert(guild_scope, name, playlist.clone());
+  playlists.save();
+  drop(playlists);
+
+  playlist_response(ctx, command, "Shared playlist", name, &playlist.tracks).await
+}
+
+async fn get_storage(ctx: &Context) -> <PlaylistStorage as serenity::prelude::TypeMapKey>::Value {
+  let data = ctx.data.read().await;
+  data
+    .get::<PlaylistStorage>()
+    .cloned()
+    .expect("PlaylistStorage did not exist")
+}
+
+/// Server playlists can be changed by their owner and by DJs
+async fn can_modify(
+  ctx: &Context,
+  playlist: &playlists::Playlist,
+  scope: PlaylistScope,
+  command: &CommandInteraction,
+) -> bool {
+  match scope {
+    PlaylistScope::User(_) => true,
+    PlaylistScope::Guild(guild_id) => {
+      playlist.owner == command.user.id
+        || is_dj(
+          command.member.as_deref(),
+          &guild_settings(ctx, guild_id).await,
+        )
+    }
+  }
+}
+
+fn format_track_list(tracks: &[SongMetadata]) -> String {
+  let mut out = tracks
+    .iter()
+    .enumerate()
+    .take(LIST_MAX_ENTRIES)
+    .map(|(i, t)| {
+      format!(
+        "#{} {} - {}",
+        i + 1,
+        remove_md_characters(&t.title),
+        format_duration(t.duration)
+      )
+    })
+    .collect::<Vec<_>>()
+    .join("\n");
+
+  if tracks.len() > LIST_MAX_ENTRIES {
+    out.push_str(&format!(
+      "\n...and {} more",
+      tracks.len() - LIST_MAX_ENTRIES
+    ));
+  }
+  if out.is_empty() {
+    out.push_str("No tracks");
+  }
+  out
+}
+
+async fn playlist_response(
+  ctx: &Context,
+  command: &CommandInteraction,
+  title: &str,
+  name: &str,
+  tracks: &[SongMetadata],
+) -> Result<(), Error> {
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new().embed(playlist_embed(title, name, tracks)),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+fn playlist_embed(title: &str, name: &str, tracks: &[SongMetadata]) -> CreateEmbed {
+  let duration: Duration = tracks.iter().map(|t| t.duration).sum();
+
+  CreateEmbed::new()
+    .title(title)
+    .colour(EMBED_COLOUR)
+    .fields(vec![
+      ("Playlist", remove_md_characters(name), true),
+      ("Tracks", tracks.len().to_string(), true),
+    ])
+    .footer(CreateEmbedFooter::new(format_duration(duration)))
+}
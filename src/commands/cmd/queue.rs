@@ -1,130 +1,446 @@
+use crate::audio::filters::track_position;
 use crate::commands::{
   playback::{
-    format_duration, format_duration_live, get_queue_length_and_duration, SongMetadata, VOIPData,
+    enqueue_song, format_duration, format_duration_live, get_call, get_queue_length_and_duration,
+    progress_bar, Requester, SongMetadata, VOIPData,
   },
-  text_response,
-  utils::remove_md_characters,
+  queue_file::{self, QueueFileEntry},
+  resolve::{resolve_metadata, stored_source},
+  text_response, ticker,
+  utils::{get_subcommand, remove_md_characters},
   Command,
 };
 use crate::constants::EMBED_COLOUR;
-use serenity::builder::{CreateCommand, CreateEmbedFooter, EditInteractionResponse};
+use crate::settings::guild_settings;
+use serenity::builder::{
+  CreateAttachment, CreateCommand, CreateCommandOption, CreateEmbedFooter, EditInteractionResponse,
+};
 use serenity::client::Context;
-use serenity::model::application::CommandInteraction;
+use serenity::model::application::{
+  CommandInteraction, CommandOptionType, ResolvedOption, ResolvedValue,
+};
+use serenity::model::id::GuildId;
 use serenity::Error;
 use serenity::{async_trait, builder::CreateEmbed};
 use songbird::tracks::TrackHandle;
+use std::sync::Arc;
 use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
 use tracing::error;
 
 pub struct Queue;
 
+const FILE_OPTION_NAME: &str = "file";
+const IMPORT_MAX_SIZE: u32 = 512 * 1024;
+const IMPORT_MAX_ENTRIES: usize = 200;
+/// Imported tracks looked up at once when their lengths have to be checked
+const IMPORT_LOOKUPS: usize = 4;
+/// Time spent looking imported tracks up, leaving the rest of the command timeout for queueing
+const IMPORT_LOOKUP_TIME: Duration = Duration::from_secs(8);
+
 #[async_trait]
 impl Command for Queue {
   async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
-    let voip_data = match VOIPData::from(ctx, command).await {
-      Ok(v) => v,
-      Err(s) => return text_response(ctx, command, s).await,
-    };
+    match get_subcommand(command) {
+      Some(("export", _)) => export(ctx, command).await,
+      Some(("import", options)) => import(ctx, command, &options).await,
+      _ => view(ctx, command).await,
+    }
+  }
 
-    let guild_id = voip_data.guild_id;
+  fn name() -> &'static str {
+    "queue"
+  }
 
-    let manager = match songbird::get(ctx).await {
-      Some(arc) => arc.clone(),
-      None => {
-        error!("Error with songbird client");
-        return text_response(ctx, command, "Error getting voice client").await;
-      }
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("View, export or import the queue")
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "view",
+        "View currently queued songs",
+      ))
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "export",
+        "Export the queue as M3U8 and JSON files",
+      ))
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "import",
+          "Add every track from an exported M3U8 or JSON file to the queue",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Attachment,
+            FILE_OPTION_NAME,
+            "M3U8 or JSON file from /queue export",
+          )
+          .required(true),
+        ),
+      )
+  }
+}
+
+async fn view(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+  let voip_data = match VOIPData::from(ctx, command).await {
+    Ok(v) => v,
+    Err(s) => return text_response(ctx, command, s).await,
+  };
+
+  let guild_id = voip_data.guild_id;
+
+  let manager = match songbird::get(ctx).await {
+    Some(arc) => arc.clone(),
+    None => {
+      error!("Error with songbird client");
+      return text_response(ctx, command, "Error getting voice client").await;
+    }
+  };
+
+  let handler_lock = match manager.get(guild_id) {
+    Some(h) => h,
+    None => return text_response(ctx, command, "Not in a voice channel").await,
+  };
+
+  let queue = handler_lock.lock().await.queue().current_queue();
+  if queue.is_empty() {
+    return text_response(ctx, command, "Queue is empty").await;
+  }
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new().embed(Queue::embed(ctx, guild_id, queue).await),
+    )
+    .await
+  {
+    Ok(m) => {
+      ticker::watch_queue(ctx, guild_id, m).await;
+      Ok(())
+    }
+    Err(e) => Err(e),
+  }
+}
+
+impl Queue {
+  /// The current track with its progress and the tracks after it, `queue` can't be empty
+  pub async fn embed(ctx: &Context, guild_id: GuildId, queue: Vec<TrackHandle>) -> CreateEmbed {
+    let (count, duration) = get_queue_length_and_duration(&queue).await;
+
+    let current_metadata = SongMetadata::from_handle(&queue[0]).await;
+    let current_requester = Requester::from_handle(&queue[0]).await;
+
+    let track_position = track_position(ctx, guild_id, &queue[0]).await;
+
+    let current_song_duration =
+      format_duration_live(current_metadata.duration, &current_metadata.title);
+    let mut live = bool::from(&current_song_duration);
+
+    let mut current_song_info = format_with_url(
+      remove_md_characters(truncate_unicode(&current_metadata.title, 67)),
+      current_metadata.url.as_ref(),
+    );
+    if !live && !current_metadata.duration.is_zero() {
+      current_song_info.push_str(&format!(
+        "\n{}",
+        progress_bar(track_position, current_metadata.duration)
+      ));
+    }
+    current_song_info.push_str(&format!(
+      " \n**[ {} / {} ]**",
+      format_duration(track_position),
+      current_song_duration,
+    ));
+    if let Some(requester) = current_requester {
+      current_song_info.push_str(&format!(
+        " - requested by {}",
+        remove_md_characters(requester.name)
+      ));
+    }
+    if let Some(chapter) = current_metadata.chapter_at(track_position) {
+      current_song_info.push_str(&format!(
+        "\n{}",
+        remove_md_characters(current_metadata.format_chapter(chapter))
+      ));
+    }
+
+    let length = queue.len();
+    let queue_f = format_queue_string(queue).await;
+
+    live = queue_f.3 || live;
+
+    let fields = match length < 2 {
+      true => vec![("Currently playing: ", current_song_info, false)],
+      false => vec![
+        ("Currently playing: ", current_song_info, false),
+        ("Position", queue_f.0, true),
+        ("Track", queue_f.1, true),
+        ("Duration", queue_f.2, true),
+      ],
     };
 
-    let handler_lock = match manager.get(guild_id) {
-      Some(h) => h,
-      None => return text_response(ctx, command, "Not in a voice channel").await,
+    let time_left = match live {
+      true => "LIVE".to_string(),
+      false => format_duration(
+        duration
+          .checked_sub(track_position)
+          .unwrap_or(Duration::from_secs(0)),
+      ),
     };
 
-    let handler = handler_lock.lock().await;
+    CreateEmbed::new()
+      .title("Queue")
+      .colour(EMBED_COLOUR)
+      .fields(fields)
+      .footer(CreateEmbedFooter::new(format!(
+        "{} songs in queue - {}",
+        count, time_left
+      )))
+  }
+}
 
-    if !handler.queue().is_empty() {
-      let queue = handler.queue().current_queue();
-      let (count, duration) = get_queue_length_and_duration(&queue).await;
+async fn export(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+  let voip_data = match VOIPData::from(ctx, command).await {
+    Ok(v) => v,
+    Err(s) => return text_response(ctx, command, s).await,
+  };
 
-      let current_metadata = SongMetadata::from_handle(&queue[0]).await;
+  let manager = match songbird::get(ctx).await {
+    Some(arc) => arc.clone(),
+    None => {
+      error!("Error with songbird client");
+      return text_response(ctx, command, "Error getting voice client").await;
+    }
+  };
 
-      let current_position = match queue[0].get_info().await {
-        Ok(state) => state.position,
-        Err(e) => {
-          error!("Couldn't get track state: {}", e);
-          Duration::from_secs(0)
-        }
-      };
+  let queue = match manager.get(voip_data.guild_id) {
+    Some(h) => h.lock().await.queue().current_queue(),
+    None => return text_response(ctx, command, "Not in a voice channel").await,
+  };
 
-      let current_song_duration =
-        format_duration_live(current_metadata.duration, &current_metadata.title);
-      let mut live = bool::from(&current_song_duration);
+  if queue.is_empty() {
+    return text_response(ctx, command, "Queue is empty").await;
+  }
 
-      let current_song_info = format!(
-        "{} \n**[ {} / {} ]**",
-        format_with_url(
-          remove_md_characters(truncate_unicode(&current_metadata.title, 67)),
-          current_metadata.url.as_ref()
-        ),
-        format_duration(current_position),
-        current_song_duration,
-      );
-
-      let queue_f = format_queue_string(queue).await;
-
-      live = queue_f.3 || live;
-
-      let fields = match handler.queue().len() < 2 {
-        true => vec![("Currently playing: ", current_song_info, false)],
-        false => vec![
-          ("Currently playing: ", current_song_info, false),
-          ("Position", queue_f.0, true),
-          ("Track", queue_f.1, true),
-          ("Duration", queue_f.2, true),
-        ],
-      };
-
-      let time_left = match live {
-        true => "LIVE".to_string(),
-        false => format_duration(
-          duration
-            .checked_sub(current_position)
-            .unwrap_or(Duration::from_secs(0)),
-        ),
-      };
-
-      match command
-        .edit_response(
-          &ctx.http,
-          EditInteractionResponse::new().embed(
-            CreateEmbed::new()
-              .title("Queue")
-              .colour(EMBED_COLOUR)
-              .fields(fields)
-              .footer(CreateEmbedFooter::new(format!(
-                "{} songs in queue - {}",
-                count, time_left
-              ))),
-          ),
+  let mut entries = Vec::with_capacity(queue.len());
+  for handle in &queue {
+    let metadata = SongMetadata::from_handle(handle).await;
+    let mut entry = QueueFileEntry::from(&metadata);
+    entry.requester = Requester::from_handle(handle).await.map(|r| r.name);
+    entries.push(entry);
+  }
+  let (count, duration) = get_queue_length_and_duration(&queue).await;
+
+  let json = match queue_file::to_json(&entries) {
+    Ok(j) => j,
+    Err(e) => {
+      error!("Error serializing queue: {}", e);
+      return text_response(ctx, command, "Error exporting queue").await;
+    }
+  };
+
+  match command
+    .edit_response(
+      &ctx.http,
+      EditInteractionResponse::new()
+        .embed(
+          CreateEmbed::new()
+            .title("Exported queue")
+            .colour(EMBED_COLOUR)
+            .footer(CreateEmbedFooter::new(format!(
+              "{} songs in queue - {}",
+              count,
+              format_duration(duration)
+            ))),
         )
-        .await
-      {
-        Ok(_m) => Ok(()),
-        Err(e) => Err(e),
+        .new_attachment(CreateAttachment::bytes(
+          queue_file::to_m3u(&entries),
+          queue_file::M3U_FILENAME,
+        ))
+        .new_attachment(CreateAttachment::bytes(json, queue_file::JSON_FILENAME)),
+    )
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+async fn import(
+  ctx: &Context,
+  command: &CommandInteraction,
+  options: &[ResolvedOption<'_>],
+) -> Result<(), Error> {
+  let attachment = match options.iter().find(|o| o.name == FILE_OPTION_NAME) {
+    Some(o) => {
+      if let ResolvedValue::Attachment(a) = o.value {
+        a
+      } else {
+        error!("Invalid attachment option provided");
+        return text_response(ctx, command, "No file in request").await;
       }
-    } else {
-      text_response(ctx, command, "Queue is empty").await
     }
+    None => {
+      error!("No options provided");
+      return text_response(ctx, command, "No file in request").await;
+    }
+  };
+
+  if attachment.size > IMPORT_MAX_SIZE {
+    return text_response(
+      ctx,
+      command,
+      format!("File is too large (max: {}KiB)", IMPORT_MAX_SIZE / 1024),
+    )
+    .await;
   }
 
-  fn name() -> &'static str {
-    "queue"
+  let content = match attachment.download().await {
+    Ok(bytes) => String::from_utf8_lossy(&bytes).to_string(),
+    Err(e) => {
+      error!("Error downloading attachment: {}", e);
+      return text_response(ctx, command, "Couldn't download the file").await;
+    }
+  };
+
+  let entries = match queue_file::parse(&attachment.filename, &content) {
+    Ok(e) => e,
+    Err(e) => return text_response(ctx, command, e).await,
+  };
+
+  if entries.is_empty() {
+    return text_response(ctx, command, "No tracks in the file").await;
+  }
+  if entries.len() > IMPORT_MAX_ENTRIES {
+    return text_response(
+      ctx,
+      command,
+      format!("Too many tracks in the file (max: {})", IMPORT_MAX_ENTRIES),
+    )
+    .await;
   }
 
-  fn info() -> CreateCommand {
-    CreateCommand::new(Self::name()).description("View currently queued songs")
+  let voip_data = match VOIPData::from(ctx, command).await {
+    Ok(v) => v,
+    Err(s) => return text_response(ctx, command, s).await,
+  };
+
+  let manager = match songbird::get(ctx).await {
+    Some(arc) => arc.clone(),
+    None => {
+      error!("Error with songbird client");
+      return text_response(ctx, command, "Error getting voice client").await;
+    }
+  };
+
+  let guild_id = voip_data.guild_id;
+  let handler_lock = match get_call(manager, voip_data).await {
+    Ok(h) => h,
+    Err(e) => return text_response(ctx, command, e).await,
+  };
+
+  // Lengths in the file can be edited, so they're looked up again when the limits depend on them
+  let limits = guild_settings(ctx, guild_id).await.limits;
+  let tracks = entries
+    .into_iter()
+    .map(SongMetadata::from)
+    .collect::<Vec<_>>();
+  let tracks = match limits.max_duration.is_some() || !limits.allow_live {
+    true => lookup_all(ctx, tracks).await,
+    false => tracks.into_iter().map(Ok).collect(),
+  };
+
+  let requester = Requester::from_command(command);
+  let mut handler = handler_lock.lock().await;
+  let mut imported = 0;
+  let mut skipped = vec![];
+  for track in tracks {
+    let metadata = match track {
+      Ok(m) => m,
+      Err(reason) => {
+        skipped.push(reason);
+        continue;
+      }
+    };
+    let source = stored_source(ctx, &metadata).await;
+    match enqueue_song(
+      ctx,
+      command,
+      &mut handler,
+      source,
+      metadata,
+      requester.clone(),
+    )
+    .await
+    {
+      Ok(_) => imported += 1,
+      Err(reason) => skipped.push(reason),
+    }
+  }
+  let (count, duration) = get_queue_length_and_duration(&handler.queue().current_queue()).await;
+  drop(handler);
+
+  let mut embed = CreateEmbed::new()
+    .title(format!("Imported {} tracks", imported))
+    .colour(EMBED_COLOUR)
+    .footer(CreateEmbedFooter::new(format!(
+      "{} songs in queue - {}",
+      count,
+      format_duration(duration)
+    )));
+  if let Some(reason) = skipped.first() {
+    embed = embed.field(
+      "Skipped",
+      format!("{} tracks: {}", skipped.len(), reason),
+      false,
+    );
+  }
+
+  match command
+    .edit_response(&ctx.http, EditInteractionResponse::new().embed(embed))
+    .await
+  {
+    Ok(_m) => Ok(()),
+    Err(e) => Err(e),
+  }
+}
+
+/// Looks each track up by what would be played, keeping the order. Tracks that can't be found or
+/// aren't looked up in time are errors
+async fn lookup_all(ctx: &Context, tracks: Vec<SongMetadata>) -> Vec<Result<SongMetadata, String>> {
+  let mut results = vec![Err("Couldn't look the track up in time".to_string()); tracks.len()];
+  let lookups = Arc::new(Semaphore::new(IMPORT_LOOKUPS));
+  let mut tasks = JoinSet::new();
+  for (i, track) in tracks.into_iter().enumerate() {
+    let ctx = ctx.clone();
+    let lookups = lookups.clone();
+    tasks.spawn(async move {
+      let _permit = lookups.acquire_owned().await;
+      let query = track.url.unwrap_or(track.query);
+      (i, resolve_metadata(&ctx, &query).await)
+    });
+  }
+
+  let collect = async {
+    while let Some(result) = tasks.join_next().await {
+      match result {
+        Ok((i, metadata)) if metadata.url.is_some() => results[i] = Ok(metadata),
+        Ok((i, _)) => results[i] = Err("Couldn't find the track".to_string()),
+        Err(e) => error!("Error looking up an imported track: {}", e),
+      }
+    }
+  };
+  // Dropping the set stops the lookups still running
+  if tokio::time::timeout(IMPORT_LOOKUP_TIME, collect)
+    .await
+    .is_err()
+  {
+    error!("Imported tracks weren't all looked up in time");
   }
+  results
 }
 
 fn format_with_url(title: String, url: Option<&String>) -> String {
@@ -156,13 +472,24 @@ async fn format_queue_string(queue: Vec<TrackHandle>) -> (String, String, String
   let mut live = false;
   for (i, handle) in queue.iter().enumerate().skip(1).take(4) {
     let metadata = SongMetadata::from_handle(handle).await;
+    let requester = Requester::from_handle(handle).await;
     let title_trimmed = truncate_unicode(&metadata.title, 37);
     let title = format_with_url(remove_md_characters(title_trimmed), metadata.url.as_ref());
 
     let duration = format_duration_live(metadata.duration, &metadata.title);
     live = bool::from(&duration) || live;
 
-    pos_out.push_str(format!("#{} \n", i).as_str());
+    match requester {
+      Some(r) => pos_out.push_str(
+        format!(
+          "#{} {} \n",
+          i,
+          remove_md_characters(truncate_unicode(&r.name, 12))
+        )
+        .as_str(),
+      ),
+      None => pos_out.push_str(format!("#{} \n", i).as_str()),
+    }
     title_out.push_str(format!("{} \n", title).as_str());
     duration_out.push_str(format!("{} \n", duration).as_str());
   }
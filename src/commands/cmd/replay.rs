@@ -0,0 +1,98 @@
+use crate::commands::{
+  playback::{enqueue_song_unchecked, Requester, SongMetadata, VOIPData},
+  resolve::stored_source,
+  text_response,
+  utils::remove_md_characters,
+  Command,
+};
+use serenity::async_trait;
+use serenity::builder::CreateCommand;
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+use serenity::Error;
+use std::time::Duration;
+use tracing::error;
+
+pub struct Replay;
+
+#[async_trait]
+impl Command for Replay {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let voip_data = match VOIPData::from(ctx, command).await {
+      Ok(v) => v,
+      Err(s) => return text_response(ctx, command, s).await,
+    };
+
+    let guild_id = voip_data.guild_id;
+
+    let manager = match songbird::get(ctx).await {
+      Some(arc) => arc.clone(),
+      None => {
+        error!("Error with songbird client");
+        return text_response(ctx, command, "Error getting voice client").await;
+      }
+    };
+
+    let handler_lock = match manager.get(guild_id) {
+      Some(h) => {
+        if voip_data.compare_to_call(&h).await {
+          h
+        } else {
+          return text_response(ctx, command, "You're not in the voice channel").await;
+        }
+      }
+      None => return text_response(ctx, command, "Not in a voice channel").await,
+    };
+
+    let mut handler = handler_lock.lock().await;
+
+    let current = match handler.queue().current() {
+      Some(t) => t,
+      None => return text_response(ctx, command, "Nothing playing").await,
+    };
+    let metadata = SongMetadata::from_handle(&current).await;
+    let title = remove_md_characters(&metadata.title);
+
+    // Songbird recreates inputs that can't seek backwards, this fails if that isn't possible
+    let error = match current.seek(Duration::ZERO).result_async().await {
+      Ok(_) => return text_response(ctx, command, format!("Replaying {}", title)).await,
+      Err(e) => e,
+    };
+    error!(
+      "Error seeking to the start, playing it again instead: {}",
+      error
+    );
+
+    // Start over with a fresh copy of the track in its place
+    let requester = Requester::from_handle(&current)
+      .await
+      .unwrap_or_else(|| Requester::from_command(command));
+    let source = stored_source(ctx, &metadata).await;
+    let handle =
+      match enqueue_song_unchecked(ctx, command, &mut handler, source, metadata, requester).await {
+        Ok(h) => h,
+        Err(e) => return text_response(ctx, command, e).await,
+      };
+    handler.queue().modify_queue(|q| {
+      if let Some(i) = q.iter().position(|t| t.uuid() == handle.uuid()) {
+        if let Some(track) = q.remove(i) {
+          q.insert(1, track);
+        }
+      }
+    });
+    if let Err(e) = handler.queue().skip() {
+      error!("Error skipping to the replayed track: {}", e);
+      return text_response(ctx, command, "Could not replay").await;
+    }
+
+    text_response(ctx, command, format!("Replaying {}", title)).await
+  }
+
+  fn name() -> &'static str {
+    "replay"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name()).description("Play the current song again from the start")
+  }
+}
@@ -0,0 +1,137 @@
+use crate::commands::{
+  playback::{
+    enqueue_song, format_duration, get_queue_length_and_duration, Requester, SongMetadata, VOIPData,
+  },
+  resolve::stored_source,
+  text_response,
+  utils::{get_string_option, remove_md_characters},
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use serenity::async_trait;
+use serenity::builder::{
+  CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter, EditInteractionResponse,
+};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType};
+use serenity::Error;
+use tracing::error;
+
+pub struct Requeue;
+
+const POSITION_OPTION_NAME: &str = "position";
+
+const POSITION_FRONT: &str = "front";
+const POSITION_BACK: &str = "back";
+
+#[async_trait]
+impl Command for Requeue {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let voip_data = match VOIPData::from(ctx, command).await {
+      Ok(v) => v,
+      Err(s) => return text_response(ctx, command, s).await,
+    };
+
+    let guild_id = voip_data.guild_id;
+
+    let manager = match songbird::get(ctx).await {
+      Some(arc) => arc.clone(),
+      None => {
+        error!("Error with songbird client");
+        return text_response(ctx, command, "Error getting voice client").await;
+      }
+    };
+
+    let handler_lock = match manager.get(guild_id) {
+      Some(h) => {
+        if voip_data.compare_to_call(&h).await {
+          h
+        } else {
+          return text_response(ctx, command, "You're not in the voice channel").await;
+        }
+      }
+      None => return text_response(ctx, command, "Not in a voice channel").await,
+    };
+
+    let front =
+      get_string_option(&command.data.options(), POSITION_OPTION_NAME) != Some(POSITION_BACK);
+
+    let mut handler = handler_lock.lock().await;
+
+    let current = match handler.queue().current() {
+      Some(t) => t,
+      None => return text_response(ctx, command, "Nothing playing").await,
+    };
+    let metadata = SongMetadata::from_handle(&current).await;
+    let title = metadata.title.clone();
+
+    let source = stored_source(ctx, &metadata).await;
+    let requester = Requester::from_command(command);
+    let handle = match enqueue_song(ctx, command, &mut handler, source, metadata, requester).await {
+      Ok(h) => h,
+      Err(e) => return text_response(ctx, command, e).await,
+    };
+
+    // Placed exactly where asked, even if fair share moved it
+    handler.queue().modify_queue(|q| {
+      if let Some(i) = q.iter().position(|t| t.uuid() == handle.uuid()) {
+        if let Some(track) = q.remove(i) {
+          match front {
+            true => q.insert(1, track),
+            false => q.push_back(track),
+          }
+        }
+      }
+    });
+
+    let queue = handler.queue().current_queue();
+    drop(handler);
+    let position = queue
+      .iter()
+      .position(|t| t.uuid() == handle.uuid())
+      .unwrap_or_default();
+    let (count, duration) = get_queue_length_and_duration(&queue).await;
+
+    match command
+      .edit_response(
+        &ctx.http,
+        EditInteractionResponse::new().embed(
+          CreateEmbed::new()
+            .title("Requeued")
+            .colour(EMBED_COLOUR)
+            .fields(vec![
+              ("Track", remove_md_characters(title), true),
+              ("Position", format!("#{}", position), true),
+            ])
+            .footer(CreateEmbedFooter::new(format!(
+              "{} songs in queue - {}",
+              count,
+              format_duration(duration)
+            ))),
+        ),
+      )
+      .await
+    {
+      Ok(_m) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn name() -> &'static str {
+    "requeue"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("Add the current song to the queue again")
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::String,
+          POSITION_OPTION_NAME,
+          "Where in the queue to add it, next by default",
+        )
+        .add_string_choice("Next", POSITION_FRONT)
+        .add_string_choice("Last", POSITION_BACK),
+      )
+  }
+}
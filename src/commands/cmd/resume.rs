@@ -1,6 +1,8 @@
+use crate::audio::filters::track_position;
 use crate::commands::{
+  events::resume_playback,
   playback::{format_duration_live, SongMetadata, VOIPData},
-  text_response, Command,
+  text_response, ticker, Command,
 };
 use crate::constants::EMBED_COLOUR;
 use serenity::builder::CreateCommand;
@@ -54,20 +56,19 @@ impl Command for Resume {
       None => return text_response(ctx, command, "Nothing is paused").await,
     };
 
-    match current.play() {
+    match resume_playback(handler.queue()).await {
       Err(e) => {
         error!("Error resuming track: {}", e);
         text_response(ctx, command, "Could not resume").await
       }
       Ok(_) => {
+        drop(handler);
+        ticker::resume(ctx, guild_id).await;
+
         let metadata = SongMetadata::from_handle(&current).await;
         let title = metadata.title.clone();
 
-        let current_time = current
-          .get_info()
-          .await
-          .map(|info| info.position)
-          .unwrap_or_default();
+        let current_time = track_position(ctx, guild_id, &current).await;
         let current_time = format_duration_live(current_time, &title);
         let duration = format_duration_live(metadata.duration, &title);
 
@@ -1,3 +1,4 @@
+use crate::audio::filters::{played_time, track_position};
 use crate::commands::playback::{SongMetadata, VOIPData};
 use crate::commands::{text_response, Command};
 use serenity::async_trait;
@@ -85,13 +86,7 @@ impl Command for Seek {
       .await;
     }
 
-    let current_position = match current.get_info().await {
-      Ok(state) => state.position,
-      Err(e) => {
-        error!("Couldn't get TrackState: {}", e);
-        std::time::Duration::default()
-      }
-    };
+    let current_position = track_position(ctx, guild_id, &current).await;
 
     if timestamp <= current_position {
       return text_response(
@@ -105,7 +100,11 @@ impl Command for Seek {
       .await;
     }
 
-    match current.seek(timestamp).result_async().await {
+    match current
+      .seek(played_time(ctx, guild_id, timestamp).await)
+      .result_async()
+      .await
+    {
       Ok(_) => text_response(ctx, command, format!("Seeked to {:?}", timestamp)).await,
       Err(e) => {
         error!("Error while seeking: {}", e);
@@ -0,0 +1,649 @@
+use crate::audio::loudness::{MAX_TARGET, MIN_TARGET};
+use crate::commands::{
+  command_names,
+  playback::format_duration,
+  text_response,
+  utils::{
+    get_bool_option, get_channel_option, get_integer_option, get_number_option, get_role_option,
+    get_string_option, get_subcommand,
+  },
+  Command,
+};
+use crate::constants::EMBED_COLOUR;
+use crate::segments::Category;
+use crate::settings::{get_storage, GuildSettings, QueueLimits};
+use serenity::async_trait;
+use serenity::builder::{CreateCommand, CreateCommandOption, CreateEmbed, EditInteractionResponse};
+use serenity::client::Context;
+use serenity::model::application::{CommandInteraction, CommandOptionType, ResolvedOption};
+use serenity::model::channel::ChannelType;
+use serenity::model::Permissions;
+use serenity::Error;
+use std::time::Duration;
+use tracing::error;
+
+pub struct Settings;
+
+const ENABLED_OPTION_NAME: &str = "enabled";
+const FRACTION_OPTION_NAME: &str = "fraction";
+const ROLE_OPTION_NAME: &str = "role";
+const CHANNEL_OPTION_NAME: &str = "channel";
+const ACTION_OPTION_NAME: &str = "action";
+const COMMAND_OPTION_NAME: &str = "command";
+const QUEUE_LENGTH_OPTION_NAME: &str = "queue_length";
+const PER_USER_OPTION_NAME: &str = "per_user";
+const MAX_MINUTES_OPTION_NAME: &str = "max_minutes";
+const LIVE_OPTION_NAME: &str = "live";
+const DUPLICATES_OPTION_NAME: &str = "duplicates";
+const TARGET_OPTION_NAME: &str = "target";
+const CROSSFADE_OPTION_NAME: &str = "crossfade";
+const PRELOAD_OPTION_NAME: &str = "preload";
+const MAX_CROSSFADE_SECS: u64 = 12;
+const MAX_PRELOAD_SECS: u64 = 60;
+
+#[async_trait]
+impl Command for Settings {
+  async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
+    let guild_id = match command.guild_id {
+      Some(g) => g,
+      None => return text_response(ctx, command, "Error getting guild information").await,
+    };
+
+    let (subcommand, options) = match get_subcommand(command) {
+      Some(s) => s,
+      None => {
+        error!("No subcommand provided");
+        return text_response(ctx, command, "No subcommand in request").await;
+      }
+    };
+
+    let storage = get_storage(ctx).await;
+    let mut settings = storage.write().await;
+    let guild_settings = settings.get_mut(guild_id);
+
+    let title = match subcommand {
+      "show" => "Settings",
+      "voteskip" => {
+        set_vote_skip(guild_settings, &options);
+        "Updated vote skip"
+      }
+      "fairshare" => {
+        if let Some(enabled) = get_bool_option(&options, ENABLED_OPTION_NAME) {
+          guild_settings.fair_share = enabled;
+        }
+        "Updated fair share queue"
+      }
+      "limits" => {
+        set_limits(guild_settings, &options);
+        "Updated queue limits"
+      }
+      "normalize" => {
+        if let Some(enabled) = get_bool_option(&options, ENABLED_OPTION_NAME) {
+          guild_settings.normalize = enabled;
+        }
+        if let Some(target) = get_number_option(&options, TARGET_OPTION_NAME) {
+          guild_settings.loudness_target = target.clamp(MIN_TARGET, MAX_TARGET);
+        }
+        "Updated loudness normalization"
+      }
+      "transitions" => {
+        if let Some(secs) = get_integer_option(&options, CROSSFADE_OPTION_NAME) {
+          guild_settings.crossfade =
+            Duration::from_secs((secs.max(0) as u64).min(MAX_CROSSFADE_SECS));
+        }
+        if let Some(secs) = get_integer_option(&options, PRELOAD_OPTION_NAME) {
+          guild_settings.preload = Duration::from_secs((secs.max(0) as u64).min(MAX_PRELOAD_SECS));
+        }
+        "Updated transitions"
+      }
+      "segments" => {
+        set_segments(guild_settings, &options);
+        "Updated segment skipping"
+      }
+      "dj" => {
+        guild_settings.dj_role = get_role_option(&options, ROLE_OPTION_NAME);
+        "Updated DJ role"
+      }
+      "channels" => match set_channels(guild_settings, &options) {
+        Ok(_) => "Updated command channels",
+        Err(e) => return text_response(ctx, command, e).await,
+      },
+      "command" => match set_command_rule(guild_settings, &options) {
+        Ok(_) => "Updated command permissions",
+        Err(e) => return text_response(ctx, command, e).await,
+      },
+      _ => return text_response(ctx, command, "Invalid subcommand").await,
+    };
+
+    let embed = settings_embed(guild_settings).title(title);
+    if subcommand != "show" {
+      settings.save();
+    }
+    drop(settings);
+
+    match command
+      .edit_response(&ctx.http, EditInteractionResponse::new().embed(embed))
+      .await
+    {
+      Ok(_m) => Ok(()),
+      Err(e) => Err(e),
+    }
+  }
+
+  fn name() -> &'static str {
+    "settings"
+  }
+
+  fn info() -> CreateCommand {
+    CreateCommand::new(Self::name())
+      .description("Configure the bot for this server")
+      .default_member_permissions(Permissions::MANAGE_GUILD)
+      .dm_permission(false)
+      .add_option(CreateCommandOption::new(
+        CommandOptionType::SubCommand,
+        "show",
+        "Show the current settings",
+      ))
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "voteskip",
+          "Make listeners vote to skip tracks they didn't request",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            ENABLED_OPTION_NAME,
+            "Require votes to skip",
+          )
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            FRACTION_OPTION_NAME,
+            "Fraction of listeners that need to vote, e.g. 0.5",
+          )
+          .min_number_value(0.01)
+          .max_number_value(1.0)
+          .required(false),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "fairshare",
+          "Take turns between requesters instead of playing tracks in order",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            ENABLED_OPTION_NAME,
+            "Take turns between requesters",
+          )
+          .required(true),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "limits",
+          "Limit what can be queued, 0 removes a limit",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            QUEUE_LENGTH_OPTION_NAME,
+            "Maximum number of tracks in the queue",
+          )
+          .min_int_value(0)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            PER_USER_OPTION_NAME,
+            "Maximum number of tracks one user can have in the queue",
+          )
+          .min_int_value(0)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            MAX_MINUTES_OPTION_NAME,
+            "Maximum track length in minutes",
+          )
+          .min_int_value(0)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            LIVE_OPTION_NAME,
+            "Allow live streams",
+          )
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            DUPLICATES_OPTION_NAME,
+            "Allow tracks that are already in the queue",
+          )
+          .required(false),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "normalize",
+          "Even out the volume of tracks, applies to tracks queued afterwards",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            ENABLED_OPTION_NAME,
+            "Normalize track loudness",
+          )
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Number,
+            TARGET_OPTION_NAME,
+            "Target loudness in LUFS, e.g. -14",
+          )
+          .min_number_value(MIN_TARGET)
+          .max_number_value(MAX_TARGET)
+          .required(false),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "transitions",
+          "Crossfade between tracks and load the next track early",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            CROSSFADE_OPTION_NAME,
+            "Seconds the end of a track overlaps the next one, 0 to disable",
+          )
+          .min_int_value(0)
+          .max_int_value(MAX_CROSSFADE_SECS)
+          .required(false),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Integer,
+            PRELOAD_OPTION_NAME,
+            "Seconds before the end of a track to start loading the next one",
+          )
+          .min_int_value(0)
+          .max_int_value(MAX_PRELOAD_SECS)
+          .required(false),
+        ),
+      )
+      .add_option(segments_subcommand())
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "dj",
+          "Set the DJ role, DJs can skip without a vote",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Role,
+            ROLE_OPTION_NAME,
+            "DJ role, removes the DJ role if left out",
+          )
+          .required(false),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "channels",
+          "Limit the text channels commands can be used in",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            ACTION_OPTION_NAME,
+            "Allow or disallow a channel, or allow every channel",
+          )
+          .add_string_choice("Allow", "add")
+          .add_string_choice("Disallow", "remove")
+          .add_string_choice("Allow every channel", "clear")
+          .required(true),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Channel,
+            CHANNEL_OPTION_NAME,
+            "Channel to allow or disallow",
+          )
+          .channel_types(vec![ChannelType::Text, ChannelType::Voice])
+          .required(false),
+        ),
+      )
+      .add_option(
+        CreateCommandOption::new(
+          CommandOptionType::SubCommand,
+          "command",
+          "Control who can use a command",
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            COMMAND_OPTION_NAME,
+            "Name of the command, e.g. stop",
+          )
+          .required(true),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::String,
+            ACTION_OPTION_NAME,
+            "What to change",
+          )
+          .add_string_choice("Allow a role", "allow")
+          .add_string_choice("Deny a role", "deny")
+          .add_string_choice("Remove a role from the lists", "unlist")
+          .add_string_choice("Only DJs", "djonly")
+          .add_string_choice("Not only DJs", "anyone")
+          .add_string_choice("Reset", "reset")
+          .required(true),
+        )
+        .add_sub_option(
+          CreateCommandOption::new(
+            CommandOptionType::Role,
+            ROLE_OPTION_NAME,
+            "Role to allow, deny or remove",
+          )
+          .required(false),
+        ),
+      )
+  }
+}
+
+fn set_vote_skip(settings: &mut GuildSettings, options: &[ResolvedOption<'_>]) {
+  if let Some(enabled) = get_bool_option(options, ENABLED_OPTION_NAME) {
+    settings.vote_skip = enabled;
+  }
+  if let Some(fraction) = get_number_option(options, FRACTION_OPTION_NAME) {
+    settings.skip_fraction = fraction.clamp(0.01, 1.0);
+  }
+}
+
+fn set_limits(settings: &mut GuildSettings, options: &[ResolvedOption<'_>]) {
+  let limit = |name| {
+    get_integer_option(options, name).map(|l| match l > 0 {
+      true => Some(l as usize),
+      false => None,
+    })
+  };
+  let limits = &mut settings.limits;
+
+  if let Some(l) = limit(QUEUE_LENGTH_OPTION_NAME) {
+    limits.max_queue_length = l;
+  }
+  if let Some(l) = limit(PER_USER_OPTION_NAME) {
+    limits.max_tracks_per_user = l;
+  }
+  if let Some(l) = limit(MAX_MINUTES_OPTION_NAME) {
+    limits.max_duration = l.map(|m| Duration::from_secs(m as u64 * 60));
+  }
+  if let Some(allow) = get_bool_option(options, LIVE_OPTION_NAME) {
+    limits.allow_live = allow;
+  }
+  if let Some(allow) = get_bool_option(options, DUPLICATES_OPTION_NAME) {
+    limits.allow_duplicates = allow;
+  }
+}
+
+fn set_segments(settings: &mut GuildSettings, options: &[ResolvedOption<'_>]) {
+  for category in Category::ALL {
+    match get_bool_option(options, category.id()) {
+      Some(true) if !settings.skip_segments.contains(&category) => {
+        settings.skip_segments.push(category)
+      }
+      Some(false) => settings.skip_segments.retain(|c| *c != category),
+      _ => (),
+    }
+  }
+}
+
+fn segments_subcommand() -> CreateCommandOption {
+  Category::ALL.into_iter().fold(
+    CreateCommandOption::new(
+      CommandOptionType::SubCommand,
+      "segments",
+      "Skip parts of YouTube tracks, like intros and non-music sections",
+    ),
+    |subcommand, category| {
+      subcommand.add_sub_option(
+        CreateCommandOption::new(
+          CommandOptionType::Boolean,
+          category.id(),
+          format!("Skip {} segments", category.name().to_lowercase()),
+        )
+        .required(false),
+      )
+    },
+  )
+}
+
+fn set_channels(
+  settings: &mut GuildSettings,
+  options: &[ResolvedOption<'_>],
+) -> Result<(), String> {
+  let channel = get_channel_option(options, CHANNEL_OPTION_NAME);
+  match (get_string_option(options, ACTION_OPTION_NAME), channel) {
+    (Some("clear"), _) => settings.allowed_channels.clear(),
+    (Some("add"), Some(c)) => {
+      if !settings.allowed_channels.contains(&c) {
+        settings.allowed_channels.push(c);
+      }
+    }
+    (Some("remove"), Some(c)) => settings.allowed_channels.retain(|a| *a != c),
+    (Some(_), None) => return Err("No channel in request".to_string()),
+    _ => return Err("Invalid action in request".to_string()),
+  }
+  Ok(())
+}
+
+fn set_command_rule(
+  settings: &mut GuildSettings,
+  options: &[ResolvedOption<'_>],
+) -> Result<(), String> {
+  let name = match get_string_option(options, COMMAND_OPTION_NAME) {
+    Some(n) => n.trim().trim_start_matches('/').to_lowercase(),
+    None => return Err("No command in request".to_string()),
+  };
+  if !command_names().contains(&name.as_str()) {
+    return Err(format!("No command named /{}", name));
+  }
+
+  let role = get_role_option(options, ROLE_OPTION_NAME);
+  let action = get_string_option(options, ACTION_OPTION_NAME);
+
+  if action == Some("reset") {
+    settings.command_rules.remove(&name);
+    return Ok(());
+  }
+
+  let rule = settings.command_rules.entry(name).or_default();
+  match (action, role) {
+    (Some("djonly"), _) => rule.dj_only = true,
+    (Some("anyone"), _) => rule.dj_only = false,
+    (Some("allow"), Some(r)) => {
+      rule.deny.retain(|d| *d != r);
+      if !rule.allow.contains(&r) {
+        rule.allow.push(r);
+      }
+    }
+    (Some("deny"), Some(r)) => {
+      rule.allow.retain(|a| *a != r);
+      if !rule.deny.contains(&r) {
+        rule.deny.push(r);
+      }
+    }
+    (Some("unlist"), Some(r)) => {
+      rule.allow.retain(|a| *a != r);
+      rule.deny.retain(|d| *d != r);
+    }
+    (Some(_), None) => return Err("No role in request".to_string()),
+    _ => return Err("Invalid action in request".to_string()),
+  }
+  Ok(())
+}
+
+fn format_limits(limits: &QueueLimits) -> String {
+  let limit = |l: Option<usize>| l.map_or("none".to_string(), |l| l.to_string());
+  format!(
+    "Queue length: {}\nPer user: {}\nTrack length: {}\nLive streams: {}\nDuplicates: {}",
+    limit(limits.max_queue_length),
+    limit(limits.max_tracks_per_user),
+    limits
+      .max_duration
+      .map_or("none".to_string(), format_duration),
+    if limits.allow_live {
+      "allowed"
+    } else {
+      "not allowed"
+    },
+    if limits.allow_duplicates {
+      "allowed"
+    } else {
+      "not allowed"
+    },
+  )
+}
+
+fn settings_embed(settings: &GuildSettings) -> CreateEmbed {
+  let mentions = |ids: Vec<String>| match ids.is_empty() {
+    true => "-".to_string(),
+    false => ids.join(" "),
+  };
+
+  let mut rules = settings
+    .command_rules
+    .iter()
+    .map(|(name, rule)| {
+      let mut parts = vec![];
+      if rule.dj_only {
+        parts.push("DJs only".to_string());
+      }
+      if !rule.allow.is_empty() {
+        parts.push(format!(
+          "allow {}",
+          mentions(rule.allow.iter().map(|r| format!("<@&{}>", r)).collect())
+        ));
+      }
+      if !rule.deny.is_empty() {
+        parts.push(format!(
+          "deny {}",
+          mentions(rule.deny.iter().map(|r| format!("<@&{}>", r)).collect())
+        ));
+      }
+      format!("/{}: {}", name, parts.join(", "))
+    })
+    .collect::<Vec<_>>();
+  rules.sort();
+
+  CreateEmbed::new().colour(EMBED_COLOUR).fields(vec![
+    (
+      "Vote skip",
+      match settings.vote_skip {
+        true => format!("On, {:.0}% of listeners", settings.skip_fraction * 100.0),
+        false => "Off".to_string(),
+      },
+      true,
+    ),
+    (
+      "Fair share queue",
+      match settings.fair_share {
+        true => "On".to_string(),
+        false => "Off".to_string(),
+      },
+      true,
+    ),
+    (
+      "Autoplay",
+      match settings.autoplay {
+        true => "On".to_string(),
+        false => "Off".to_string(),
+      },
+      true,
+    ),
+    ("Queue limits", format_limits(&settings.limits), false),
+    (
+      "Loudness normalization",
+      match settings.normalize {
+        true => format!("On, {:.1} LUFS", settings.loudness_target),
+        false => "Off".to_string(),
+      },
+      true,
+    ),
+    (
+      "Transitions",
+      format!(
+        "Crossfade: {}\nPreload: {}",
+        match settings.crossfade.is_zero() {
+          true => "off".to_string(),
+          false => format_duration(settings.crossfade),
+        },
+        match settings.preload.is_zero() {
+          true => "off".to_string(),
+          false => format!("{} before the end", format_duration(settings.preload)),
+        },
+      ),
+      true,
+    ),
+    (
+      "Segment skipping",
+      match settings.skip_segments.is_empty() {
+        true => "Off".to_string(),
+        false => settings
+          .skip_segments
+          .iter()
+          .map(|c| c.name())
+          .collect::<Vec<_>>()
+          .join(", "),
+      },
+      true,
+    ),
+    (
+      "DJ role",
+      settings
+        .dj_role
+        .map_or("-".to_string(), |r| format!("<@&{}>", r)),
+      true,
+    ),
+    (
+      "Command channels",
+      match settings.allowed_channels.is_empty() {
+        true => "Any".to_string(),
+        false => mentions(
+          settings
+            .allowed_channels
+            .iter()
+            .map(|c| format!("<#{}>", c))
+            .collect(),
+        ),
+      },
+      true,
+    ),
+    (
+      "Command permissions",
+      match rules.is_empty() {
+        true => "-".to_string(),
+        false => rules.join("\n"),
+      },
+      false,
+    ),
+  ])
+}
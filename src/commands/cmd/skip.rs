@@ -1,20 +1,40 @@
 use crate::commands::{
-  playback::{format_duration_live, SongMetadata, VOIPData},
-  text_response, Command,
+  permissions::is_dj,
+  playback::{format_duration_live, Requester, SongMetadata, VOIPData},
+  text_response,
+  utils::remove_md_characters,
+  Command,
 };
 use crate::constants::EMBED_COLOUR;
-use serenity::builder::CreateCommand;
+use crate::settings::guild_settings;
+use serenity::builder::{CreateCommand, CreateEmbedFooter, EditMessage};
 use serenity::client::Context;
 use serenity::model::application::CommandInteraction;
+use serenity::model::id::{ChannelId, MessageId, UserId};
 use serenity::Error;
 use serenity::{
   async_trait,
   builder::{CreateEmbed, EditInteractionResponse},
 };
+use songbird::{tracks::TrackHandle, typemap::TypeMapKey};
+use std::collections::HashSet;
 use tracing::error;
 
 pub struct Skip;
 
+/// Votes to skip a track, kept in the track's typemap so they reset with every new track
+#[derive(Default)]
+struct SkipVotes {
+  voters: HashSet<UserId>,
+  message: Option<(ChannelId, MessageId)>,
+}
+
+struct SkipVotesKey;
+
+impl TypeMapKey for SkipVotesKey {
+  type Value = SkipVotes;
+}
+
 #[async_trait]
 impl Command for Skip {
   async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), Error> {
@@ -44,25 +64,101 @@ impl Command for Skip {
       None => return text_response(ctx, command, "Not in a voice channel").await,
     };
 
-    let handler = handler_lock.lock().await;
+    // The queue is shared with the call, the lock isn't kept while messages are edited
+    let queue = handler_lock.lock().await.queue().clone();
 
-    if !handler.queue().is_empty() {
-      let current = match handler.queue().current() {
+    if !queue.is_empty() {
+      let current = match queue.current() {
         Some(t) => t,
         None => return text_response(ctx, command, "Nothing to skip").await,
       };
 
-      match handler.queue().skip() {
+      let metadata = SongMetadata::from_handle(&current).await;
+      let requester = Requester::from_handle(&current).await;
+
+      let settings = guild_settings(ctx, guild_id).await;
+      let is_requester = requester.as_ref().is_some_and(|r| r.id == command.user.id);
+
+      if settings.vote_skip && !is_requester && !is_dj(command.member.as_deref(), &settings) {
+        let listeners = voip_data.listeners(ctx);
+        let required = ((listeners.len() as f64 * settings.skip_fraction).ceil() as usize).max(1);
+
+        let (votes, message) = add_vote(&current, command.user.id, &listeners).await;
+
+        if votes < required {
+          let embed = vote_embed(&metadata, votes, required);
+          if let Some((channel_id, message_id)) = message {
+            if let Err(e) = channel_id
+              .edit_message(
+                &ctx.http,
+                message_id,
+                EditMessage::new().embed(embed.clone()),
+              )
+              .await
+            {
+              error!("Error updating skip vote message: {}", e);
+            }
+          }
+
+          return match command
+            .edit_response(&ctx.http, EditInteractionResponse::new().embed(embed))
+            .await
+          {
+            Ok(m) => {
+              if message.is_none() {
+                let mut data = current.typemap().write().await;
+                if let Some(v) = data.get_mut::<SkipVotesKey>() {
+                  v.message = Some((m.channel_id, m.id));
+                }
+              }
+              Ok(())
+            }
+            Err(e) => Err(e),
+          };
+        }
+
+        if let Some((channel_id, message_id)) = message {
+          if let Err(e) = channel_id
+            .edit_message(
+              &ctx.http,
+              message_id,
+              EditMessage::new().embed(
+                CreateEmbed::new()
+                  .title("Vote to skip passed")
+                  .colour(EMBED_COLOUR)
+                  .fields(vec![(
+                    "Track",
+                    remove_md_characters(metadata.title.clone()),
+                    true,
+                  )]),
+              ),
+            )
+            .await
+          {
+            error!("Error updating skip vote message: {}", e);
+          }
+        }
+      }
+
+      // The track can have ended while the messages were edited
+      if queue.current().map(|t| t.uuid()) != Some(current.uuid()) {
+        return text_response(ctx, command, "Nothing to skip").await;
+      }
+      match queue.skip() {
         Err(e) => {
           error!("Error skipping track: {}", e);
           text_response(ctx, command, "Nothing to skip").await
         }
         Ok(_) => {
-          let metadata = SongMetadata::from_handle(&current).await;
           let title = metadata.title.clone();
 
           let length = format_duration_live(metadata.duration, &title);
 
+          let mut fields = vec![("Track", title, true), ("Length", length.to_string(), true)];
+          if let Some(requester) = requester {
+            fields.push(("Requested by", remove_md_characters(requester.name), true));
+          }
+
           match command
             .edit_response(
               &ctx.http,
@@ -70,10 +166,7 @@ impl Command for Skip {
                 CreateEmbed::new()
                   .title("Skipped")
                   .colour(EMBED_COLOUR)
-                  .fields(vec![
-                    ("Track", title, true),
-                    ("Length", length.to_string(), true),
-                  ]),
+                  .fields(fields),
               ),
             )
             .await
@@ -96,3 +189,36 @@ impl Command for Skip {
     CreateCommand::new(Self::name()).description("Skip the currently playing song")
   }
 }
+
+/// Registers a vote and returns the number of votes from users still listening
+async fn add_vote(
+  track: &TrackHandle,
+  user_id: UserId,
+  listeners: &[UserId],
+) -> (usize, Option<(ChannelId, MessageId)>) {
+  let mut data = track.typemap().write().await;
+  if !data.contains_key::<SkipVotesKey>() {
+    data.insert::<SkipVotesKey>(SkipVotes::default());
+  }
+  let votes = data
+    .get_mut::<SkipVotesKey>()
+    .expect("Skip votes not found");
+
+  votes.voters.insert(user_id);
+  votes.voters.retain(|u| listeners.contains(u));
+
+  (votes.voters.len(), votes.message)
+}
+
+fn vote_embed(metadata: &SongMetadata, votes: usize, required: usize) -> CreateEmbed {
+  CreateEmbed::new()
+    .title("Vote to skip")
+    .colour(EMBED_COLOUR)
+    .fields(vec![
+      ("Track", remove_md_characters(metadata.title.clone()), true),
+      ("Votes", format!("{} / {}", votes, required), true),
+    ])
+    .footer(CreateEmbedFooter::new(
+      "Use /skip to vote, the requester or a DJ can skip right away",
+    ))
+}
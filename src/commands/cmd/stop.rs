@@ -27,7 +27,13 @@ impl Command for Stop {
     };
 
     let handler_lock = match manager.get(guild_id) {
-      Some(h) => h,
+      Some(h) => {
+        if voip_data.compare_to_call(&h).await {
+          h
+        } else {
+          return text_response(ctx, command, "You're not in the voice channel").await;
+        }
+      }
       None => return text_response(ctx, command, "Not in a voice channel").await,
     };
 
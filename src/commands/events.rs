@@ -0,0 +1,405 @@
+use crate::audio::{
+  cache::{download as download_audio, get_audio_cache},
+  filters::{current_filters, played_time, track_position},
+  gain::{track_gain, update_gain},
+  loudness::{LoudnessKey, LoudnessStorage},
+};
+use crate::commands::{
+  autoplay::autoplay,
+  playback::{
+    format_duration, format_duration_live, format_timestamp, get_queue_length_and_duration,
+    progress_bar, Requester, SongMetadata,
+  },
+  text_response, ticker,
+  utils::remove_md_characters,
+};
+use crate::constants::EMBED_COLOUR;
+use crate::history::{HistoryEntry, HistoryStorage};
+use crate::segments::segments_note;
+use crate::settings::guild_settings;
+use serenity::{
+  async_trait,
+  builder::{
+    CreateActionRow, CreateButton, CreateEmbed, CreateEmbedAuthor, CreateEmbedFooter, CreateMessage,
+  },
+  client::Context,
+  model::application::CommandInteraction,
+  model::id::{ChannelId, GuildId},
+};
+use songbird::{
+  events::Event,
+  tracks::{PlayMode, TrackHandle, TrackQueue, TrackResult},
+  EventContext, EventHandler,
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tracing::error;
+
+pub struct SongStart {
+  pub channel_id: ChannelId,
+  pub guild_id: GuildId,
+  pub ctx: Context,
+}
+
+#[async_trait]
+impl EventHandler for SongStart {
+  async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+    let handle = if let EventContext::Track(track_ctx) = ctx {
+      let (_state, handle) = track_ctx[0];
+      handle
+    } else {
+      return Some(Event::Cancel);
+    };
+
+    let message = playing_message(&self.ctx, self.guild_id, "Playing", handle).await;
+
+    match self.channel_id.send_message(&self.ctx.http, message).await {
+      Ok(m) => {
+        ticker::watch_now_playing(&self.ctx, self.guild_id, m, handle.clone(), "Playing").await;
+        return None;
+      }
+      Err(e) => {
+        error!("{}", e);
+        return None;
+      }
+    }
+  }
+}
+
+/// Announcement for a track that started playing on its own
+pub async fn playing_message(
+  ctx: &Context,
+  guild_id: GuildId,
+  title: &str,
+  handle: &TrackHandle,
+) -> CreateMessage {
+  let url = SongMetadata::from_handle(handle)
+    .await
+    .url
+    .unwrap_or_default();
+
+  CreateMessage::new()
+    .embed(playing_embed(ctx, guild_id, title, handle).await)
+    .components(vec![CreateActionRow::Buttons(vec![
+      CreateButton::new_link(url).label("Open in browser"),
+    ])])
+}
+
+/// The track and how far into it playback is
+pub async fn playing_embed(
+  ctx: &Context,
+  guild_id: GuildId,
+  title: &str,
+  handle: &TrackHandle,
+) -> CreateEmbed {
+  let metadata = SongMetadata::from_handle(handle).await;
+  let requester = Requester::from_handle(handle).await;
+  let queue = match songbird::get(ctx).await.and_then(|m| m.get(guild_id)) {
+    Some(h) => h.lock().await.queue().current_queue(),
+    None => {
+      error!("Error locking guild voice client");
+      vec![]
+    }
+  };
+  let (count, duration) = get_queue_length_and_duration(&queue).await;
+  let filters = current_filters(ctx, guild_id).await;
+  let position = track_position(ctx, guild_id, handle).await;
+  let live = bool::from(format_duration_live(metadata.duration, &metadata.title));
+
+  let mut embed = CreateEmbed::new()
+    .title(title)
+    .colour(EMBED_COLOUR)
+    .image(metadata.thumbnail.clone())
+    .fields(vec![
+      ("Track", remove_md_characters(metadata.title.clone()), true),
+      (
+        "Duration",
+        format_duration_live(metadata.duration, &metadata.title).to_string(),
+        true,
+      ),
+    ])
+    .footer(CreateEmbedFooter::new(format!(
+      "{} songs in queue - {}",
+      count,
+      format_duration(duration)
+    )));
+  if !live && !metadata.duration.is_zero() {
+    embed = embed.field(
+      "Progress",
+      format!(
+        "`{}` {} `{}`",
+        format_timestamp(position),
+        progress_bar(position, metadata.duration),
+        format_timestamp(metadata.duration)
+      ),
+      false,
+    );
+  }
+  if let Some(chapter) = metadata.chapter_at(position) {
+    embed = embed.description(remove_md_characters(metadata.format_chapter(chapter)));
+  }
+  if filters.is_active() {
+    embed = embed.field("Filters", filters.to_string(), false);
+  }
+  if let Some(note) = segments_note(handle).await {
+    embed = embed.field("Skipping", note, false);
+  }
+  if let Some(requester) = requester {
+    embed = embed.author(
+      CreateEmbedAuthor::new(remove_md_characters(requester.name)).icon_url(requester.avatar),
+    );
+  }
+  embed
+}
+
+pub struct SongEnd {
+  pub guild_id: GuildId,
+  pub ctx: Context,
+  pub command: CommandInteraction,
+}
+
+#[async_trait]
+impl EventHandler for SongEnd {
+  async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+    let (state, handle) = if let EventContext::Track(track_ctx) = ctx {
+      track_ctx[0]
+    } else {
+      return Some(Event::Cancel);
+    };
+
+    // Tracks cleared from the queue before they ever started also end
+    if state.play_time.is_zero() {
+      return None;
+    }
+
+    let metadata = SongMetadata::from_handle(handle).await;
+    let measurement = {
+      let data = handle.typemap().read().await;
+      data.get::<LoudnessKey>().map(|l| match l.lock() {
+        Ok(m) => *m,
+        Err(e) => *e.into_inner(),
+      })
+    };
+    if let (Some(url), Some(loudness)) = (
+      &metadata.url,
+      measurement.filter(|m| m.complete).and_then(|m| m.loudness),
+    ) {
+      let cache_lock = {
+        let data = self.ctx.data.read().await;
+        data
+          .get::<LoudnessStorage>()
+          .cloned()
+          .expect("LoudnessStorage did not exist")
+      };
+      let mut cache = cache_lock.write().await;
+      cache.insert(url.clone(), loudness);
+      cache.save();
+    }
+
+    let entry = HistoryEntry {
+      metadata,
+      requester: Requester::from_handle(handle).await,
+      played_at: chrono::Utc::now().timestamp(),
+    };
+
+    let history_lock = {
+      let data = self.ctx.data.read().await;
+      data
+        .get::<HistoryStorage>()
+        .cloned()
+        .expect("HistoryStorage did not exist")
+    };
+    {
+      let mut history = history_lock.write().await;
+      history.push(self.guild_id, entry.clone());
+      history.save();
+    }
+
+    // Skipping or stopping also ends the track, only running out starts autoplay
+    if matches!(state.playing, PlayMode::End)
+      && guild_settings(&self.ctx, self.guild_id).await.autoplay
+    {
+      let queue = match songbird::get(&self.ctx)
+        .await
+        .and_then(|m| m.get(self.guild_id))
+      {
+        Some(h) => h.lock().await.queue().current_queue(),
+        None => return None,
+      };
+      if queue.iter().all(|h| h.uuid() == handle.uuid()) {
+        let (ctx, command, guild_id) = (self.ctx.clone(), self.command.clone(), self.guild_id);
+        tokio::spawn(async move {
+          autoplay(&ctx, &command, guild_id, &entry.metadata).await;
+        });
+      }
+    }
+
+    None
+  }
+}
+
+/// Preloads the next track and crossfades into it as the current one ends
+pub struct Transition {
+  guild_id: GuildId,
+  ctx: Context,
+  preloaded: AtomicBool,
+}
+
+impl Transition {
+  pub fn new(guild_id: GuildId, ctx: Context) -> Self {
+    Self {
+      guild_id,
+      ctx,
+      preloaded: AtomicBool::new(false),
+    }
+  }
+}
+
+#[async_trait]
+impl EventHandler for Transition {
+  async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+    let (state, handle) = if let EventContext::Track(track_ctx) = ctx {
+      track_ctx[0]
+    } else {
+      return Some(Event::Cancel);
+    };
+
+    let metadata = SongMetadata::from_handle(handle).await;
+    if metadata.duration.is_zero()
+      || bool::from(format_duration_live(metadata.duration, &metadata.title))
+    {
+      return Some(Event::Cancel);
+    }
+
+    let queue = match songbird::get(&self.ctx)
+      .await
+      .and_then(|m| m.get(self.guild_id))
+    {
+      Some(h) => h.lock().await.queue().current_queue(),
+      None => return Some(Event::Cancel),
+    };
+    // The next track runs its own events while fading in, only the one in front leads
+    if queue.first().map(|h| h.uuid()) != Some(handle.uuid()) {
+      return None;
+    }
+
+    let settings = guild_settings(&self.ctx, self.guild_id).await;
+    let remaining = played_time(&self.ctx, self.guild_id, metadata.duration)
+      .await
+      .saturating_sub(state.position);
+
+    let fading = !settings.crossfade.is_zero() && remaining < settings.crossfade;
+    let fade = match fading {
+      true => remaining.as_secs_f32() / settings.crossfade.as_secs_f32(),
+      false => 1.0,
+    };
+    if let Err(e) = update_gain(handle, &track_gain(handle).await, |g| g.fade = fade) {
+      error!("Error fading track: {}", e);
+      return Some(Event::Cancel);
+    }
+
+    let next = queue.get(1)?;
+
+    if remaining < settings.preload.max(settings.crossfade)
+      && !self.preloaded.swap(true, Ordering::Relaxed)
+    {
+      drop(next.make_playable());
+    }
+
+    let next_gain = track_gain(next).await;
+    let next_state = match next.get_info().await {
+      Ok(s) => s,
+      Err(_) => return None,
+    };
+    if fading {
+      if let Err(e) = update_gain(next, &next_gain, |g| g.fade = 1.0 - fade) {
+        error!("Error fading in next track: {}", e);
+      }
+      if matches!(next_state.playing, PlayMode::Pause) {
+        if let Err(e) = next.play() {
+          error!("Error starting next track: {}", e);
+        }
+      }
+    } else if matches!(next_state.playing, PlayMode::Play) {
+      // Seeking back cut the crossfade short, the next track waits for its turn again
+      next.pause().unwrap_or(());
+      drop(next.seek(Duration::ZERO));
+      update_gain(next, &next_gain, |g| g.fade = 1.0).unwrap_or(());
+    }
+
+    None
+  }
+}
+
+/// The next track, if a crossfade into it has started
+async fn fading_in(queue: &TrackQueue) -> Option<TrackHandle> {
+  let next = queue.current_queue().get(1)?.clone();
+  let fade = {
+    let gain = track_gain(&next).await;
+    let gain = match gain.lock() {
+      Ok(g) => g,
+      Err(e) => e.into_inner(),
+    };
+    gain.fade
+  };
+  (fade < 1.0).then_some(next)
+}
+
+/// Pauses the current track along with the one fading in after it, which is already playing
+pub async fn pause_playback(queue: &TrackQueue) -> TrackResult<()> {
+  if let Some(current) = queue.current() {
+    current.pause()?;
+  }
+  match fading_in(queue).await {
+    Some(next) => next.pause(),
+    None => Ok(()),
+  }
+}
+
+/// Resumes the current track along with the one fading in after it
+pub async fn resume_playback(queue: &TrackQueue) -> TrackResult<()> {
+  if let Some(current) = queue.current() {
+    current.play()?;
+  }
+  match fading_in(queue).await {
+    Some(next) => next.play(),
+    None => Ok(()),
+  }
+}
+
+pub struct SongError {
+  pub command: CommandInteraction,
+  pub ctx: Context,
+}
+
+#[async_trait]
+impl EventHandler for SongError {
+  async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+    match text_response(&self.ctx, &self.command, "Error playing song").await {
+      Ok(_) => None,
+      Err(e) => {
+        error!("Failed editing error response: {}", e);
+        None
+      }
+    }
+  }
+}
+
+/// Counts a play towards caching the track's audio, once it actually starts
+pub struct CountPlay {
+  pub url: String,
+  pub ctx: Context,
+}
+
+#[async_trait]
+impl EventHandler for CountPlay {
+  async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+    let cache = get_audio_cache(&self.ctx).await;
+    let download = cache.write().await.record_play(&self.url);
+    if download {
+      tokio::spawn(download_audio(cache, self.url.clone()));
+    }
+    // Resuming fires the event again
+    Some(Event::Cancel)
+  }
+}
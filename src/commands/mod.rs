@@ -1,19 +1,31 @@
 use crate::config::ConfigStorage;
 use crate::constants::EMBED_COLOUR;
+use crate::settings::guild_settings;
 use serenity::builder::CreateEmbed;
+use serenity::builder::CreateInteractionResponse;
 use serenity::builder::CreateInteractionResponseMessage;
 use serenity::builder::EditInteractionResponse;
-use serenity::model::application::CommandInteraction;
+use serenity::model::application::{CommandInteraction, ComponentInteraction};
 use serenity::model::prelude::Ready;
 use serenity::prelude::Context;
 use serenity::Error;
 use serenity::{async_trait, builder::CreateCommand};
+use std::future::Future;
+use std::pin::Pin;
 use tracing::{error, info};
 
+mod autoplay;
 mod cmd;
+mod events;
+mod permissions;
 mod playback;
+mod queue_file;
+mod resolve;
+pub mod ticker;
 mod utils;
 
+pub use playback::{format_duration, Requester, SongMetadata};
+
 static COMMAND_TIMEOUT: tokio::time::Duration = tokio::time::Duration::from_secs(10);
 
 #[async_trait]
@@ -51,33 +63,45 @@ pub async fn register_commands(ctx: &Context, _ready: &Ready) {
   }
 }
 
-fn command_list() -> Vec<CreateCommand> {
-  vec![
-    cmd::Join::info(),
-    cmd::Leave::info(),
-    cmd::Play::info(),
-    cmd::Capybara::info(),
-    cmd::Seek::info(),
-    cmd::Skip::info(),
-    cmd::Queue::info(),
-    cmd::Me::info(),
-    cmd::Info::info(),
-    cmd::Stop::info(),
-    cmd::Eval::info(),
-    cmd::Pause::info(),
-    cmd::Resume::info(),
-  ]
+/// Generates the registration list, the names and the dispatch from one list of commands in the
+/// order they're registered in
+macro_rules! commands {
+  ($($command:ident),* $(,)?) => {
+    fn command_list() -> Vec<CreateCommand> {
+      vec![$(cmd::$command::info()),*]
+    }
+
+    pub fn command_names() -> Vec<&'static str> {
+      vec![$(cmd::$command::name()),*]
+    }
+
+    /// Runs the command the interaction is for, `None` if there's no such command
+    fn execute<'a>(ctx: &'a Context, command: &'a CommandInteraction) -> Option<CommandFuture<'a>> {
+      let name = command.data.name.as_str();
+      $(
+        if name == cmd::$command::name() {
+          return Some(cmd::$command::execute(ctx, command));
+        }
+      )*
+      None
+    }
+  };
 }
 
+type CommandFuture<'a> = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>>;
+
+commands!(
+  Join, Leave, Play, Capybara, Seek, Skip, Queue, Me, Info, Stop, Eval, Pause, Resume, Playlist,
+  History, Settings, Filter, Cache, Autoplay, Chapter, Lyrics, NowPlaying, Replay, Requeue,
+);
+
 pub async fn handle_commands(ctx: &Context, command: CommandInteraction) {
   let name = command.data.name.clone();
   let user = command.user.clone();
   match command
     .create_response(
       &ctx.http,
-      serenity::builder::CreateInteractionResponse::Defer(
-        CreateInteractionResponseMessage::new().content("Loading"),
-      ),
+      CreateInteractionResponse::Defer(CreateInteractionResponseMessage::new().content("Loading")),
     )
     .await
   {
@@ -85,21 +109,22 @@ pub async fn handle_commands(ctx: &Context, command: CommandInteraction) {
     Err(e) => error!("Error deferring command {}: {}", name, e),
   }
 
-  let result = match name.as_str() {
-    _ if name == cmd::Join::name() => cmd::Join::execute(ctx, &command),
-    _ if name == cmd::Leave::name() => cmd::Leave::execute(ctx, &command),
-    _ if name == cmd::Play::name() => cmd::Play::execute(ctx, &command),
-    _ if name == cmd::Seek::name() => cmd::Seek::execute(ctx, &command),
-    _ if name == cmd::Skip::name() => cmd::Skip::execute(ctx, &command),
-    _ if name == cmd::Queue::name() => cmd::Queue::execute(ctx, &command),
-    _ if name == cmd::Stop::name() => cmd::Stop::execute(ctx, &command),
-    _ if name == cmd::Capybara::name() => cmd::Capybara::execute(ctx, &command),
-    _ if name == cmd::Me::name() => cmd::Me::execute(ctx, &command),
-    _ if name == cmd::Info::name() => cmd::Info::execute(ctx, &command),
-    _ if name == cmd::Eval::name() => cmd::Eval::execute(ctx, &command),
-    _ if name == cmd::Pause::name() => cmd::Pause::execute(ctx, &command),
-    _ if name == cmd::Resume::name() => cmd::Resume::execute(ctx, &command),
-    _ => Box::pin(text_response(ctx, &command, "Invalid command")),
+  if let Some(guild_id) = command.guild_id {
+    let settings = guild_settings(ctx, guild_id).await;
+    if let Err(e) = permissions::check_permissions(ctx, &command, &settings) {
+      info!(
+        "{user} was denied running command {cmd}",
+        user = user.tag(),
+        cmd = name
+      );
+      text_response(ctx, &command, e).await.unwrap_or(());
+      return;
+    }
+  }
+
+  let result = match execute(ctx, &command) {
+    Some(r) => r,
+    None => Box::pin(text_response(ctx, &command, "Invalid command")),
   };
 
   match tokio::time::timeout(COMMAND_TIMEOUT, result).await {
@@ -132,6 +157,75 @@ pub async fn handle_commands(ctx: &Context, command: CommandInteraction) {
   }
 }
 
+pub async fn handle_autocomplete(ctx: &Context, command: CommandInteraction) {
+  let name = command.data.name.as_str();
+  let result = match name {
+    _ if name == cmd::Play::name() => cmd::Play::autocomplete(ctx, &command).await,
+    _ => Ok(()),
+  };
+
+  if let Err(e) = result {
+    error!("Couldn't respond to autocomplete for {}: {}", name, e);
+  }
+}
+
+pub async fn handle_components(ctx: &Context, component: ComponentInteraction) {
+  let id = component.data.custom_id.as_str();
+  let name = match id {
+    _ if cmd::NowPlaying::handles(id) => cmd::NowPlaying::command_name(id),
+    _ if cmd::Eval::handles(id) => cmd::Eval::name(),
+    _ => return,
+  };
+
+  if let Some(guild_id) = component.guild_id {
+    let settings = guild_settings(ctx, guild_id).await;
+    if let Err(e) = permissions::check_rules(
+      ctx,
+      name,
+      component.guild_id,
+      component.channel_id,
+      component.member.as_ref(),
+      &settings,
+    ) {
+      info!("{} was denied pressing {}", component.user.tag(), id);
+      ephemeral_response(ctx, &component, e).await.unwrap_or(());
+      return;
+    }
+  }
+
+  let result = match id {
+    _ if cmd::NowPlaying::handles(id) => cmd::NowPlaying::component(ctx, &component).await,
+    _ if cmd::Eval::handles(id) => cmd::Eval::component(ctx, &component).await,
+    _ => Ok(()),
+  };
+
+  match result {
+    Ok(_) => info!("{} pressed {}", component.user.tag(), id),
+    Err(e) => error!("Couldn't respond to {}: {}", id, e),
+  }
+}
+
+/// Replies to a button press with a message only the presser sees
+pub async fn ephemeral_response<D>(
+  ctx: &Context,
+  component: &ComponentInteraction,
+  text: D,
+) -> Result<(), Error>
+where
+  std::string::String: From<D>,
+{
+  component
+    .create_response(
+      &ctx.http,
+      CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+          .embed(CreateEmbed::new().title(text).colour(EMBED_COLOUR))
+          .ephemeral(true),
+      ),
+    )
+    .await
+}
+
 pub async fn text_response<D>(
   ctx: &Context,
   command: &CommandInteraction,
@@ -0,0 +1,93 @@
+use crate::settings::GuildSettings;
+use serenity::client::Context;
+use serenity::model::application::CommandInteraction;
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId};
+
+/// Members who can manage the server or its channels are always DJs
+pub fn is_dj(member: Option<&Member>, settings: &GuildSettings) -> bool {
+  let member = match member {
+    Some(m) => m,
+    None => return false,
+  };
+
+  let has_permissions = member
+    .permissions
+    .is_some_and(|p| p.administrator() || p.manage_guild() || p.manage_channels());
+  let has_role = settings
+    .dj_role
+    .is_some_and(|role| member.roles.contains(&role));
+
+  has_permissions || has_role
+}
+
+fn is_admin(member: Option<&Member>) -> bool {
+  member
+    .and_then(|m| m.permissions)
+    .is_some_and(|p| p.administrator() || p.manage_guild())
+}
+
+/// Checks the guild's permission policy, admins bypass it so they can't lock themselves out
+pub fn check_permissions(
+  ctx: &Context,
+  command: &CommandInteraction,
+  settings: &GuildSettings,
+) -> Result<(), String> {
+  check_rules(
+    ctx,
+    &command.data.name,
+    command.guild_id,
+    command.channel_id,
+    command.member.as_deref(),
+    settings,
+  )
+}
+
+/// Same policy for anything that does what the command `name` does, like buttons
+pub fn check_rules(
+  ctx: &Context,
+  name: &str,
+  guild_id: Option<GuildId>,
+  channel_id: ChannelId,
+  member: Option<&Member>,
+  settings: &GuildSettings,
+) -> Result<(), String> {
+  let guild_id = match guild_id {
+    Some(g) => g,
+    None => return Ok(()),
+  };
+
+  if is_admin(member) {
+    return Ok(());
+  }
+
+  if !settings.allowed_channels.is_empty() && !settings.allowed_channels.contains(&channel_id) {
+    let channels = match guild_id.to_guild_cached(&ctx.cache) {
+      Some(guild) => settings
+        .allowed_channels
+        .iter()
+        .filter_map(|c| guild.channels.get(c))
+        .map(|c| format!("#{}", c.name))
+        .collect::<Vec<_>>()
+        .join(", "),
+      None => "the allowed channels".to_string(),
+    };
+    return Err(format!("Commands can only be used in {}", channels));
+  }
+
+  let rule = match settings.command_rules.get(name) {
+    Some(r) => r,
+    None => return Ok(()),
+  };
+
+  let roles = member.map(|m| m.roles.as_slice()).unwrap_or_default();
+  let denied = rule.deny.iter().any(|r| roles.contains(r));
+  let allowed = rule.allow.is_empty() || rule.allow.iter().any(|r| roles.contains(r));
+  let dj = is_dj(member, settings);
+
+  if denied || !(allowed || dj) || (rule.dj_only && !dj) {
+    Err(format!("You don't have permission to use /{}", name))
+  } else {
+    Ok(())
+  }
+}
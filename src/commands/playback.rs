@@ -1,18 +1,37 @@
-use crate::constants::placeholder_img;
+use crate::audio::{
+  filters::guild_filters,
+  gain::{update_gain, GainKey, SharedGain},
+  loudness::{normalized_volume, LoudnessKey, LoudnessStorage, NormalizeVolume, SharedLoudness},
+  Filtered,
+};
+use crate::commands::events::{CountPlay, SongEnd, SongError, SongStart, Transition};
+use crate::config::ConfigStorage;
+use crate::constants::HttpKey;
+use crate::segments::{youtube_id, SegmentsKey, SkipSegments, TrackSegments};
+use crate::settings::{guild_settings, GuildSettings, QueueLimits};
 use regex::Regex;
+use serde::{Deserialize, Serialize};
 use serenity::client::Context;
 use serenity::model::application::CommandInteraction;
-use serenity::model::id::ChannelId;
+use serenity::model::id::{ChannelId, UserId};
 use serenity::model::prelude::GuildId;
 use serenity::prelude::Mutex;
 use songbird::{
-  input::{Compose, YoutubeDl},
-  tracks::TrackHandle,
+  events::{Event, EventData},
+  input::{Compose, Input, YoutubeDl},
+  tracks::{Track, TrackHandle},
   typemap::TypeMapKey,
+  Call, Songbird, TrackEvent,
 };
-use std::{sync::Arc, time::Duration};
+use std::{collections::HashMap, sync::Arc, time::Duration};
 use tracing::error;
 
+/// How often fades are updated during crossfades
+const TRANSITION_INTERVAL: Duration = Duration::from_millis(200);
+const PROGRESS_BAR_LENGTH: usize = 16;
+/// How often the position is checked against segments to skip
+const SEGMENT_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
 pub struct VOIPData {
   pub channel_id: ChannelId,
   pub guild_id: GuildId,
@@ -20,8 +39,16 @@ pub struct VOIPData {
 
 impl VOIPData {
   pub async fn from(ctx: &Context, command: &CommandInteraction) -> Result<VOIPData, String> {
-    let guild_from_command = command.guild_id;
-    let guild_id = match guild_from_command {
+    VOIPData::from_user(ctx, command.guild_id, command.user.id)
+  }
+
+  /// Voice channel of a user who interacted with something other than a command
+  pub fn from_user(
+    ctx: &Context,
+    guild_id: Option<GuildId>,
+    user_id: UserId,
+  ) -> Result<VOIPData, String> {
+    let guild_id = match guild_id {
       Some(g_id) => g_id,
       None => {
         error!("Error getting guild from command");
@@ -35,7 +62,7 @@ impl VOIPData {
       Some(guild) => {
         let ch = guild
           .voice_states
-          .get(&command.member.as_ref().unwrap().user.id.clone())
+          .get(&user_id)
           .and_then(|vs| vs.channel_id);
 
         match ch {
@@ -59,6 +86,27 @@ impl VOIPData {
     Ok(data)
   }
 
+  /// Users in the voice channel, not counting bots
+  pub fn listeners(&self, ctx: &Context) -> Vec<UserId> {
+    match self.guild_id.to_guild_cached(&ctx.cache) {
+      Some(guild) => guild
+        .voice_states
+        .values()
+        .filter(|vs| vs.channel_id == Some(self.channel_id))
+        .filter(|vs| {
+          !vs
+            .member
+            .as_ref()
+            .map(|m| m.user.bot)
+            .or_else(|| ctx.cache.user(vs.user_id).map(|u| u.bot))
+            .unwrap_or(false)
+        })
+        .map(|vs| vs.user_id)
+        .collect(),
+      None => vec![],
+    }
+  }
+
   pub async fn compare_to_call(&self, call: &Arc<Mutex<songbird::Call>>) -> bool {
     call
       .lock()
@@ -69,12 +117,22 @@ impl VOIPData {
   }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SongMetadata {
   pub title: String,
   pub thumbnail: String,
   pub duration: Duration,
   pub url: Option<String>,
+  pub query: String,
+  #[serde(default)]
+  pub chapters: Vec<Chapter>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Chapter {
+  pub title: String,
+  pub start: Duration,
+  pub end: Duration,
 }
 
 pub struct SongMetadataKey;
@@ -84,42 +142,62 @@ impl TypeMapKey for SongMetadataKey {
 }
 
 impl SongMetadata {
-  pub async fn from_source(source: &mut YoutubeDl) -> Self {
-    let metadata = match source.aux_metadata().await {
-      Ok(m) => m,
-      Err(e) => {
-        error!("Error getting metadata: {}", e);
-        return Self {
-          title: "N/A".to_string(),
-          thumbnail: placeholder_img(),
-          duration: Duration::default(),
-          url: None,
-        };
-      }
-    };
+  pub async fn from_handle(handle: &TrackHandle) -> SongMetadata {
+    let data = handle.typemap().read().await;
+    data
+      .get::<SongMetadataKey>()
+      .expect("Metadata not found")
+      .clone()
+  }
 
-    let thumbnail = metadata.thumbnail.clone().unwrap_or_else(placeholder_img);
+  /// Index of the chapter playing at `position` in the track
+  pub fn chapter_at(&self, position: Duration) -> Option<usize> {
+    self.chapters.iter().rposition(|c| c.start <= position)
+  }
 
-    let title = metadata.title.clone().unwrap_or_else(|| "N/A".to_string());
+  pub fn format_chapter(&self, index: usize) -> String {
+    match self.chapters.get(index) {
+      Some(chapter) => format!(
+        "Chapter {}/{}: {}",
+        index + 1,
+        self.chapters.len(),
+        chapter.title
+      ),
+      None => "No chapter".to_string(),
+    }
+  }
+}
 
-    let duration = metadata.duration.unwrap_or_default();
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Requester {
+  pub id: UserId,
+  pub name: String,
+  pub avatar: String,
+}
+
+pub struct RequesterKey;
+
+impl TypeMapKey for RequesterKey {
+  type Value = Requester;
+}
 
-    let url = metadata.source_url.clone();
+impl Requester {
+  pub fn from_command(command: &CommandInteraction) -> Self {
+    let (name, avatar) = match &command.member {
+      Some(member) => (member.display_name().to_string(), member.face()),
+      None => (command.user.display_name().to_string(), command.user.face()),
+    };
 
     Self {
-      title,
-      thumbnail,
-      duration,
-      url,
+      id: command.user.id,
+      name,
+      avatar,
     }
   }
 
-  pub async fn from_handle(handle: &TrackHandle) -> SongMetadata {
+  pub async fn from_handle(handle: &TrackHandle) -> Option<Requester> {
     let data = handle.typemap().read().await;
-    data
-      .get::<SongMetadataKey>()
-      .expect("Metadata not found")
-      .clone()
+    data.get::<RequesterKey>().cloned()
   }
 }
 
@@ -131,6 +209,371 @@ pub fn get_source(client: crate::constants::HttpClient, param: String) -> Youtub
   }
 }
 
+pub fn get_stored_source(
+  client: crate::constants::HttpClient,
+  metadata: &SongMetadata,
+) -> YoutubeDl {
+  match &metadata.url {
+    Some(url) => YoutubeDl::new(client, url.clone()),
+    None => get_source(client, metadata.query.clone()),
+  }
+}
+
+pub async fn get_call(
+  manager: Arc<Songbird>,
+  voip_data: VOIPData,
+) -> Result<Arc<Mutex<Call>>, String> {
+  match manager.get(voip_data.guild_id) {
+    Some(h) => {
+      if voip_data.compare_to_call(&h).await {
+        Ok(h)
+      } else {
+        join_channel(manager, voip_data).await
+      }
+    }
+    None => join_channel(manager, voip_data).await,
+  }
+}
+
+async fn join_channel(
+  manager: Arc<Songbird>,
+  voip_data: VOIPData,
+) -> Result<Arc<Mutex<Call>>, String> {
+  let join = manager.join(voip_data.guild_id, voip_data.channel_id).await;
+  match join {
+    Ok(j) => Ok(j),
+    Err(e) => {
+      error!("Error joining voice channel: {}", e);
+      Err("Not in a voice channel".to_string())
+    }
+  }
+}
+
+pub async fn enqueue_song(
+  ctx: &Context,
+  command: &CommandInteraction,
+  handler: &mut Call,
+  source: Box<dyn Compose>,
+  metadata: SongMetadata,
+  requester: Requester,
+) -> Result<TrackHandle, String> {
+  enqueue(ctx, command, handler, source, metadata, requester, true).await
+}
+
+/// Enqueues a fresh copy of a song to replay it in its own place, without checking the queue
+/// limits against it a second time
+pub async fn enqueue_song_unchecked(
+  ctx: &Context,
+  command: &CommandInteraction,
+  handler: &mut Call,
+  source: Box<dyn Compose>,
+  metadata: SongMetadata,
+  requester: Requester,
+) -> Result<TrackHandle, String> {
+  enqueue(ctx, command, handler, source, metadata, requester, false).await
+}
+
+async fn enqueue(
+  ctx: &Context,
+  command: &CommandInteraction,
+  handler: &mut Call,
+  source: Box<dyn Compose>,
+  metadata: SongMetadata,
+  requester: Requester,
+  limits: bool,
+) -> Result<TrackHandle, String> {
+  let settings = match command.guild_id {
+    Some(guild_id) => guild_settings(ctx, guild_id).await,
+    None => GuildSettings::default(),
+  };
+
+  if limits {
+    check_limits(
+      &settings.limits,
+      &handler.queue().current_queue(),
+      &metadata,
+      &requester,
+    )
+    .await?;
+  }
+
+  let filters = match command.guild_id {
+    Some(guild_id) => guild_filters(ctx, guild_id).await,
+    None => Default::default(),
+  };
+  let cached_loudness = match (settings.normalize, &metadata.url) {
+    (true, Some(url)) => cached_loudness(ctx, url).await,
+    _ => None,
+  };
+  let loudness = match settings.normalize && cached_loudness.is_none() {
+    true => Some(SharedLoudness::default()),
+    false => None,
+  };
+  let input = Input::Lazy(Box::new(Filtered::new(source, filters, loudness.clone())));
+
+  let segments = track_segments(ctx, &settings, &metadata).await;
+
+  let mut track = Track::from(input);
+  // Added before queueing, the first track starts playing before later events are in place
+  if let Some(url) = &metadata.url {
+    track.events.add_event(
+      EventData::new(
+        Event::Track(TrackEvent::Play),
+        CountPlay {
+          url: url.clone(),
+          ctx: ctx.clone(),
+        },
+      ),
+      Duration::ZERO,
+    );
+  }
+
+  let handle = handler.enqueue(track).await;
+  let requester_id = requester.id;
+  let gain = SharedGain::default();
+  {
+    let mut data = handle.typemap().write().await;
+    data.insert::<GainKey>(gain.clone());
+    data.insert::<SongMetadataKey>(metadata);
+    data.insert::<RequesterKey>(requester);
+    if let Some(loudness) = &loudness {
+      data.insert::<LoudnessKey>(loudness.clone());
+    }
+    if let Some(segments) = &segments {
+      data.insert::<SegmentsKey>(segments.clone());
+    }
+  }
+
+  if let Some(l) = cached_loudness {
+    let volume = normalized_volume(l, settings.loudness_target);
+    if let Err(e) = update_gain(&handle, &gain, |g| g.normalization = volume) {
+      error!("Error setting track volume: {}", e);
+    }
+  }
+  if let Some(loudness) = loudness {
+    match handle.add_event(
+      Event::Periodic(Duration::from_secs(1), None),
+      NormalizeVolume {
+        loudness,
+        gain: gain.clone(),
+        target: settings.loudness_target,
+      },
+    ) {
+      Ok(_) => (),
+      Err(e) => error!("Error adding NormalizeVolume event: {}", e),
+    }
+  }
+  match handle.add_event(
+    Event::Track(TrackEvent::Error),
+    SongError {
+      ctx: ctx.clone(),
+      command: command.clone(),
+    },
+  ) {
+    Ok(_) => (),
+    Err(e) => error!("Error adding SongError event: {}", e),
+  }
+
+  if let Some(guild_id) = command.guild_id {
+    match handle.add_event(
+      Event::Track(TrackEvent::End),
+      SongEnd {
+        guild_id,
+        ctx: ctx.clone(),
+        command: command.clone(),
+      },
+    ) {
+      Ok(_) => (),
+      Err(e) => error!("Error adding SongEnd event: {}", e),
+    }
+
+    match handle.add_event(
+      Event::Periodic(TRANSITION_INTERVAL, None),
+      Transition::new(guild_id, ctx.clone()),
+    ) {
+      Ok(_) => (),
+      Err(e) => error!("Error adding Transition event: {}", e),
+    }
+
+    if let Some(segments) = segments {
+      match handle.add_event(
+        Event::Periodic(SEGMENT_CHECK_INTERVAL, None),
+        SkipSegments {
+          segments,
+          guild_id,
+          ctx: ctx.clone(),
+        },
+      ) {
+        Ok(_) => (),
+        Err(e) => error!("Error adding SkipSegments event: {}", e),
+      }
+    }
+  }
+
+  if handler.queue().len() > 1 {
+    if let Some(guild_id) = command.guild_id {
+      match handle.add_event(
+        Event::Track(TrackEvent::Play),
+        SongStart {
+          channel_id: command.channel_id,
+          guild_id,
+          ctx: ctx.clone(),
+        },
+      ) {
+        Ok(_) => (),
+        Err(e) => error!("Error adding SongStart event: {}", e),
+      }
+    }
+  }
+
+  if settings.fair_share {
+    let queue = handler.queue().current_queue();
+    let mut requesters = Vec::with_capacity(queue.len());
+    for h in &queue {
+      requesters.push(Requester::from_handle(h).await.map(|r| r.id));
+    }
+
+    let position = fair_share_position(&requesters[..requesters.len() - 1], requester_id);
+    if position < queue.len() - 1 {
+      handler.queue().modify_queue(|q| {
+        if let Some(track) = q.pop_back() {
+          q.insert(position, track);
+        }
+      });
+    }
+  }
+
+  Ok(handle)
+}
+
+/// Segments to skip in the track, if it's on YouTube and the guild skips any
+async fn track_segments(
+  ctx: &Context,
+  settings: &GuildSettings,
+  metadata: &SongMetadata,
+) -> Option<Arc<TrackSegments>> {
+  if settings.skip_segments.is_empty() {
+    return None;
+  }
+  let video_id = metadata.url.as_deref().and_then(youtube_id)?;
+
+  let (config, client) = {
+    let data = ctx.data.read().await;
+    (
+      data
+        .get::<ConfigStorage>()
+        .cloned()
+        .expect("No config in global storage"),
+      data
+        .get::<HttpKey>()
+        .cloned()
+        .expect("HttpClient did not exist"),
+    )
+  };
+  let api = config.segments_api.clone()?;
+
+  Some(Arc::new(TrackSegments::new(
+    client,
+    api,
+    video_id,
+    settings.skip_segments.clone(),
+  )))
+}
+
+async fn cached_loudness(ctx: &Context, url: &str) -> Option<f64> {
+  let cache = {
+    let data = ctx.data.read().await;
+    data
+      .get::<LoudnessStorage>()
+      .cloned()
+      .expect("LoudnessStorage did not exist")
+  };
+  let loudness = cache.read().await.get(url);
+  loudness
+}
+
+async fn check_limits(
+  limits: &QueueLimits,
+  queue: &[TrackHandle],
+  metadata: &SongMetadata,
+  requester: &Requester,
+) -> Result<(), String> {
+  if let Some(max) = limits.max_queue_length {
+    if queue.len() >= max {
+      return Err(format!("The queue is full (max: {} tracks)", max));
+    }
+  }
+
+  let live = bool::from(format_duration_live(metadata.duration, &metadata.title));
+  if live && !limits.allow_live {
+    return Err("Live streams aren't allowed".to_string());
+  }
+
+  if let Some(max) = limits.max_duration {
+    if !live && metadata.duration > max {
+      return Err(format!("Track is too long (max: {})", format_duration(max)));
+    }
+  }
+
+  if limits.max_tracks_per_user.is_none() && limits.allow_duplicates {
+    return Ok(());
+  }
+
+  let mut user_tracks = 0;
+  for handle in queue {
+    if Requester::from_handle(handle)
+      .await
+      .is_some_and(|r| r.id == requester.id)
+    {
+      user_tracks += 1;
+    }
+
+    if !limits.allow_duplicates {
+      let queued = SongMetadata::from_handle(handle).await;
+      let duplicate = match (&queued.url, &metadata.url) {
+        (Some(a), Some(b)) => a == b,
+        _ => queued.query == metadata.query,
+      };
+      if duplicate {
+        return Err("Track is already in the queue".to_string());
+      }
+    }
+  }
+
+  if let Some(max) = limits.max_tracks_per_user {
+    if user_tracks >= max {
+      return Err(format!(
+        "You already have {} tracks in the queue (max: {})",
+        user_tracks, max
+      ));
+    }
+  }
+
+  Ok(())
+}
+
+/// Finds where a new track by `requester` goes so that everyone gets a turn. Each track belongs
+/// to the round matching how many tracks its requester has ahead of it, counting the one playing,
+/// and the new track goes after the last track of its round
+pub fn fair_share_position(requesters: &[Option<UserId>], requester: UserId) -> usize {
+  if requesters.len() < 2 {
+    return requesters.len();
+  }
+
+  let round = requesters.iter().filter(|r| **r == Some(requester)).count();
+
+  let mut counts: HashMap<Option<UserId>, usize> = HashMap::new();
+  let mut position = 1;
+  for (i, r) in requesters.iter().enumerate() {
+    let count = counts.entry(*r).or_default();
+    if *count <= round {
+      position = i + 1;
+    }
+    *count += 1;
+  }
+  position
+}
+
 pub async fn get_queue_length_and_duration(queue: &Vec<TrackHandle>) -> (usize, Duration) {
   (queue.len(), get_queue_duration(queue).await)
 }
@@ -185,6 +628,32 @@ pub fn format_duration_live(d: Duration, t: &str) -> DurationFormat {
   }
 }
 
+/// Bar with a knob as far along it as `position` is into `duration`
+pub fn progress_bar(position: Duration, duration: Duration) -> String {
+  let fraction = match duration.is_zero() {
+    true => 0.0,
+    false => (position.as_secs_f64() / duration.as_secs_f64()).clamp(0.0, 1.0),
+  };
+  let knob = (fraction * (PROGRESS_BAR_LENGTH - 1) as f64).round() as usize;
+  (0..PROGRESS_BAR_LENGTH)
+    .map(|i| match i == knob {
+      true => "🔘",
+      false => "▬",
+    })
+    .collect()
+}
+
+/// Clock style time, like 3:05 or 1:02:03
+pub fn format_timestamp(d: Duration) -> String {
+  let s = d.as_secs() % 60;
+  let m = (d.as_secs() / 60) % 60;
+  let h = d.as_secs() / 60 / 60;
+  match h > 0 {
+    true => format!("{}:{:02}:{:02}", h, m, s),
+    false => format!("{}:{:02}", m, s),
+  }
+}
+
 pub fn format_duration(d: Duration) -> String {
   let s = d.as_secs() % 60;
   let m = (d.as_secs() / 60) % 60;
@@ -211,3 +680,34 @@ pub fn format_duration(d: Duration) -> String {
     },
   )
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const A: Option<UserId> = Some(UserId::new(1));
+  const B: Option<UserId> = Some(UserId::new(2));
+
+  #[test]
+  fn fair_share_goes_after_the_current_track_or_at_the_end_of_short_queues() {
+    assert_eq!(fair_share_position(&[], UserId::new(2)), 0);
+    assert_eq!(fair_share_position(&[A], UserId::new(2)), 1);
+  }
+
+  #[test]
+  fn fair_share_puts_a_first_request_ahead_of_someone_elses_second() {
+    assert_eq!(fair_share_position(&[A, A, A], UserId::new(2)), 1);
+    assert_eq!(fair_share_position(&[A, A, B, A], UserId::new(2)), 3);
+  }
+
+  #[test]
+  fn fair_share_keeps_a_users_own_requests_in_order() {
+    assert_eq!(fair_share_position(&[A, B, A, A], UserId::new(2)), 3);
+    assert_eq!(fair_share_position(&[A, B, A, B], UserId::new(1)), 4);
+  }
+
+  #[test]
+  fn fair_share_counts_autoplayed_tracks_as_one_requester() {
+    assert_eq!(fair_share_position(&[A, None, None], UserId::new(2)), 2);
+  }
+}
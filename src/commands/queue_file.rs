@@ -0,0 +1,166 @@
+use crate::commands::playback::SongMetadata;
+use crate::constants::placeholder_img;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const M3U_FILENAME: &str = "queue.m3u8";
+pub const JSON_FILENAME: &str = "queue.json";
+
+#[derive(Serialize, Deserialize)]
+pub struct QueueFileEntry {
+  pub title: String,
+  pub url: Option<String>,
+  #[serde(default)]
+  pub duration: u64,
+  #[serde(default)]
+  pub thumbnail: Option<String>,
+  #[serde(default)]
+  pub query: Option<String>,
+  #[serde(default)]
+  pub requester: Option<String>,
+}
+
+impl From<&SongMetadata> for QueueFileEntry {
+  fn from(metadata: &SongMetadata) -> Self {
+    Self {
+      title: metadata.title.clone(),
+      url: metadata.url.clone(),
+      duration: metadata.duration.as_secs(),
+      thumbnail: Some(metadata.thumbnail.clone()),
+      query: Some(metadata.query.clone()),
+      requester: None,
+    }
+  }
+}
+
+impl From<QueueFileEntry> for SongMetadata {
+  fn from(entry: QueueFileEntry) -> Self {
+    let query = entry
+      .query
+      .or_else(|| entry.url.clone())
+      .unwrap_or_else(|| entry.title.clone());
+    Self {
+      title: entry.title,
+      thumbnail: entry.thumbnail.unwrap_or_else(placeholder_img),
+      duration: Duration::from_secs(entry.duration),
+      url: entry.url,
+      query,
+      chapters: vec![],
+    }
+  }
+}
+
+pub fn to_m3u(entries: &[QueueFileEntry]) -> String {
+  let mut out = "#EXTM3U\n".to_string();
+  for entry in entries {
+    let location = match (&entry.url, &entry.query) {
+      (Some(url), _) => url,
+      (None, Some(query)) => query,
+      (None, None) => &entry.title,
+    };
+    out.push_str(&format!(
+      "#EXTINF:{},{}\n{}\n",
+      entry.duration,
+      entry.title.replace('\n', " "),
+      location
+    ));
+  }
+  out
+}
+
+pub fn to_json(entries: &[QueueFileEntry]) -> Result<String, serde_json::Error> {
+  serde_json::to_string_pretty(entries)
+}
+
+/// Parses an exported queue, guessing the format from the filename and falling
+/// back to trying both.
+pub fn parse(filename: &str, content: &str) -> Result<Vec<QueueFileEntry>, String> {
+  let filename = filename.to_lowercase();
+  if filename.ends_with(".json") {
+    parse_json(content)
+  } else if filename.ends_with(".m3u") || filename.ends_with(".m3u8") {
+    Ok(parse_m3u(content))
+  } else {
+    parse_json(content).or_else(|_| Ok(parse_m3u(content)))
+  }
+}
+
+fn parse_json(content: &str) -> Result<Vec<QueueFileEntry>, String> {
+  serde_json::from_str(content).map_err(|e| format!("Invalid queue JSON: {}", e))
+}
+
+fn parse_m3u(content: &str) -> Vec<QueueFileEntry> {
+  let mut entries = vec![];
+  let mut info: Option<(u64, String)> = None;
+
+  for line in content.lines().map(str::trim) {
+    if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+      info = extinf.split_once(',').map(|(duration, title)| {
+        (
+          duration.trim().parse::<f64>().unwrap_or_default().max(0.0) as u64,
+          title.trim().to_string(),
+        )
+      });
+    } else if !line.is_empty() && !line.starts_with('#') {
+      let (duration, title) = info.take().unwrap_or_else(|| (0, line.to_string()));
+      let url = match line.starts_with("https://") || line.starts_with("http://") {
+        true => Some(line.to_string()),
+        false => None,
+      };
+      entries.push(QueueFileEntry {
+        title: match title.is_empty() {
+          true => line.to_string(),
+          false => title,
+        },
+        url,
+        duration,
+        thumbnail: None,
+        query: Some(line.to_string()),
+        requester: None,
+      });
+    }
+  }
+
+  entries
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn m3u_entries_take_their_extinf_info() {
+    let entries = parse_m3u(
+      "#EXTM3U\n#EXTINF:212.7,Artist - Song\nhttps://example.com/song\n\n#EXTINF:-1,Stream\nhttp://example.com/live\n",
+    );
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].title, "Artist - Song");
+    assert_eq!(entries[0].duration, 212);
+    assert_eq!(entries[0].url.as_deref(), Some("https://example.com/song"));
+    assert_eq!(
+      entries[0].query.as_deref(),
+      Some("https://example.com/song")
+    );
+    assert_eq!(entries[1].title, "Stream");
+    assert_eq!(entries[1].duration, 0);
+  }
+
+  #[test]
+  fn m3u_lines_without_info_are_searched_for() {
+    let entries = parse_m3u("some song\n#EXTINF:10,\nother song\n# comment");
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].title, "some song");
+    assert_eq!(entries[0].url, None);
+    assert_eq!(entries[0].query.as_deref(), Some("some song"));
+    assert_eq!(entries[1].title, "other song");
+    assert_eq!(entries[1].duration, 10);
+  }
+
+  #[test]
+  fn m3u_info_only_applies_to_the_next_entry() {
+    let entries = parse_m3u("#EXTINF:30,First\nfirst\nsecond");
+    assert_eq!(entries[0].title, "First");
+    assert_eq!(entries[1].title, "second");
+    assert_eq!(entries[1].duration, 0);
+  }
+}
@@ -0,0 +1,224 @@
+use crate::audio::cache::{get_audio_cache, CachedAudio};
+use crate::commands::playback::{get_source, get_stored_source, Chapter, SongMetadata};
+use crate::constants::{placeholder_img, HttpClient, HttpKey, YTDL_COMMAND, YTDL_TIMEOUT};
+use crate::metadata_cache::{get_cache, CacheEntry, StreamInfo};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::Deserialize;
+use serenity::client::Context;
+use songbird::input::{Compose, HttpRequest};
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{error, info};
+
+#[derive(Deserialize)]
+struct YtdlOutput {
+  title: Option<String>,
+  thumbnail: Option<String>,
+  duration: Option<f64>,
+  webpage_url: Option<String>,
+  url: String,
+  http_headers: Option<HashMap<String, String>>,
+  filesize: Option<u64>,
+  protocol: Option<String>,
+  chapters: Option<Vec<YtdlChapter>>,
+}
+
+#[derive(Deserialize)]
+struct YtdlChapter {
+  title: Option<String>,
+  start_time: f64,
+  end_time: f64,
+}
+
+/// Finds the track for a URL or search term, from the metadata cache if possible
+pub async fn resolve(ctx: &Context, query: &str) -> (Box<dyn Compose>, SongMetadata) {
+  let (metadata, stream) = lookup(ctx, query).await;
+
+  let client = get_http_client(ctx).await;
+  let source: Box<dyn Compose> = match (stream, &metadata.url) {
+    (Some(stream), _) => http_source(client, &stream),
+    (None, Some(_)) => Box::new(get_stored_source(client, &metadata)),
+    (None, None) => Box::new(get_source(client, query.to_string())),
+  };
+  (cached_audio(ctx, &metadata, source).await, metadata)
+}
+
+/// Like `resolve`, for when the track is only stored and not played
+pub async fn resolve_metadata(ctx: &Context, query: &str) -> SongMetadata {
+  lookup(ctx, query).await.0
+}
+
+async fn lookup(ctx: &Context, query: &str) -> (SongMetadata, Option<StreamInfo>) {
+  let cache = get_cache(ctx).await;
+  let now = chrono::Utc::now().timestamp();
+
+  let cached = cache.write().await.get(query, now);
+  if let Some(entry) = cached {
+    info!("Metadata cache hit for {}", query);
+    let metadata = SongMetadata {
+      query: query.to_string(),
+      ..entry.metadata
+    };
+    return (metadata, entry.stream);
+  }
+
+  let output = match query_ytdl(query).await {
+    Ok(o) => o,
+    Err(e) => {
+      error!("Error resolving {}: {}", query, e);
+      let metadata = SongMetadata {
+        title: "N/A".to_string(),
+        thumbnail: placeholder_img(),
+        duration: Duration::default(),
+        url: None,
+        query: query.to_string(),
+        chapters: vec![],
+      };
+      return (metadata, None);
+    }
+  };
+
+  let metadata = SongMetadata {
+    title: output.title.unwrap_or_else(|| "N/A".to_string()),
+    thumbnail: output.thumbnail.unwrap_or_else(placeholder_img),
+    duration: output
+      .duration
+      .map(Duration::from_secs_f64)
+      .unwrap_or_default(),
+    url: output.webpage_url,
+    query: query.to_string(),
+    chapters: output
+      .chapters
+      .unwrap_or_default()
+      .into_iter()
+      .enumerate()
+      .map(|(i, c)| Chapter {
+        title: c.title.unwrap_or_else(|| format!("Chapter {}", i + 1)),
+        start: Duration::from_secs_f64(c.start_time.max(0.0)),
+        end: Duration::from_secs_f64(c.end_time.max(c.start_time).max(0.0)),
+      })
+      .collect(),
+  };
+  // Live streams are played in segments that yt-dlp has to keep resolving
+  let stream = match output.protocol.as_deref() {
+    Some("m3u8_native") => None,
+    _ => Some(StreamInfo::new(
+      output.url,
+      output.http_headers.unwrap_or_default(),
+      output.filesize,
+      now,
+    )),
+  };
+
+  let mut cache = cache.write().await;
+  cache.insert(
+    query,
+    CacheEntry::new(metadata.clone(), stream.clone(), now),
+  );
+  cache.save();
+  (metadata, stream)
+}
+
+/// Source for already resolved metadata, e.g. from a playlist, reusing a cached stream if there is one
+pub async fn stored_source(ctx: &Context, metadata: &SongMetadata) -> Box<dyn Compose> {
+  let client = get_http_client(ctx).await;
+  let now = chrono::Utc::now().timestamp();
+
+  let stream = match &metadata.url {
+    Some(url) => get_cache(ctx)
+      .await
+      .write()
+      .await
+      .get(url, now)
+      .and_then(|e| e.stream),
+    None => None,
+  };
+
+  let source = match stream {
+    Some(stream) => http_source(client, &stream),
+    None => Box::new(get_stored_source(client, metadata)),
+  };
+  cached_audio(ctx, metadata, source).await
+}
+
+/// Plays the track from the disk cache if it's there, streaming `source` otherwise
+async fn cached_audio(
+  ctx: &Context,
+  metadata: &SongMetadata,
+  source: Box<dyn Compose>,
+) -> Box<dyn Compose> {
+  let url = match &metadata.url {
+    Some(url) => url,
+    None => return source,
+  };
+  match get_audio_cache(ctx).await.read().await.cached(url) {
+    Some(path) => {
+      info!("Audio cache hit for {}", url);
+      Box::new(CachedAudio::new(path, source))
+    }
+    None => source,
+  }
+}
+
+async fn get_http_client(ctx: &Context) -> HttpClient {
+  let data = ctx.data.read().await;
+  data
+    .get::<HttpKey>()
+    .cloned()
+    .expect("HttpClient did not exist")
+}
+
+fn http_source(client: HttpClient, stream: &StreamInfo) -> Box<dyn Compose> {
+  let headers = stream
+    .headers
+    .iter()
+    .filter_map(|(k, v)| {
+      Some((
+        HeaderName::from_bytes(k.as_bytes()).ok()?,
+        HeaderValue::from_str(v).ok()?,
+      ))
+    })
+    .collect::<HeaderMap>();
+
+  Box::new(HttpRequest {
+    client,
+    request: stream.url.clone(),
+    headers,
+    content_length: stream.content_length,
+  })
+}
+
+/// Runs yt-dlp with the same arguments songbird uses, keeping the stream URL it resolves
+async fn query_ytdl(query: &str) -> Result<YtdlOutput, String> {
+  let target = match query.contains("https://") {
+    true => query.to_string(),
+    false => format!("ytsearch1:{}", query),
+  };
+
+  let output = Command::new(YTDL_COMMAND)
+    .args([
+      "-j",
+      &target,
+      "-f",
+      "ba[abr>0][vcodec=none]/best",
+      "--no-playlist",
+    ])
+    .kill_on_drop(true)
+    .output();
+  let output = match tokio::time::timeout(YTDL_TIMEOUT, output).await {
+    Ok(o) => o.map_err(|e| e.to_string())?,
+    Err(_) => return Err("yt-dlp took too long".to_string()),
+  };
+
+  if !output.status.success() {
+    return Err(String::from_utf8_lossy(&output.stderr).to_string());
+  }
+
+  let line = output
+    .stdout
+    .split(|b| *b == b'\n')
+    .find(|l| !l.is_empty())
+    .ok_or_else(|| "No results".to_string())?;
+  serde_json::from_slice(line).map_err(|e| e.to_string())
+}
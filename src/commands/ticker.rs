@@ -0,0 +1,180 @@
+use crate::commands::{cmd::Queue, events::playing_embed};
+use serenity::builder::EditMessage;
+use serenity::client::Context;
+use serenity::model::channel::Message;
+use serenity::model::id::GuildId;
+use serenity::prelude::{Mutex, TypeMapKey};
+use songbird::tracks::{PlayMode, TrackHandle};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+/// How often progress bars are redrawn, editing too often runs into rate limits
+const TICK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Messages with progress bars kept up to date in each guild
+pub struct TickerStorage;
+
+impl TypeMapKey for TickerStorage {
+  type Value = Arc<Mutex<HashMap<GuildId, Ticker>>>;
+}
+
+#[derive(Default)]
+pub struct Ticker {
+  now_playing: Option<NowPlaying>,
+  queue: Option<Message>,
+  task: Option<tokio::task::AbortHandle>,
+}
+
+#[derive(Clone)]
+struct NowPlaying {
+  message: Message,
+  handle: TrackHandle,
+  title: String,
+}
+
+/// Keeps the progress in a now playing message up to date while its track plays
+pub async fn watch_now_playing(
+  ctx: &Context,
+  guild_id: GuildId,
+  message: Message,
+  handle: TrackHandle,
+  title: &str,
+) {
+  let tickers = get_tickers(ctx).await;
+  let mut tickers = tickers.lock().await;
+  let ticker = tickers.entry(guild_id).or_default();
+  ticker.now_playing = Some(NowPlaying {
+    message,
+    handle,
+    title: title.to_string(),
+  });
+  start(ctx, guild_id, ticker);
+}
+
+/// Keeps a queue message up to date while tracks play
+pub async fn watch_queue(ctx: &Context, guild_id: GuildId, message: Message) {
+  let tickers = get_tickers(ctx).await;
+  let mut tickers = tickers.lock().await;
+  let ticker = tickers.entry(guild_id).or_default();
+  ticker.queue = Some(message);
+  start(ctx, guild_id, ticker);
+}
+
+/// Picks the messages back up after playback was paused
+pub async fn resume(ctx: &Context, guild_id: GuildId) {
+  let tickers = get_tickers(ctx).await;
+  let mut tickers = tickers.lock().await;
+  if let Some(ticker) = tickers.get_mut(&guild_id) {
+    start(ctx, guild_id, ticker);
+  }
+}
+
+fn start(ctx: &Context, guild_id: GuildId, ticker: &mut Ticker) {
+  if let Some(task) = &ticker.task {
+    if !task.is_finished() {
+      return;
+    }
+  }
+  let ctx = ctx.clone();
+  ticker.task = Some(tokio::spawn(async move { tick(ctx, guild_id).await }).abort_handle());
+}
+
+async fn tick(ctx: Context, guild_id: GuildId) {
+  let tickers = get_tickers(&ctx).await;
+
+  loop {
+    tokio::time::sleep(TICK_INTERVAL).await;
+
+    let (queue, now_playing, queue_message) = {
+      // Held while checking so a message watched meanwhile doesn't miss the task stopping
+      let mut tickers = tickers.lock().await;
+      let queue = match songbird::get(&ctx).await.and_then(|m| m.get(guild_id)) {
+        Some(h) => h.lock().await.queue().current_queue(),
+        // Left the voice channel
+        None => {
+          tickers.remove(&guild_id);
+          return;
+        }
+      };
+      let current = match queue.first() {
+        Some(c) => c,
+        None => {
+          tickers.remove(&guild_id);
+          return;
+        }
+      };
+      let ticker = match tickers.get_mut(&guild_id) {
+        Some(t) => t,
+        None => return,
+      };
+
+      match current.get_info().await.map(|s| s.playing) {
+        Ok(PlayMode::Play) => (),
+        // Paused, resuming starts the task again
+        Ok(PlayMode::Pause) => {
+          ticker.task = None;
+          return;
+        }
+        _ => {
+          tickers.remove(&guild_id);
+          return;
+        }
+      }
+
+      // The message is about a track that's over now
+      if let Some(n) = &ticker.now_playing {
+        if n.handle.uuid() != current.uuid() {
+          ticker.now_playing = None;
+        }
+      }
+      if ticker.now_playing.is_none() && ticker.queue.is_none() {
+        tickers.remove(&guild_id);
+        return;
+      }
+      (queue, ticker.now_playing.clone(), ticker.queue.clone())
+    };
+
+    if let Some(mut n) = now_playing {
+      let embed = playing_embed(&ctx, guild_id, &n.title, &n.handle).await;
+      if let Err(e) = n
+        .message
+        .edit(&ctx.http, EditMessage::new().embed(embed))
+        .await
+      {
+        error!("Error updating now playing message: {}", e);
+        let mut tickers = tickers.lock().await;
+        // Unless it was replaced by a new message meanwhile
+        if let Some(t) = tickers.get_mut(&guild_id) {
+          if t.now_playing.as_ref().map(|m| m.message.id) == Some(n.message.id) {
+            t.now_playing = None;
+          }
+        }
+      }
+    }
+    if let Some(mut message) = queue_message {
+      let embed = Queue::embed(&ctx, guild_id, queue).await;
+      if let Err(e) = message
+        .edit(&ctx.http, EditMessage::new().embed(embed))
+        .await
+      {
+        error!("Error updating queue message: {}", e);
+        let mut tickers = tickers.lock().await;
+        if let Some(t) = tickers.get_mut(&guild_id) {
+          if t.queue.as_ref().map(|m| m.id) == Some(message.id) {
+            t.queue = None;
+          }
+        }
+      }
+    }
+  }
+}
+
+async fn get_tickers(ctx: &Context) -> Arc<Mutex<HashMap<GuildId, Ticker>>> {
+  let data = ctx.data.read().await;
+  data
+    .get::<TickerStorage>()
+    .cloned()
+    .expect("TickerStorage did not exist")
+}
@@ -1,3 +1,6 @@
+use serenity::model::application::{CommandInteraction, ResolvedOption, ResolvedValue};
+use serenity::model::id::{ChannelId, RoleId};
+
 pub fn remove_md_characters<S>(s: S) -> String
 where
   S: ToString,
@@ -12,3 +15,74 @@ where
     .replace('[', r"\[")
     .replace(']', r"\]")
 }
+
+pub fn get_subcommand(command: &CommandInteraction) -> Option<(&str, Vec<ResolvedOption<'_>>)> {
+  command
+    .data
+    .options()
+    .into_iter()
+    .find_map(|o| match o.value {
+      ResolvedValue::SubCommand(options) => Some((o.name, options)),
+      _ => None,
+    })
+}
+
+pub fn get_string_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> Option<&'a str> {
+  options
+    .iter()
+    .find(|o| o.name == name)
+    .and_then(|o| match o.value {
+      ResolvedValue::String(s) => Some(s),
+      _ => None,
+    })
+}
+
+pub fn get_integer_option(options: &[ResolvedOption<'_>], name: &str) -> Option<i64> {
+  options
+    .iter()
+    .find(|o| o.name == name)
+    .and_then(|o| match o.value {
+      ResolvedValue::Integer(i) => Some(i),
+      _ => None,
+    })
+}
+
+pub fn get_number_option(options: &[ResolvedOption<'_>], name: &str) -> Option<f64> {
+  options
+    .iter()
+    .find(|o| o.name == name)
+    .and_then(|o| match o.value {
+      ResolvedValue::Number(n) => Some(n),
+      _ => None,
+    })
+}
+
+pub fn get_bool_option(options: &[ResolvedOption<'_>], name: &str) -> Option<bool> {
+  options
+    .iter()
+    .find(|o| o.name == name)
+    .and_then(|o| match o.value {
+      ResolvedValue::Boolean(b) => Some(b),
+      _ => None,
+    })
+}
+
+pub fn get_role_option(options: &[ResolvedOption<'_>], name: &str) -> Option<RoleId> {
+  options
+    .iter()
+    .find(|o| o.name == name)
+    .and_then(|o| match o.value {
+      ResolvedValue::Role(r) => Some(r.id),
+      _ => None,
+    })
+}
+
+pub fn get_channel_option(options: &[ResolvedOption<'_>], name: &str) -> Option<ChannelId> {
+  options
+    .iter()
+    .find(|o| o.name == name)
+    .and_then(|o| match o.value {
+      ResolvedValue::Channel(c) => Some(c.id),
+      _ => None,
+    })
+}
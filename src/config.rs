@@ -1,9 +1,13 @@
+use crate::assets;
+use crate::audio::cache::DEFAULT_MIN_PLAYS;
 use crate::constants;
+use crate::lyrics;
+use crate::segments::DEFAULT_API;
 use serenity::{
   model::id::{ApplicationId, GuildId},
   prelude::TypeMapKey,
 };
-use std::sync::Arc;
+use std::{path::PathBuf, sync::Arc};
 use tracing::{error, info};
 
 pub struct ConfigStorage;
@@ -16,6 +20,27 @@ pub struct Config {
   pub token: String,
   pub application_id: ApplicationId,
   pub guild_id: Option<GuildId>,
+  pub data_dir: PathBuf,
+  /// Keep resolved track metadata in `data_dir` between restarts
+  pub persist_metadata_cache: bool,
+  /// Bytes of audio kept on disk for tracks played often, disabled if zero
+  pub audio_cache_size: u64,
+  /// Plays before a track's audio is kept on disk
+  pub audio_cache_min_plays: u32,
+  /// SponsorBlock compatible API segments are fetched from, segment skipping is off if unset
+  pub segments_api: Option<String>,
+  /// Directory with `.lrc` and `.txt` lyrics files
+  pub lyrics_dir: PathBuf,
+  /// LRCLIB compatible API lyrics are fetched from, only local files are used if unset
+  pub lyrics_api: Option<String>,
+  /// Base URL of the capybara gifs, used when `capybara_dir` is unset
+  pub capybara_url: String,
+  /// Gifs for each weekday at `capybara_url`, named `cp_monday.gif`, `cp_monday_2.gif`...
+  pub capybara_gifs_per_day: usize,
+  /// Directory capybara gifs are uploaded from instead of linked, any amount per weekday
+  pub capybara_dir: Option<PathBuf>,
+  /// Thumbnail URL for tracks without one, embeds can't show it as an attachment
+  pub placeholder_img: String,
 }
 
 pub fn read_config() -> Config {
@@ -52,9 +77,100 @@ pub fn read_config() -> Config {
     }
   };
 
+  let data_dir = PathBuf::from(std::env::var("DATA_DIR").unwrap_or_else(|_| "data".to_string()));
+  if let Err(e) = std::fs::create_dir_all(&data_dir) {
+    error!("Error creating DATA_DIR({}): {}", data_dir.display(), e);
+    std::process::exit(constants::ErrorCodes::DataDirError as i32);
+  }
+  info!("Using DATA_DIR({})", data_dir.display());
+
+  let persist_metadata_cache = match std::env::var("PERSIST_METADATA_CACHE") {
+    Ok(v) => !matches!(v.to_lowercase().as_str(), "false" | "0" | "no"),
+    Err(_e) => true,
+  };
+
+  let audio_cache_size = match std::env::var("AUDIO_CACHE_SIZE_MB") {
+    Ok(s) => match s.parse::<u64>() {
+      Ok(mb) => mb * 1024 * 1024,
+      Err(e) => {
+        error!(
+          "Error parsing AUDIO_CACHE_SIZE_MB({}), disabling audio cache",
+          s
+        );
+        error!("ParseError: {:?}", e);
+        0
+      }
+    },
+    Err(_e) => 0,
+  };
+
+  let audio_cache_min_plays = std::env::var("AUDIO_CACHE_MIN_PLAYS")
+    .ok()
+    .and_then(|n| n.parse::<u32>().ok())
+    .unwrap_or(DEFAULT_MIN_PLAYS)
+    .max(1);
+
+  let segments_api = match std::env::var("SPONSORBLOCK_API") {
+    Ok(api) if api.trim().is_empty() => {
+      info!("SPONSORBLOCK_API is empty, segment skipping disabled");
+      None
+    }
+    Ok(api) => Some(api.trim().to_string()),
+    Err(_e) => Some(DEFAULT_API.to_string()),
+  };
+
+  let lyrics_dir = std::env::var("LYRICS_DIR")
+    .map(PathBuf::from)
+    .unwrap_or_else(|_| data_dir.join("lyrics"));
+
+  let lyrics_api = match std::env::var("LYRICS_API") {
+    Ok(api) if api.trim().is_empty() => {
+      info!("LYRICS_API is empty, only using local lyrics");
+      None
+    }
+    Ok(api) => Some(api.trim().to_string()),
+    Err(_e) => Some(lyrics::DEFAULT_API.to_string()),
+  };
+
+  let capybara_url = match std::env::var("CAPYBARA_GIF_URL") {
+    Ok(url) if url.ends_with('/') => url,
+    Ok(url) => format!("{}/", url),
+    Err(_e) => assets::DEFAULT_CAPYBARA_URL.to_string(),
+  };
+
+  let capybara_gifs_per_day = std::env::var("CAPYBARA_GIFS_PER_DAY")
+    .ok()
+    .and_then(|n| n.parse::<usize>().ok())
+    .unwrap_or(1)
+    .max(1);
+
+  let capybara_dir = match std::env::var("CAPYBARA_GIF_DIR") {
+    Ok(dir) if dir.trim().is_empty() => None,
+    Ok(dir) => {
+      info!("Uploading capybara gifs from CAPYBARA_GIF_DIR({})", dir);
+      Some(PathBuf::from(dir))
+    }
+    Err(_e) => None,
+  };
+
+  let placeholder_img = std::env::var("PLACEHOLDER_IMG")
+    .unwrap_or_else(|_| assets::DEFAULT_PLACEHOLDER_IMG.to_string());
+  constants::set_placeholder_img(placeholder_img.clone());
+
   Config {
     token,
     application_id,
     guild_id,
+    data_dir,
+    persist_metadata_cache,
+    audio_cache_size,
+    audio_cache_min_plays,
+    segments_api,
+    lyrics_dir,
+    lyrics_api,
+    capybara_url,
+    capybara_gifs_per_day,
+    capybara_dir,
+    placeholder_img,
   }
 }
@@ -1,15 +1,37 @@
+use crate::assets::DEFAULT_PLACEHOLDER_IMG;
 pub use reqwest::Client as HttpClient;
 use serenity::model::Colour;
 use serenity::prelude::TypeMapKey;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::error;
 
 pub enum ErrorCodes {
   ConfigFileError = 10,
+  DataDirError = 11,
+}
+
+/// Set from the config at startup
+static PLACEHOLDER_IMG: OnceLock<String> = OnceLock::new();
+
+pub fn set_placeholder_img(url: String) {
+  if PLACEHOLDER_IMG.set(url).is_err() {
+    error!("Placeholder image was already set");
+  }
 }
 
 pub fn placeholder_img() -> String {
-  "https://karei.dev/files/capybara-default.jpg".to_string()
+  PLACEHOLDER_IMG
+    .get()
+    .map(String::as_str)
+    .unwrap_or(DEFAULT_PLACEHOLDER_IMG)
+    .to_string()
 }
 
+pub const YTDL_COMMAND: &str = "yt-dlp";
+/// Lookups running longer are killed, a hung yt-dlp would otherwise outlive the command
+pub const YTDL_TIMEOUT: Duration = Duration::from_secs(8);
+
 pub const EMBED_COLOUR: Colour = Colour::from_rgb(232, 12, 116);
 
 pub struct HttpKey;
@@ -0,0 +1,122 @@
+use evalexpr::Value;
+
+/// Decimals shown when no precision is asked for
+pub const DEFAULT_PRECISION: usize = 6;
+pub const MAX_PRECISION: usize = 15;
+/// Numbers this large are shown in scientific notation
+const SCIENTIFIC_ABOVE: f64 = 1e15;
+/// Numbers this small are shown in scientific notation, as are ones the precision rounds to zero
+const SCIENTIFIC_BELOW: f64 = 1e-4;
+/// Tuples longer than this on one line get a line per element
+const TUPLE_LINE_LENGTH: usize = 60;
+
+pub fn format_number(n: f64) -> String {
+  format_float(n, DEFAULT_PRECISION)
+}
+
+/// Rounds to `precision` decimals without trailing zeros, very large or small numbers in
+/// scientific notation
+pub fn format_float(n: f64, precision: usize) -> String {
+  if n == 0.0 {
+    return "0".to_string();
+  }
+  if n.fract() == 0.0 && n.abs() < SCIENTIFIC_ABOVE {
+    return format!("{:.0}", n);
+  }
+
+  let smallest = SCIENTIFIC_BELOW.max(0.5 * 10f64.powi(-(precision as i32)));
+  if n.is_finite() && (n.abs() >= SCIENTIFIC_ABOVE || n.abs() < smallest) {
+    let scientific = format!("{:.*e}", precision, n);
+    return match scientific.split_once('e') {
+      Some((mantissa, exponent)) => format!("{}e{}", trim_zeros(mantissa), exponent),
+      None => scientific,
+    };
+  }
+  trim_zeros(&format!("{:.*}", precision, n)).to_string()
+}
+
+fn trim_zeros(number: &str) -> &str {
+  match number.contains('.') {
+    true => number.trim_end_matches('0').trim_end_matches('.'),
+    false => number,
+  }
+}
+
+/// Formats a result, strings are shown without quotes unless they're in a tuple
+pub fn format_value(value: &Value, precision: usize) -> String {
+  match value {
+    Value::String(s) => s.clone(),
+    _ => format_nested(value, precision, 0),
+  }
+}
+
+fn format_nested(value: &Value, precision: usize, depth: usize) -> String {
+  match value {
+    Value::String(s) => format!("{:?}", s),
+    Value::Float(f) => format_float(*f, precision),
+    Value::Int(i) => i.to_string(),
+    Value::Boolean(b) => b.to_string(),
+    Value::Empty => "()".to_string(),
+    Value::Tuple(t) => {
+      let elements = t
+        .iter()
+        .map(|v| format_nested(v, precision, depth + 1))
+        .collect::<Vec<_>>();
+      let inline = format!("({})", elements.join(", "));
+      if inline.chars().count() <= TUPLE_LINE_LENGTH && !inline.contains('\n') {
+        return inline;
+      }
+
+      let indent = "  ".repeat(depth + 1);
+      let lines = elements
+        .iter()
+        .map(|e| format!("{}{},", indent, e))
+        .collect::<Vec<_>>()
+        .join("\n");
+      format!("(\n{}\n{})", lines, "  ".repeat(depth))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn floats_are_rounded_without_trailing_zeros() {
+    assert_eq!(format_float(0.0, 6), "0");
+    assert_eq!(format_float(42.0, 6), "42");
+    assert_eq!(format_float(-2.5, 6), "-2.5");
+    assert_eq!(format_float(1.0 / 3.0, 6), "0.333333");
+    assert_eq!(format_float(1.0 / 3.0, 2), "0.33");
+    assert_eq!(format_float(2.0 / 3.0, 0), "1");
+  }
+
+  #[test]
+  fn very_large_or_small_floats_are_scientific() {
+    assert_eq!(format_float(1.5e20, 6), "1.5e20");
+    assert_eq!(format_float(1e15, 6), "1e15");
+    assert_eq!(format_float(0.00001234, 6), "1.234e-5");
+    assert_eq!(format_float(-0.00001234, 6), "-1.234e-5");
+    assert_eq!(format_float(0.001, 2), "1e-3");
+    assert_eq!(format_float(0.001, 6), "0.001");
+  }
+
+  #[test]
+  fn non_finite_floats_are_shown_as_is() {
+    assert_eq!(format_float(f64::INFINITY, 6), "inf");
+    assert_eq!(format_float(f64::NAN, 6), "NaN");
+  }
+
+  #[test]
+  fn long_tuples_get_a_line_per_element() {
+    let short = Value::Tuple(vec![Value::Int(1), Value::from("a")]);
+    assert_eq!(format_value(&short, 6), "(1, \"a\")");
+    assert_eq!(format_value(&Value::from("a"), 6), "a");
+
+    let long = Value::Tuple(vec![Value::Float(1.0 / 3.0); 10]);
+    let lines = format_value(&long, 6);
+    assert_eq!(lines.lines().count(), 12);
+    assert!(lines.starts_with("(\n  0.333333,\n"));
+  }
+}
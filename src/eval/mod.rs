@@ -0,0 +1,274 @@
+use crate::storage::Persisted;
+use evalexpr::{ContextWithMutableVariables, HashMapContext, IterateVariablesContext, Value};
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::model::id::UserId;
+use serenity::prelude::{Mutex, TypeMapKey};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use tracing::error;
+
+mod format;
+mod plot;
+mod sandbox;
+mod units;
+
+pub use format::{format_number, DEFAULT_PRECISION, MAX_PRECISION};
+pub use plot::SERIES_COLOURS;
+pub use sandbox::{evaluate, plot, MAX_EXPRESSION_LENGTH};
+
+pub const MAX_VARIABLES: usize = 50;
+/// Expressions kept per user for /eval history
+pub const MAX_HISTORY: usize = 10;
+/// Variable holding the user's previous result
+pub const ANS_VARIABLE: &str = "ans";
+/// Bytes a single variable can take up
+pub const MAX_VARIABLE_SIZE: usize = 1024;
+
+pub struct EvalContextStorage;
+
+impl TypeMapKey for EvalContextStorage {
+  type Value = Arc<Mutex<EvalContexts>>;
+}
+
+/// `evalexpr::Value` in a form that can be saved
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub enum Variable {
+  String(String),
+  Float(f64),
+  Int(i64),
+  Boolean(bool),
+  Tuple(Vec<Variable>),
+  Empty,
+}
+
+impl Variable {
+  fn size(&self) -> usize {
+    match self {
+      Self::String(s) => s.len(),
+      Self::Tuple(t) => t.iter().map(|v| v.size()).sum(),
+      _ => std::mem::size_of::<f64>(),
+    }
+  }
+}
+
+/// Bytes a value takes up, counted the same way as a kept variable
+fn value_size(value: &Value) -> usize {
+  match value {
+    Value::String(s) => s.len(),
+    Value::Tuple(t) => t.iter().map(value_size).sum(),
+    _ => std::mem::size_of::<f64>(),
+  }
+}
+
+impl From<&Value> for Variable {
+  fn from(value: &Value) -> Self {
+    match value {
+      Value::String(s) => Self::String(s.clone()),
+      Value::Float(f) => Self::Float(*f),
+      Value::Int(i) => Self::Int(*i),
+      Value::Boolean(b) => Self::Boolean(*b),
+      Value::Tuple(t) => Self::Tuple(t.iter().map(Self::from).collect()),
+      Value::Empty => Self::Empty,
+    }
+  }
+}
+
+impl From<&Variable> for Value {
+  fn from(variable: &Variable) -> Self {
+    match variable {
+      Variable::String(s) => Value::String(s.clone()),
+      Variable::Float(f) => Value::Float(*f),
+      Variable::Int(i) => Value::Int(*i),
+      Variable::Boolean(b) => Value::Boolean(*b),
+      Variable::Tuple(t) => Value::Tuple(t.iter().map(Value::from).collect()),
+      Variable::Empty => Value::Empty,
+    }
+  }
+}
+
+/// Result of an expression, calculations with units are done outside evalexpr
+pub enum Answer {
+  Value(Value),
+  Quantity(units::Quantity),
+}
+
+impl Answer {
+  /// Value `ans` is set to for the next expression, assignments don't have one
+  fn ans(&self) -> Option<Value> {
+    match self {
+      Self::Value(Value::Empty) => None,
+      Self::Value(v) => Some(v.clone()),
+      Self::Quantity(q) => Some(q.shown()),
+    }
+  }
+}
+
+/// Floats are rounded to the formatter's precision, like `{:.3}`
+impl std::fmt::Display for Answer {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let precision = f.precision().unwrap_or(DEFAULT_PRECISION);
+    match self {
+      Self::Value(v) => write!(f, "{}", format::format_value(v, precision)),
+      Self::Quantity(q) => write!(f, "{:.*}", precision, q),
+    }
+  }
+}
+
+pub type EvalVariables = HashMap<UserId, BTreeMap<String, Variable>>;
+
+/// An expression a user evaluated, with how it was shown
+#[derive(Clone)]
+pub struct HistoryEntry {
+  /// Unique across users, so a button refers to the same entry as the history grows
+  pub id: u64,
+  pub expression: String,
+  pub precision: usize,
+}
+
+/// Variables each user assigned in /eval, kept for their next expressions
+pub struct EvalContexts {
+  contexts: HashMap<UserId, HashMapContext>,
+  variables: Persisted<EvalVariables>,
+  history: HashMap<UserId, VecDeque<HistoryEntry>>,
+  next_history_id: u64,
+  running: sandbox::Running,
+}
+
+impl EvalContexts {
+  pub fn new(variables: Persisted<EvalVariables>) -> Self {
+    Self {
+      contexts: HashMap::new(),
+      variables,
+      history: HashMap::new(),
+      next_history_id: 0,
+      running: sandbox::Running::default(),
+    }
+  }
+
+  /// Context with the user's variables to evaluate an expression in
+  pub fn get(&mut self, user_id: UserId) -> HashMapContext {
+    let variables = &self.variables;
+    self
+      .contexts
+      .entry(user_id)
+      .or_insert_with(|| {
+        let mut context = HashMapContext::new();
+        for (name, variable) in variables.get(&user_id).into_iter().flatten() {
+          if let Err(e) = context.set_value(name.clone(), variable.into()) {
+            error!("Error restoring variable {}: {}", name, e);
+          }
+        }
+        context
+      })
+      .clone()
+  }
+
+  /// Keeps the variables of a context an expression was evaluated in, if they fit the caps
+  pub fn update(&mut self, user_id: UserId, context: HashMapContext) -> Result<(), String> {
+    let variables = context
+      .iter_variables()
+      .map(|(name, value)| (name, Variable::from(&value)))
+      .collect::<BTreeMap<_, _>>();
+
+    if variables.len() > MAX_VARIABLES {
+      return Err(format!(
+        "Too many variables, clear them with /eval reset (max: {})",
+        MAX_VARIABLES
+      ));
+    }
+    if let Some((name, _)) = variables.iter().find(|(_, v)| v.size() > MAX_VARIABLE_SIZE) {
+      return Err(format!(
+        "{} is too large to keep (max: {} bytes)",
+        name, MAX_VARIABLE_SIZE
+      ));
+    }
+
+    if self.variables.get(&user_id) != Some(&variables) {
+      match variables.is_empty() {
+        true => self.variables.remove(&user_id),
+        false => self.variables.insert(user_id, variables),
+      };
+      self.variables.save();
+    }
+    self.contexts.insert(user_id, context);
+    Ok(())
+  }
+
+  /// The user's variables by name
+  pub fn variables(&self, user_id: UserId) -> Vec<(String, Value)> {
+    self
+      .variables
+      .get(&user_id)
+      .into_iter()
+      .flatten()
+      .map(|(name, variable)| (name.clone(), variable.into()))
+      .collect()
+  }
+
+  /// Forgets the user's variables, returning how many there were
+  pub fn reset(&mut self, user_id: UserId) -> usize {
+    self.contexts.remove(&user_id);
+    let removed = self.variables.remove(&user_id).map_or(0, |v| v.len());
+    if removed > 0 {
+      self.variables.save();
+    }
+    removed
+  }
+
+  /// Remembers an expression the user evaluated, moving it to the top if it was already there
+  pub fn record(&mut self, user_id: UserId, expression: &str, precision: usize) {
+    let history = self.history.entry(user_id).or_default();
+    history.retain(|e| e.expression != expression);
+    history.push_front(HistoryEntry {
+      id: self.next_history_id,
+      expression: expression.to_string(),
+      precision,
+    });
+    history.truncate(MAX_HISTORY);
+    self.next_history_id += 1;
+  }
+
+  /// The user's recent expressions, newest first
+  pub fn history(&self, user_id: UserId) -> Vec<HistoryEntry> {
+    self
+      .history
+      .get(&user_id)
+      .map(|h| h.iter().cloned().collect())
+      .unwrap_or_default()
+  }
+
+  pub fn history_entry(&self, user_id: UserId, id: u64) -> Option<HistoryEntry> {
+    self
+      .history
+      .get(&user_id)?
+      .iter()
+      .find(|e| e.id == id)
+      .cloned()
+  }
+}
+
+/// Sets a variable even if it held a value of another type, which evalexpr refuses to
+fn set_variable(context: &mut HashMapContext, name: &str, value: Value) -> Result<(), String> {
+  if context.set_value(name.to_string(), value.clone()).is_ok() {
+    return Ok(());
+  }
+
+  let mut replaced = HashMapContext::new();
+  for (n, v) in context.iter_variables().filter(|(n, _)| n != name) {
+    replaced.set_value(n, v).map_err(|e| e.to_string())?;
+  }
+  replaced
+    .set_value(name.to_string(), value)
+    .map_err(|e| e.to_string())?;
+  *context = replaced;
+  Ok(())
+}
+
+pub async fn get_contexts(ctx: &Context) -> Arc<Mutex<EvalContexts>> {
+  let data = ctx.data.read().await;
+  data
+    .get::<EvalContextStorage>()
+    .cloned()
+    .expect("EvalContextStorage did not exist")
+}
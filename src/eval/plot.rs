@@ -0,0 +1,532 @@
+use crate::eval::format::format_number;
+use evalexpr::{
+  build_operator_tree, eval_number_with_context, ContextWithMutableVariables, HashMapContext,
+  IterateVariablesContext, Node, Value,
+};
+use regex::Regex;
+
+const WIDTH: usize = 800;
+const HEIGHT: usize = 450;
+/// Space around the plot area, the left and bottom hold the tick labels
+const MARGIN_LEFT: usize = 80;
+const MARGIN_RIGHT: usize = 20;
+const MARGIN_TOP: usize = 20;
+const MARGIN_BOTTOM: usize = 40;
+/// Points each function is evaluated at across the range
+const SAMPLES: usize = 500;
+const DEFAULT_RANGE: (f64, f64) = (-10.0, 10.0);
+/// Ticks aimed for on each axis, the actual amount depends on how the step rounds
+const TARGET_TICKS: f64 = 8.0;
+/// Pixels each pixel of the font is drawn as
+const FONT_SCALE: usize = 2;
+
+const BACKGROUND: u8 = 0;
+const GRID: u8 = 1;
+const FOREGROUND: u8 = 2;
+/// Palette index of the first series colour
+const SERIES: u8 = 3;
+const PALETTE: [[u8; 3]; 8] = [
+  [0xff, 0xff, 0xff],
+  [0xe5, 0xe7, 0xeb],
+  [0x37, 0x41, 0x51],
+  [0x3b, 0x82, 0xf6],
+  [0xef, 0x44, 0x44],
+  [0x22, 0xc5, 0x5e],
+  [0xf9, 0x73, 0x16],
+  [0xa8, 0x55, 0xf7],
+];
+/// Squares matching each series colour, for a legend outside the image
+pub const SERIES_COLOURS: [&str; 5] = ["🟦", "🟥", "🟩", "🟧", "🟪"];
+
+/// A rendered chart and what's on it
+pub struct Plot {
+  pub png: Vec<u8>,
+  pub series: Vec<String>,
+  pub from: f64,
+  pub to: f64,
+}
+
+/// Plots comma separated functions of x, optionally followed by "from a to b"
+pub fn render(input: &str, context: &HashMapContext) -> Result<Plot, String> {
+  let (functions, from, to) = parse(input, context)?;
+  if functions.is_empty() {
+    return Err("Nothing to plot, give a function of x like x^2".to_string());
+  }
+  if functions.len() > SERIES_COLOURS.len() {
+    return Err(format!(
+      "Too many functions (max: {})",
+      SERIES_COLOURS.len()
+    ));
+  }
+
+  // x replaces any variable of the same name, it may not be a number
+  let mut context = context
+    .iter_variables()
+    .filter(|(name, _)| name != "x")
+    .try_fold(HashMapContext::new(), |mut c, (name, value)| {
+      c.set_value(name, value).map(|_| c)
+    })
+    .map_err(|e| e.to_string())?;
+
+  let xs = (0..SAMPLES)
+    .map(|i| from + (to - from) * i as f64 / (SAMPLES - 1) as f64)
+    .collect::<Vec<_>>();
+  let mut series = Vec::new();
+  for function in &functions {
+    let tree = build_operator_tree(function).map_err(|e| format!("{}: {}", function, e))?;
+    series.push(sample(function, &tree, &xs, &mut context)?);
+  }
+
+  let (bottom, top) = y_range(&series);
+  let mut canvas = Canvas::new();
+  let area = Area {
+    from,
+    to,
+    bottom,
+    top,
+  };
+  draw_axes(&mut canvas, &area);
+  for (i, ys) in series.iter().enumerate() {
+    draw_series(&mut canvas, &area, &xs, ys, SERIES + i as u8);
+  }
+
+  Ok(Plot {
+    png: canvas.encode()?,
+    series: functions,
+    from,
+    to,
+  })
+}
+
+/// Splits the input into functions and the range to plot them over
+fn parse(input: &str, context: &HashMapContext) -> Result<(Vec<String>, f64, f64), String> {
+  let range = Regex::new(r"(?is)^(.*?)\s+from\s+(.+?)\s+to\s+(.+)$").expect("Invalid regex");
+  let (functions, from, to) = match range.captures(input.trim()) {
+    Some(c) => {
+      let bound = |s: &str| {
+        eval_number_with_context(s, context).map_err(|e| format!("Invalid range {}: {}", s, e))
+      };
+      (c[1].to_string(), bound(&c[2])?, bound(&c[3])?)
+    }
+    None => (input.to_string(), DEFAULT_RANGE.0, DEFAULT_RANGE.1),
+  };
+  if !from.is_finite() || !to.is_finite() || from >= to {
+    return Err(format!(
+      "Invalid range {} to {}, the start has to be below the end",
+      format_number(from),
+      format_number(to)
+    ));
+  }
+  Ok((split_functions(&functions), from, to))
+}
+
+/// Splits on commas outside of parentheses and strings, those belong to function arguments
+fn split_functions(input: &str) -> Vec<String> {
+  let mut functions = Vec::new();
+  let mut current = String::new();
+  let mut depth = 0usize;
+  let mut in_string = false;
+  let mut escaped = false;
+  for c in input.chars() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if in_string => escaped = true,
+      '"' => in_string = !in_string,
+      '(' if !in_string => depth += 1,
+      ')' if !in_string => depth = depth.saturating_sub(1),
+      ',' if !in_string && depth == 0 => {
+        functions.push(std::mem::take(&mut current));
+        continue;
+      }
+      _ => (),
+    }
+    current.push(c);
+  }
+  functions.push(current);
+  functions
+    .into_iter()
+    .map(|f| f.trim().to_string())
+    .filter(|f| !f.is_empty())
+    .collect()
+}
+
+/// Values of a function at each x, points it isn't defined at are NaN
+fn sample(
+  function: &str,
+  tree: &Node,
+  xs: &[f64],
+  context: &mut HashMapContext,
+) -> Result<Vec<f64>, String> {
+  let mut first_error = None;
+  let mut ys = Vec::with_capacity(xs.len());
+  for &x in xs {
+    context
+      .set_value("x".to_string(), Value::Float(x))
+      .map_err(|e| e.to_string())?;
+    match tree.eval_number_with_context(&*context) {
+      Ok(y) => ys.push(y),
+      Err(e) => {
+        first_error.get_or_insert(e);
+        ys.push(f64::NAN);
+      }
+    }
+  }
+
+  match (ys.iter().any(|y| y.is_finite()), first_error) {
+    (false, Some(e)) => Err(format!("{}: {}", function, e)),
+    (false, None) => Err(format!("{} has no finite values in the range", function)),
+    (true, _) => Ok(ys),
+  }
+}
+
+/// Range of y values to show, ignoring the few extreme ones near asymptotes
+fn y_range(series: &[Vec<f64>]) -> (f64, f64) {
+  let mut ys = series
+    .iter()
+    .flatten()
+    .copied()
+    .filter(|y| y.is_finite())
+    .collect::<Vec<_>>();
+  ys.sort_by(|a, b| a.total_cmp(b));
+
+  let (min, max) = (ys[0], ys[ys.len() - 1]);
+  let trim = ys.len() / 100;
+  let (low, high) = (ys[trim], ys[ys.len() - 1 - trim]);
+  let (min, max) = match max - min > 10.0 * (high - low) && high > low {
+    true => (low, high),
+    false => (min, max),
+  };
+
+  match max - min {
+    d if d > 0.0 => (min - d * 0.05, max + d * 0.05),
+    _ => (min - 1.0, max + 1.0),
+  }
+}
+
+/// Values shown in the plot area
+struct Area {
+  from: f64,
+  to: f64,
+  bottom: f64,
+  top: f64,
+}
+
+impl Area {
+  const LEFT: usize = MARGIN_LEFT;
+  const RIGHT: usize = WIDTH - MARGIN_RIGHT - 1;
+  const TOP: usize = MARGIN_TOP;
+  const BOTTOM: usize = HEIGHT - MARGIN_BOTTOM - 1;
+
+  fn px(&self, x: f64) -> f64 {
+    Self::LEFT as f64 + (x - self.from) / (self.to - self.from) * (Self::RIGHT - Self::LEFT) as f64
+  }
+
+  fn py(&self, y: f64) -> f64 {
+    Self::TOP as f64 + (self.top - y) / (self.top - self.bottom) * (Self::BOTTOM - Self::TOP) as f64
+  }
+
+  fn contains(&self, x: i64, y: i64) -> bool {
+    (Self::LEFT as i64..=Self::RIGHT as i64).contains(&x)
+      && (Self::TOP as i64..=Self::BOTTOM as i64).contains(&y)
+  }
+}
+
+fn draw_axes(canvas: &mut Canvas, area: &Area) {
+  let label_height = 5 * FONT_SCALE as i64;
+
+  for x in ticks(area.from, area.to) {
+    let px = area.px(x).round() as i64;
+    canvas.vline(px, Area::TOP as i64, Area::BOTTOM as i64, GRID);
+    let label = format_number(x);
+    let left = px - canvas.text_width(&label) / 2;
+    canvas.text(left, Area::BOTTOM as i64 + 8, &label, FOREGROUND);
+  }
+  for y in ticks(area.bottom, area.top) {
+    let py = area.py(y).round() as i64;
+    canvas.hline(Area::LEFT as i64, Area::RIGHT as i64, py, GRID);
+    let label = format_number(y);
+    let left = Area::LEFT as i64 - 8 - canvas.text_width(&label);
+    canvas.text(left, py - label_height / 2, &label, FOREGROUND);
+  }
+
+  // Axes through the origin when it's in view, the border otherwise
+  if area.from <= 0.0 && 0.0 <= area.to {
+    let px = area.px(0.0).round() as i64;
+    canvas.vline(px, Area::TOP as i64, Area::BOTTOM as i64, FOREGROUND);
+  }
+  if area.bottom <= 0.0 && 0.0 <= area.top {
+    let py = area.py(0.0).round() as i64;
+    canvas.hline(Area::LEFT as i64, Area::RIGHT as i64, py, FOREGROUND);
+  }
+  canvas.rect(
+    Area::LEFT as i64,
+    Area::TOP as i64,
+    Area::RIGHT as i64,
+    Area::BOTTOM as i64,
+    FOREGROUND,
+  );
+
+  let (width, height) = (WIDTH as i64, HEIGHT as i64);
+  canvas.text(
+    width - MARGIN_RIGHT as i64 - canvas.text_width("x"),
+    height - label_height - 4,
+    "x",
+    FOREGROUND,
+  );
+  canvas.text(4, 4, "y", FOREGROUND);
+}
+
+fn draw_series(canvas: &mut Canvas, area: &Area, xs: &[f64], ys: &[f64], colour: u8) {
+  // Points far outside are pulled in so lines towards them keep their direction without
+  // walking millions of pixels
+  let limit = (HEIGHT * 2) as f64;
+  let point = |x: f64, y: f64| {
+    (
+      area.px(x).round() as i64,
+      area.py(y).clamp(-limit, limit).round() as i64,
+    )
+  };
+
+  for i in 1..xs.len() {
+    let (y0, y1) = (ys[i - 1], ys[i]);
+    if !y0.is_finite() || !y1.is_finite() {
+      continue;
+    }
+    // Jumps from one side of the view to the other are discontinuities, not steep lines
+    if (y0 > area.top && y1 < area.bottom) || (y0 < area.bottom && y1 > area.top) {
+      continue;
+    }
+    let (a, b) = (point(xs[i - 1], y0), point(xs[i], y1));
+    canvas.line(a, b, colour, area);
+  }
+}
+
+/// Round values between min and max to label
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+  let rough = (max - min) / TARGET_TICKS;
+  let magnitude = 10f64.powf(rough.log10().floor());
+  let step = match rough / magnitude {
+    r if r > 5.0 => 10.0,
+    r if r > 2.0 => 5.0,
+    r if r > 1.0 => 2.0,
+    _ => 1.0,
+  } * magnitude;
+
+  let first = (min / step).ceil() as i64;
+  let last = (max / step).floor() as i64;
+  // Adding zero turns -0 into 0, the limit guards against ranges too large for an integer step
+  (first..=last)
+    .take(TARGET_TICKS as usize * 3)
+    .map(|i| i as f64 * step + 0.0)
+    .collect()
+}
+
+/// Image with one palette index per pixel
+struct Canvas {
+  pixels: Vec<u8>,
+}
+
+impl Canvas {
+  fn new() -> Self {
+    Self {
+      pixels: vec![BACKGROUND; WIDTH * HEIGHT],
+    }
+  }
+
+  fn set(&mut self, x: i64, y: i64, colour: u8) {
+    if (0..WIDTH as i64).contains(&x) && (0..HEIGHT as i64).contains(&y) {
+      self.pixels[y as usize * WIDTH + x as usize] = colour;
+    }
+  }
+
+  fn hline(&mut self, x0: i64, x1: i64, y: i64, colour: u8) {
+    for x in x0..=x1 {
+      self.set(x, y, colour);
+    }
+  }
+
+  fn vline(&mut self, x: i64, y0: i64, y1: i64, colour: u8) {
+    for y in y0..=y1 {
+      self.set(x, y, colour);
+    }
+  }
+
+  fn rect(&mut self, x0: i64, y0: i64, x1: i64, y1: i64, colour: u8) {
+    self.hline(x0, x1, y0, colour);
+    self.hline(x0, x1, y1, colour);
+    self.vline(x0, y0, y1, colour);
+    self.vline(x1, y0, y1, colour);
+  }
+
+  /// Two pixel thick line, only drawn inside the plot area
+  fn line(&mut self, (x0, y0): (i64, i64), (x1, y1): (i64, i64), colour: u8, area: &Area) {
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (sx, sy) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let (mut x, mut y, mut error) = (x0, y0, dx + dy);
+    loop {
+      for (ox, oy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+        if area.contains(x + ox, y + oy) {
+          self.set(x + ox, y + oy, colour);
+        }
+      }
+      if x == x1 && y == y1 {
+        break;
+      }
+      let e2 = 2 * error;
+      if e2 >= dy {
+        error += dy;
+        x += sx;
+      }
+      if e2 <= dx {
+        error += dx;
+        y += sy;
+      }
+    }
+  }
+
+  fn text_width(&self, text: &str) -> i64 {
+    let advance = (GLYPH_WIDTH + 1) * FONT_SCALE;
+    (text.chars().count() * advance) as i64 - FONT_SCALE as i64
+  }
+
+  /// Draws text with its top left corner at x, y, characters without a glyph are left blank
+  fn text(&mut self, x: i64, y: i64, text: &str, colour: u8) {
+    let scale = FONT_SCALE as i64;
+    for (i, c) in text.chars().enumerate() {
+      let left = x + i as i64 * (GLYPH_WIDTH as i64 + 1) * scale;
+      let rows = match glyph(c) {
+        Some(g) => g,
+        None => continue,
+      };
+      for (row, bits) in rows.iter().enumerate() {
+        for column in 0..GLYPH_WIDTH {
+          if bits & (1 << (GLYPH_WIDTH - 1 - column)) == 0 {
+            continue;
+          }
+          for (ox, oy) in (0..scale).flat_map(|ox| (0..scale).map(move |oy| (ox, oy))) {
+            self.set(
+              left + column as i64 * scale + ox,
+              y + row as i64 * scale + oy,
+              colour,
+            );
+          }
+        }
+      }
+    }
+  }
+
+  /// Encodes the canvas as an indexed colour PNG
+  fn encode(&self) -> Result<Vec<u8>, String> {
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(PALETTE.concat());
+    encoder
+      .write_header()
+      .and_then(|mut writer| {
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()
+      })
+      .map_err(|e| format!("Error encoding the image: {}", e))?;
+    Ok(png)
+  }
+}
+
+const GLYPH_WIDTH: usize = 3;
+
+/// Rows of a 3x5 font covering what numbers are formatted with, plus the axis names
+fn glyph(c: char) -> Option<[u8; 5]> {
+  let rows = match c {
+    '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+    '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+    '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+    '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+    '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+    '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+    '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+    '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+    '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+    '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+    '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+    '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+    '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+    'e' => [0b000, 0b111, 0b111, 0b100, 0b111],
+    'x' => [0b000, 0b101, 0b010, 0b101, 0b000],
+    'y' => [0b101, 0b101, 0b111, 0b001, 0b111],
+    _ => return None,
+  };
+  Some(rows)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn functions_split_on_top_level_commas() {
+    assert_eq!(
+      split_functions(r#"sin(x), max(x, 1), str::len("a,\"b,") + x"#),
+      vec!["sin(x)", "max(x, 1)", r#"str::len("a,\"b,") + x"#]
+    );
+    assert_eq!(split_functions(" , x^2,"), vec!["x^2"]);
+  }
+
+  #[test]
+  fn ticks_use_round_steps() {
+    assert_eq!(ticks(-10.0, 10.0), vec![-10.0, -5.0, 0.0, 5.0, 10.0]);
+    assert_eq!(ticks(3.0, 97.0), vec![20.0, 40.0, 60.0, 80.0]);
+
+    let small = ticks(0.0, 1.0);
+    assert_eq!(small.len(), 6);
+    assert!(small
+      .iter()
+      .zip([0.0, 0.2, 0.4, 0.6, 0.8, 1.0])
+      .all(|(a, b)| (a - b).abs() < 1e-12));
+  }
+
+  #[test]
+  fn ticks_have_no_negative_zero_or_runaway_counts() {
+    assert!(ticks(-1.0, 0.0)
+      .iter()
+      .all(|t| *t != 0.0 || t.is_sign_positive()));
+    assert!(ticks(-1e300, 1e300).len() <= TARGET_TICKS as usize * 3);
+  }
+
+  #[test]
+  fn y_range_pads_the_values() {
+    let ys = (0..=100).map(f64::from).collect::<Vec<_>>();
+    assert_eq!(y_range(&[ys]), (-5.0, 105.0));
+    assert_eq!(y_range(&[vec![3.0, 3.0, f64::NAN]]), (2.0, 4.0));
+  }
+
+  #[test]
+  fn y_range_ignores_spikes_near_asymptotes() {
+    let mut ys = (0..200).map(|i| f64::from(i) / 200.0).collect::<Vec<_>>();
+    ys.push(1e6);
+    ys.push(f64::INFINITY);
+    let (bottom, top) = y_range(&[ys]);
+    assert!(
+      bottom < 0.0 && top > 0.9 && top < 2.0,
+      "{} to {}",
+      bottom,
+      top
+    );
+  }
+
+  #[test]
+  fn canvas_encodes_as_an_indexed_png() {
+    let mut canvas = Canvas::new();
+    canvas.set(1, 2, SERIES);
+    let png = canvas.encode().unwrap();
+
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    let info = reader.info();
+    assert_eq!((info.width, info.height), (WIDTH as u32, HEIGHT as u32));
+    assert_eq!(info.color_type, png::ColorType::Indexed);
+    assert_eq!(pixels[2 * WIDTH + 1], SERIES);
+    assert_eq!(pixels[0], BACKGROUND);
+  }
+}
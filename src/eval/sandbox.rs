@@ -0,0 +1,272 @@
+use crate::eval::plot::{self, Plot};
+use crate::eval::{
+  set_variable, units, value_size, Answer, EvalContexts, ANS_VARIABLE, MAX_VARIABLE_SIZE,
+};
+use evalexpr::{
+  build_operator_tree, Context, HashMapContext, IterateVariablesContext, Node, Operator, Value,
+};
+use serenity::model::id::UserId;
+use serenity::prelude::Mutex;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+pub const MAX_EXPRESSION_LENGTH: usize = 200;
+/// Characters of a result that can be shown
+pub const MAX_OUTPUT_LENGTH: usize = 1024;
+/// Parentheses this deep are rejected, the parser and evaluator recurse into each level
+const MAX_NESTING: usize = 20;
+const EVAL_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Users with an expression being evaluated, a worker can't be stopped once it started
+#[derive(Clone, Default)]
+pub struct Running(Arc<std::sync::Mutex<HashSet<UserId>>>);
+
+/// Marks the user as running an expression until dropped
+struct RunningGuard {
+  running: Running,
+  user_id: UserId,
+}
+
+impl Running {
+  fn start(&self, user_id: UserId) -> Option<RunningGuard> {
+    let mut running = match self.0.lock() {
+      Ok(r) => r,
+      Err(e) => e.into_inner(),
+    };
+    match running.insert(user_id) {
+      true => Some(RunningGuard {
+        running: self.clone(),
+        user_id,
+      }),
+      false => None,
+    }
+  }
+}
+
+impl Drop for RunningGuard {
+  fn drop(&mut self) {
+    let mut running = match self.running.0.lock() {
+      Ok(r) => r,
+      Err(e) => e.into_inner(),
+    };
+    running.remove(&self.user_id);
+  }
+}
+
+/// Evaluates an expression in the user's context on a blocking worker, keeping its variables,
+/// with units if it has any
+pub async fn evaluate(
+  contexts: &Mutex<EvalContexts>,
+  user_id: UserId,
+  expr: &str,
+) -> Result<Answer, String> {
+  check_expression(expr)?;
+
+  let expr = expr.to_string();
+  let (result, context) = run_blocking(contexts, user_id, move |mut context| {
+    let result = match units::evaluate(&expr, |name| context.get_value(name).is_some()) {
+      Some(result) => result.map(Answer::Quantity),
+      None => eval_statements(&expr, &mut context).map(Answer::Value),
+    };
+    let result = result.and_then(|answer| match answer.ans() {
+      Some(ans) => set_variable(&mut context, ANS_VARIABLE, ans).map(|_| answer),
+      None => Ok(answer),
+    });
+    (result, context)
+  })
+  .await?;
+  let value = result?;
+
+  if value.to_string().chars().count() > MAX_OUTPUT_LENGTH {
+    return Err(format!(
+      "Result is too large to show (max: {} characters)",
+      MAX_OUTPUT_LENGTH
+    ));
+  }
+  contexts.lock().await.update(user_id, context)?;
+  Ok(value)
+}
+
+/// Plots functions of x in the user's context on a blocking worker, without keeping any variables
+pub async fn plot(
+  contexts: &Mutex<EvalContexts>,
+  user_id: UserId,
+  input: &str,
+) -> Result<Plot, String> {
+  check_expression(input)?;
+
+  let input = input.to_string();
+  run_blocking(contexts, user_id, move |context| {
+    plot::render(&input, &context)
+  })
+  .await?
+}
+
+/// Runs `f` with a copy of the user's context on a blocking worker, one at a time per user
+async fn run_blocking<T: Send + 'static>(
+  contexts: &Mutex<EvalContexts>,
+  user_id: UserId,
+  f: impl FnOnce(HashMapContext) -> T + Send + 'static,
+) -> Result<T, String> {
+  let (context, guard) = {
+    let mut contexts = contexts.lock().await;
+    (contexts.get(user_id), contexts.running.start(user_id))
+  };
+  let guard = match guard {
+    Some(g) => g,
+    None => return Err("Your previous expression is still being evaluated".to_string()),
+  };
+
+  let task = tokio::task::spawn_blocking(move || {
+    let _guard = guard;
+    f(context)
+  });
+
+  match tokio::time::timeout(EVAL_TIMEOUT, task).await {
+    Ok(Ok(r)) => Ok(r),
+    Ok(Err(e)) => {
+      error!("Evaluation task failed: {}", e);
+      Err("Evaluation failed".to_string())
+    }
+    Err(_) => Err(format!(
+      "Took too long to evaluate (max: {}s)",
+      EVAL_TIMEOUT.as_secs()
+    )),
+  }
+}
+
+/// Evaluates the statements of a `;` chain one at a time, stopping at the first that makes a
+/// value too large. A statement can only grow its values as much as its length allows, but a
+/// chain like `a=a+a; a=a+a; ...` doubles them with every statement
+fn eval_statements(expr: &str, context: &mut HashMapContext) -> Result<Value, String> {
+  let tree = build_operator_tree(expr).map_err(|e| e.to_string())?;
+  eval_statement(&tree, context)
+}
+
+fn eval_statement(node: &Node, context: &mut HashMapContext) -> Result<Value, String> {
+  match node.operator() {
+    Operator::RootNode | Operator::Chain => {
+      let mut value = Value::Empty;
+      for child in node.children() {
+        value = eval_statement(child, context)?;
+      }
+      Ok(value)
+    }
+    _ if has_chain(node) => {
+      Err("Statements can only be chained with ; outside of other operations".to_string())
+    }
+    _ => {
+      let value = node
+        .eval_with_context_mut(context)
+        .map_err(|e| e.to_string())?;
+      if value_size(&value) > MAX_VARIABLE_SIZE {
+        return Err(format!(
+          "A value got too large (max: {} bytes)",
+          MAX_VARIABLE_SIZE
+        ));
+      }
+      if let Some((name, _)) = context
+        .iter_variables()
+        .find(|(_, v)| value_size(v) > MAX_VARIABLE_SIZE)
+      {
+        return Err(format!(
+          "{} got too large (max: {} bytes)",
+          name, MAX_VARIABLE_SIZE
+        ));
+      }
+      Ok(value)
+    }
+  }
+}
+
+fn has_chain(node: &Node) -> bool {
+  node
+    .children()
+    .iter()
+    .any(|c| matches!(c.operator(), Operator::Chain) || has_chain(c))
+}
+
+/// Rejects expressions that are too large or deep to evaluate quickly
+fn check_expression(expr: &str) -> Result<(), String> {
+  if expr.chars().count() > MAX_EXPRESSION_LENGTH {
+    return Err(format!(
+      "Expression is too long (max: {} characters)",
+      MAX_EXPRESSION_LENGTH
+    ));
+  }
+
+  let mut depth = 0usize;
+  let mut in_string = false;
+  let mut escaped = false;
+  for c in expr.chars() {
+    match c {
+      _ if escaped => escaped = false,
+      '\\' if in_string => escaped = true,
+      '"' => in_string = !in_string,
+      '(' if !in_string => {
+        depth += 1;
+        if depth > MAX_NESTING {
+          return Err(format!(
+            "Expression is nested too deeply (max: {} levels)",
+            MAX_NESTING
+          ));
+        }
+      }
+      ')' if !in_string => depth = depth.saturating_sub(1),
+      _ => (),
+    }
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use evalexpr::ContextWithMutableVariables;
+
+  #[test]
+  fn doubling_a_string_stops_at_the_size_limit() {
+    let expr = format!("a=\"{}\"{}", "x".repeat(20), ";a=a+a".repeat(25));
+    assert!(expr.chars().count() <= MAX_EXPRESSION_LENGTH);
+    let mut context = HashMapContext::new();
+    let error = eval_statements(&expr, &mut context).unwrap_err();
+    assert!(error.contains("too large"), "{}", error);
+  }
+
+  #[test]
+  fn doubling_a_tuple_stops_at_the_size_limit() {
+    let mut context = HashMapContext::new();
+    let error = eval_statements(&format!("a=(1,2){}", ";a=(a,a)".repeat(20)), &mut context);
+    assert!(error.is_err());
+  }
+
+  #[test]
+  fn chains_inside_operations_are_rejected() {
+    let mut context = HashMapContext::new();
+    assert!(eval_statements("b = (a = 1; a + 1)", &mut context).is_err());
+  }
+
+  #[test]
+  fn statements_keep_their_variables() {
+    let mut context = HashMapContext::new();
+    context.set_value("c".to_string(), Value::Int(2)).unwrap();
+    assert_eq!(
+      eval_statements("a = 3; b = a * c; (b + 1)", &mut context),
+      Ok(Value::Int(7))
+    );
+    assert_eq!(context.get_value("b"), Some(&Value::Int(6)));
+    assert_eq!(
+      eval_statements("\"ab\" + \"cd\"", &mut context),
+      Ok(Value::from("abcd"))
+    );
+  }
+
+  #[test]
+  fn long_or_deep_expressions_are_rejected() {
+    assert!(check_expression(&"1+".repeat(MAX_EXPRESSION_LENGTH)).is_err());
+    assert!(check_expression(&format!("{}1{}", "(".repeat(21), ")".repeat(21))).is_err());
+    assert!(check_expression(&format!("\"{}\"", "(".repeat(30))).is_ok());
+  }
+}
@@ -0,0 +1,783 @@
+use crate::commands::format_duration;
+use crate::eval::format::{format_float, format_number, DEFAULT_PRECISION};
+use std::time::Duration;
+
+/// Powers of the base units m, kg, s, K and B
+type Dimension = [i8; 5];
+
+const BASE_UNITS: [&str; 5] = ["m", "kg", "s", "K", "B"];
+const DIMENSIONLESS: Dimension = [0; 5];
+const TIME: Dimension = [0, 0, 1, 0, 0];
+const DATA: Dimension = [0, 0, 0, 0, 1];
+
+struct Unit {
+  names: &'static [&'static str],
+  /// Size of the unit in base units
+  factor: f64,
+  /// Base units the zero of the unit is at, only for temperatures
+  offset: f64,
+  dimension: Dimension,
+}
+
+const fn unit(names: &'static [&'static str], factor: f64, dimension: Dimension) -> Unit {
+  Unit {
+    names,
+    factor,
+    offset: 0.0,
+    dimension,
+  }
+}
+
+const UNITS: &[Unit] = &[
+  // Length
+  unit(
+    &["m", "meter", "meters", "metre", "metres"],
+    1.0,
+    [1, 0, 0, 0, 0],
+  ),
+  unit(&["km"], 1e3, [1, 0, 0, 0, 0]),
+  unit(&["cm"], 1e-2, [1, 0, 0, 0, 0]),
+  unit(&["mm"], 1e-3, [1, 0, 0, 0, 0]),
+  unit(&["um", "µm"], 1e-6, [1, 0, 0, 0, 0]),
+  unit(&["nm"], 1e-9, [1, 0, 0, 0, 0]),
+  unit(&["mi", "mile", "miles"], 1609.344, [1, 0, 0, 0, 0]),
+  unit(&["yd", "yard", "yards"], 0.9144, [1, 0, 0, 0, 0]),
+  unit(&["ft", "foot", "feet"], 0.3048, [1, 0, 0, 0, 0]),
+  unit(&["inch", "inches"], 0.0254, [1, 0, 0, 0, 0]),
+  unit(&["nmi"], 1852.0, [1, 0, 0, 0, 0]),
+  unit(&["au"], 1.495978707e11, [1, 0, 0, 0, 0]),
+  unit(&["ly"], 9.4607304725808e15, [1, 0, 0, 0, 0]),
+  // Area and volume
+  unit(&["ha"], 1e4, [2, 0, 0, 0, 0]),
+  unit(&["acre", "acres"], 4046.8564224, [2, 0, 0, 0, 0]),
+  unit(
+    &["l", "L", "liter", "liters", "litre", "litres"],
+    1e-3,
+    [3, 0, 0, 0, 0],
+  ),
+  unit(&["ml", "mL"], 1e-6, [3, 0, 0, 0, 0]),
+  unit(&["gal"], 3.785411784e-3, [3, 0, 0, 0, 0]),
+  // Mass
+  unit(&["kg"], 1.0, [0, 1, 0, 0, 0]),
+  unit(&["g", "gram", "grams"], 1e-3, [0, 1, 0, 0, 0]),
+  unit(&["mg"], 1e-6, [0, 1, 0, 0, 0]),
+  unit(&["t", "tonne", "tonnes"], 1e3, [0, 1, 0, 0, 0]),
+  unit(&["lb", "lbs"], 0.45359237, [0, 1, 0, 0, 0]),
+  unit(&["oz"], 0.028349523125, [0, 1, 0, 0, 0]),
+  unit(&["st"], 6.35029318, [0, 1, 0, 0, 0]),
+  // Time
+  unit(&["s", "sec", "secs", "second", "seconds"], 1.0, TIME),
+  unit(&["ms"], 1e-3, TIME),
+  unit(&["us", "µs"], 1e-6, TIME),
+  unit(&["ns"], 1e-9, TIME),
+  unit(&["min", "mins", "minute", "minutes"], 60.0, TIME),
+  unit(&["h", "hr", "hrs", "hour", "hours"], 3600.0, TIME),
+  unit(&["d", "day", "days"], 86400.0, TIME),
+  unit(&["wk", "week", "weeks"], 604800.0, TIME),
+  unit(&["yr", "year", "years"], 31557600.0, TIME),
+  // Temperature
+  unit(&["K", "kelvin"], 1.0, [0, 0, 0, 1, 0]),
+  Unit {
+    names: &["C", "°C", "degC", "celsius"],
+    factor: 1.0,
+    offset: 273.15,
+    dimension: [0, 0, 0, 1, 0],
+  },
+  Unit {
+    names: &["F", "°F", "degF", "fahrenheit"],
+    factor: 5.0 / 9.0,
+    offset: 459.67 * 5.0 / 9.0,
+    dimension: [0, 0, 0, 1, 0],
+  },
+  // Data
+  unit(&["bit", "bits"], 0.125, DATA),
+  unit(&["B", "byte", "bytes"], 1.0, DATA),
+  unit(&["kB", "KB"], 1e3, DATA),
+  unit(&["MB"], 1e6, DATA),
+  unit(&["GB"], 1e9, DATA),
+  unit(&["TB"], 1e12, DATA),
+  unit(&["PB"], 1e15, DATA),
+  unit(&["KiB"], 1024.0, DATA),
+  unit(&["MiB"], 1048576.0, DATA),
+  unit(&["GiB"], 1073741824.0, DATA),
+  unit(&["TiB"], 1099511627776.0, DATA),
+  unit(&["kbit", "Kbit"], 125.0, DATA),
+  unit(&["Mbit"], 1.25e5, DATA),
+  unit(&["Gbit"], 1.25e8, DATA),
+  // Derived
+  unit(&["mph"], 0.44704, [1, 0, -1, 0, 0]),
+  unit(&["kph", "kmh"], 1.0 / 3.6, [1, 0, -1, 0, 0]),
+  unit(&["kn", "knot", "knots"], 1852.0 / 3600.0, [1, 0, -1, 0, 0]),
+  unit(&["Hz"], 1.0, [0, 0, -1, 0, 0]),
+  unit(&["kHz"], 1e3, [0, 0, -1, 0, 0]),
+  unit(&["MHz"], 1e6, [0, 0, -1, 0, 0]),
+  unit(&["GHz"], 1e9, [0, 0, -1, 0, 0]),
+  unit(&["N"], 1.0, [1, 1, -2, 0, 0]),
+  unit(&["J"], 1.0, [2, 1, -2, 0, 0]),
+  unit(&["kJ"], 1e3, [2, 1, -2, 0, 0]),
+  unit(&["cal"], 4.184, [2, 1, -2, 0, 0]),
+  unit(&["kcal"], 4184.0, [2, 1, -2, 0, 0]),
+  unit(&["Wh"], 3600.0, [2, 1, -2, 0, 0]),
+  unit(&["kWh"], 3.6e6, [2, 1, -2, 0, 0]),
+  unit(&["eV"], 1.602176634e-19, [2, 1, -2, 0, 0]),
+  unit(&["W"], 1.0, [2, 1, -3, 0, 0]),
+  unit(&["kW"], 1e3, [2, 1, -3, 0, 0]),
+  unit(&["MW"], 1e6, [2, 1, -3, 0, 0]),
+  unit(&["hp"], 745.699_871_582_270_2, [2, 1, -3, 0, 0]),
+  unit(&["Pa"], 1.0, [-1, 1, -2, 0, 0]),
+  unit(&["kPa"], 1e3, [-1, 1, -2, 0, 0]),
+  unit(&["bar"], 1e5, [-1, 1, -2, 0, 0]),
+  unit(&["atm"], 101325.0, [-1, 1, -2, 0, 0]),
+  unit(&["psi"], 6894.757293168, [-1, 1, -2, 0, 0]),
+];
+
+/// Names results are shown in when their dimension has one
+const CANONICAL_NAMES: &[(&str, Dimension)] = &[
+  ("m/s", [1, 0, -1, 0, 0]),
+  ("m²", [2, 0, 0, 0, 0]),
+  ("m³", [3, 0, 0, 0, 0]),
+  ("Hz", [0, 0, -1, 0, 0]),
+  ("N", [1, 1, -2, 0, 0]),
+  ("J", [2, 1, -2, 0, 0]),
+  ("W", [2, 1, -3, 0, 0]),
+  ("Pa", [-1, 1, -2, 0, 0]),
+  ("B/s", [0, 0, -1, 0, 1]),
+];
+
+const CONVERSION_KEYWORDS: [&str; 4] = ["to", "in", "as", "->"];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Base {
+  Binary,
+  Octal,
+  Decimal,
+  Hexadecimal,
+}
+
+impl Base {
+  fn from_name(name: &str) -> Option<Self> {
+    match name {
+      "bin" | "binary" => Some(Self::Binary),
+      "oct" | "octal" => Some(Self::Octal),
+      "dec" | "decimal" => Some(Self::Decimal),
+      "hex" | "hexadecimal" => Some(Self::Hexadecimal),
+      _ => None,
+    }
+  }
+}
+
+/// What a result is shown in
+enum Target {
+  Unit {
+    text: String,
+    factor: f64,
+    offset: f64,
+    dimension: Dimension,
+  },
+  Base(Base),
+}
+
+/// Result of a calculation with units
+pub struct Quantity {
+  /// In base units
+  value: f64,
+  dimension: Dimension,
+  target: Option<Target>,
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+  Number(f64),
+  /// Integer written in another base, which makes it a calculation
+  BaseNumber(f64),
+  Ident(String),
+  Op(char),
+  Conversion,
+}
+
+/// Evaluates `expr` if it uses units, conversions or integers in other bases, `None` leaves it
+/// to evalexpr
+pub fn evaluate(
+  expr: &str,
+  is_variable: impl Fn(&str) -> bool,
+) -> Option<Result<Quantity, String>> {
+  let tokens = tokenize(expr)?;
+
+  let mut uses_units = false;
+  for (i, token) in tokens.iter().enumerate() {
+    match token {
+      Token::Ident(name) if is_variable(name) => return None,
+      // Function calls like min(1, 2)
+      Token::Ident(_) if tokens.get(i + 1) == Some(&Token::Op('(')) => return None,
+      Token::Ident(name) if find_unit(name).is_some() => uses_units = true,
+      Token::Ident(name) if i > 0 && tokens[i - 1] == Token::Conversion => {
+        Base::from_name(name)?;
+        uses_units = true;
+      }
+      Token::Ident(_) => return None,
+      Token::BaseNumber(_) | Token::Conversion => uses_units = true,
+      _ => (),
+    }
+  }
+  if !uses_units {
+    return None;
+  }
+
+  Some(calculate(&tokens))
+}
+
+fn calculate(tokens: &[Token]) -> Result<Quantity, String> {
+  let split = tokens.iter().position(|t| *t == Token::Conversion);
+  let (source, target) = match split {
+    Some(i) => (&tokens[..i], Some(&tokens[i + 1..])),
+    None => (tokens, None),
+  };
+
+  let target = match target {
+    None => None,
+    Some([Token::Ident(name)]) if Base::from_name(name).is_some() => {
+      Base::from_name(name).map(Target::Base)
+    }
+    Some(target) => {
+      let mut parser = Parser {
+        tokens: target,
+        position: 0,
+      };
+      let unit = parser.parse_all()?;
+      let offset = match target {
+        [Token::Ident(name)] if is_point(source) => find_unit(name).map_or(0.0, |u| u.offset),
+        _ => 0.0,
+      };
+      Some(Target::Unit {
+        text: target_text(target),
+        factor: unit.value,
+        offset,
+        dimension: unit.dimension,
+      })
+    }
+  };
+
+  let point = match source {
+    [Token::Number(n), Token::Ident(name)] if is_point(source) => find_unit(name).map(|u| Value {
+      value: n * u.factor + u.offset,
+      dimension: u.dimension,
+    }),
+    [Token::Op('-'), Token::Number(n), Token::Ident(name)] if is_point(source) => find_unit(name)
+      .map(|u| Value {
+        value: -n * u.factor + u.offset,
+        dimension: u.dimension,
+      }),
+    _ => None,
+  };
+  let value = match point {
+    Some(v) => v,
+    None => Parser {
+      tokens: source,
+      position: 0,
+    }
+    .parse_all()?,
+  };
+
+  match &target {
+    Some(Target::Unit {
+      text, dimension, ..
+    }) if *dimension != value.dimension => {
+      return Err(format!(
+        "Can't convert {} to {}",
+        dimension_name(value.dimension),
+        text
+      ));
+    }
+    Some(Target::Base(_)) if value.dimension != DIMENSIONLESS => {
+      return Err("Only numbers without units can be shown in other bases".to_string());
+    }
+    Some(Target::Base(_)) if value.value.fract() != 0.0 || value.value.abs() > i64::MAX as f64 => {
+      return Err("Only whole numbers can be shown in other bases".to_string());
+    }
+    _ => (),
+  }
+
+  Ok(Quantity {
+    value: value.value,
+    dimension: value.dimension,
+    target,
+  })
+}
+
+/// A temperature on its own is a point on the scale, in calculations it's a difference
+fn is_point(source: &[Token]) -> bool {
+  match source {
+    [Token::Number(_), Token::Ident(name)]
+    | [Token::Op('-'), Token::Number(_), Token::Ident(name)] => {
+      find_unit(name).is_some_and(|u| u.offset != 0.0)
+    }
+    _ => false,
+  }
+}
+
+#[derive(Clone, Copy)]
+struct Value {
+  value: f64,
+  dimension: Dimension,
+}
+
+struct Parser<'a> {
+  tokens: &'a [Token],
+  position: usize,
+}
+
+impl Parser<'_> {
+  fn parse_all(&mut self) -> Result<Value, String> {
+    let value = self.parse_sum()?;
+    match self.tokens.get(self.position) {
+      None => Ok(value),
+      Some(_) => Err("Unexpected input after the end of the expression".to_string()),
+    }
+  }
+
+  fn peek(&self) -> Option<&Token> {
+    self.tokens.get(self.position)
+  }
+
+  fn parse_sum(&mut self) -> Result<Value, String> {
+    let mut value = self.parse_product()?;
+    while let Some(Token::Op(op @ ('+' | '-'))) = self.peek().cloned() {
+      self.position += 1;
+      let rhs = self.parse_product()?;
+      if rhs.dimension != value.dimension {
+        return Err(format!(
+          "Can't {} {} and {}",
+          match op {
+            '+' => "add",
+            _ => "subtract",
+          },
+          dimension_name(value.dimension),
+          dimension_name(rhs.dimension)
+        ));
+      }
+      value.value = match op {
+        '+' => value.value + rhs.value,
+        _ => value.value - rhs.value,
+      };
+    }
+    Ok(value)
+  }
+
+  fn parse_product(&mut self) -> Result<Value, String> {
+    let mut value = self.parse_term()?;
+    while let Some(Token::Op(op @ ('*' | '/'))) = self.peek().cloned() {
+      self.position += 1;
+      let rhs = self.parse_term()?;
+      value = match op {
+        '*' => multiply(value, rhs, 1)?,
+        _ => multiply(value, rhs, -1)?,
+      };
+    }
+    Ok(value)
+  }
+
+  /// Values next to each other are multiplied first, so 2 GiB / 30 s divides by 30 s
+  fn parse_term(&mut self) -> Result<Value, String> {
+    let mut value = self.parse_power()?;
+    while matches!(
+      self.peek(),
+      Some(Token::Number(_) | Token::BaseNumber(_) | Token::Ident(_) | Token::Op('('))
+    ) {
+      let rhs = self.parse_power()?;
+      value = multiply(value, rhs, 1)?;
+    }
+    Ok(value)
+  }
+
+  fn parse_power(&mut self) -> Result<Value, String> {
+    let base = self.parse_unary()?;
+    if self.peek() != Some(&Token::Op('^')) {
+      return Ok(base);
+    }
+    self.position += 1;
+    let exponent = self.parse_power()?;
+    if exponent.dimension != DIMENSIONLESS {
+      return Err("Exponents can't have units".to_string());
+    }
+
+    let mut dimension = base.dimension;
+    if dimension != DIMENSIONLESS {
+      if exponent.value.fract() != 0.0 || exponent.value.abs() > i8::MAX as f64 {
+        return Err("Units can only be raised to small whole powers".to_string());
+      }
+      for d in dimension.iter_mut() {
+        *d = d
+          .checked_mul(exponent.value as i8)
+          .ok_or("Units can only be raised to small whole powers")?;
+      }
+    }
+    Ok(Value {
+      value: base.value.powf(exponent.value),
+      dimension,
+    })
+  }
+
+  fn parse_unary(&mut self) -> Result<Value, String> {
+    match self.peek().cloned() {
+      Some(Token::Op('-')) => {
+        self.position += 1;
+        let value = self.parse_unary()?;
+        Ok(Value {
+          value: -value.value,
+          ..value
+        })
+      }
+      Some(Token::Op('+')) => {
+        self.position += 1;
+        self.parse_unary()
+      }
+      _ => self.parse_atom(),
+    }
+  }
+
+  fn parse_atom(&mut self) -> Result<Value, String> {
+    let token = self.peek().cloned();
+    self.position += 1;
+    match token {
+      Some(Token::Number(n) | Token::BaseNumber(n)) => Ok(Value {
+        value: n,
+        dimension: DIMENSIONLESS,
+      }),
+      Some(Token::Ident(name)) => match find_unit(&name) {
+        Some(u) => Ok(Value {
+          value: u.factor,
+          dimension: u.dimension,
+        }),
+        None => Err(format!("Unknown unit {}", name)),
+      },
+      Some(Token::Op('(')) => {
+        let value = self.parse_sum()?;
+        match self.peek() {
+          Some(Token::Op(')')) => {
+            self.position += 1;
+            Ok(value)
+          }
+          _ => Err("Missing closing parenthesis".to_string()),
+        }
+      }
+      Some(_) => Err("Unexpected operator".to_string()),
+      None => Err("Unexpected end of the expression".to_string()),
+    }
+  }
+}
+
+/// Multiplies `a` by `b` raised to `sign`, which divides for -1
+fn multiply(a: Value, b: Value, sign: i8) -> Result<Value, String> {
+  let mut dimension = a.dimension;
+  for (d, other) in dimension.iter_mut().zip(b.dimension) {
+    *d = d
+      .checked_add(other * sign)
+      .ok_or("Units are raised to too high powers")?;
+  }
+  Ok(Value {
+    value: match sign {
+      1 => a.value * b.value,
+      _ => a.value / b.value,
+    },
+    dimension,
+  })
+}
+
+fn find_unit(name: &str) -> Option<&'static Unit> {
+  UNITS.iter().find(|u| u.names.contains(&name))
+}
+
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+  let chars = expr.chars().collect::<Vec<_>>();
+  let mut tokens = vec![];
+  let mut i = 0;
+
+  while i < chars.len() {
+    let c = chars[i];
+    if c.is_whitespace() {
+      i += 1;
+    } else if c == '-' && chars.get(i + 1) == Some(&'>') {
+      tokens.push(Token::Conversion);
+      i += 2;
+    } else if c == '0' && matches!(chars.get(i + 1), Some('x' | 'b' | 'o')) {
+      let radix = match chars[i + 1] {
+        'x' => 16,
+        'b' => 2,
+        _ => 8,
+      };
+      let start = i + 2;
+      let mut end = start;
+      while end < chars.len() && (chars[end].is_digit(radix) || chars[end] == '_') {
+        end += 1;
+      }
+      let digits = chars[start..end]
+        .iter()
+        .filter(|c| **c != '_')
+        .collect::<String>();
+      tokens.push(Token::BaseNumber(
+        i64::from_str_radix(&digits, radix).ok()? as f64
+      ));
+      i = end;
+    } else if c.is_ascii_digit()
+      || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit()))
+    {
+      let start = i;
+      while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.' || chars[i] == '_') {
+        i += 1;
+      }
+      // Exponent, unless the e starts a unit like eV
+      if i < chars.len()
+        && (chars[i] == 'e' || chars[i] == 'E')
+        && (chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())
+          || (matches!(chars.get(i + 1), Some('+' | '-'))
+            && chars.get(i + 2).is_some_and(|c| c.is_ascii_digit())))
+      {
+        i += 2;
+        while i < chars.len() && chars[i].is_ascii_digit() {
+          i += 1;
+        }
+      }
+      let number = chars[start..i]
+        .iter()
+        .filter(|c| **c != '_')
+        .collect::<String>();
+      tokens.push(Token::Number(number.parse().ok()?));
+    } else if c.is_alphabetic() || c == '°' || c == 'µ' || c == '_' {
+      let start = i;
+      while i < chars.len()
+        && (chars[i].is_alphanumeric() || matches!(chars[i], '°' | 'µ' | '_' | ':'))
+      {
+        i += 1;
+      }
+      let name = chars[start..i].iter().collect::<String>();
+      match CONVERSION_KEYWORDS.contains(&name.as_str()) {
+        true => tokens.push(Token::Conversion),
+        false => tokens.push(Token::Ident(name)),
+      }
+    } else if "+-*/^()".contains(c) {
+      tokens.push(Token::Op(c));
+      i += 1;
+    } else {
+      // Anything else is for evalexpr
+      return None;
+    }
+  }
+
+  match tokens.iter().filter(|t| **t == Token::Conversion).count() {
+    0 | 1 => Some(tokens),
+    _ => None,
+  }
+}
+
+fn target_text(tokens: &[Token]) -> String {
+  let mut text = String::new();
+  for token in tokens {
+    match token {
+      Token::Number(n) | Token::BaseNumber(n) => text.push_str(&format_number(*n)),
+      Token::Ident(name) => {
+        if text.ends_with(|c: char| c.is_alphanumeric()) {
+          text.push('·');
+        }
+        text.push_str(name);
+      }
+      Token::Op(c) => text.push(*c),
+      Token::Conversion => (),
+    }
+  }
+  text
+}
+
+/// Canonical unit for a dimension, composed from the base units if it has no name
+fn dimension_name(dimension: Dimension) -> String {
+  if dimension == DIMENSIONLESS {
+    return "a number".to_string();
+  }
+  if let Some((name, _)) = CANONICAL_NAMES.iter().find(|(_, d)| *d == dimension) {
+    return name.to_string();
+  }
+
+  let part = |unit: &str, power: i8| match power {
+    1 => unit.to_string(),
+    p => format!("{}^{}", unit, p),
+  };
+  let numerator = BASE_UNITS
+    .iter()
+    .zip(dimension)
+    .filter(|(_, p)| *p > 0)
+    .map(|(u, p)| part(u, p))
+    .collect::<Vec<_>>();
+  let denominator = BASE_UNITS
+    .iter()
+    .zip(dimension)
+    .filter(|(_, p)| *p < 0)
+    .map(|(u, p)| part(u, -p))
+    .collect::<Vec<_>>();
+
+  match (numerator.is_empty(), denominator.is_empty()) {
+    (_, true) => numerator.join("·"),
+    (true, false) => format!("1/{}", denominator.join("·")),
+    (false, false) => format!("{}/{}", numerator.join("·"), denominator.join("·")),
+  }
+}
+
+/// Sizes in the largest binary unit they have at least one of
+fn format_data(bytes: f64) -> String {
+  let units = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+  let mut value = bytes;
+  let mut unit = 0;
+  while value.abs() >= 1024.0 && unit < units.len() - 1 {
+    value /= 1024.0;
+    unit += 1;
+  }
+  format!(
+    "{} {}",
+    format_number((value * 100.0).round() / 100.0),
+    units[unit]
+  )
+}
+
+impl Quantity {
+  /// The result as shown, in the unit or base it was converted to
+  pub fn shown(&self) -> evalexpr::Value {
+    match &self.target {
+      Some(Target::Base(_)) => evalexpr::Value::Int(self.value as i64),
+      Some(Target::Unit { factor, offset, .. }) => {
+        evalexpr::Value::Float((self.value - offset) / factor)
+      }
+      None => evalexpr::Value::Float(self.value),
+    }
+  }
+}
+
+/// Numbers are rounded to the formatter's precision, like `{:.3}`
+impl std::fmt::Display for Quantity {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let precision = f.precision().unwrap_or(DEFAULT_PRECISION);
+    let number = |n: f64| format_float(n, precision);
+    let canonical = match self.dimension == DIMENSIONLESS {
+      true => number(self.value),
+      false => format!("{} {}", number(self.value), dimension_name(self.dimension)),
+    };
+
+    match &self.target {
+      Some(Target::Base(base)) => {
+        let n = self.value as i64;
+        let sign = if n < 0 { "-" } else { "" };
+        let n = n.unsigned_abs();
+        match base {
+          Base::Binary => write!(f, "{}0b{:b}", sign, n),
+          Base::Octal => write!(f, "{}0o{:o}", sign, n),
+          Base::Decimal => write!(f, "{}{}", sign, n),
+          Base::Hexadecimal => write!(f, "{}0x{:x}", sign, n),
+        }
+      }
+      Some(Target::Unit { text, .. }) if *text == dimension_name(self.dimension) => {
+        write!(f, "{}", canonical)
+      }
+      Some(Target::Unit {
+        text,
+        factor,
+        offset,
+        ..
+      }) => write!(
+        f,
+        "{} {}\n= {}",
+        number((self.value - offset) / factor),
+        text,
+        canonical
+      ),
+      None if self.dimension == TIME && self.value >= 1.0 && self.value < u64::MAX as f64 => {
+        write!(
+          f,
+          "{}\n= {}",
+          format_duration(Duration::from_secs_f64(self.value)),
+          canonical
+        )
+      }
+      None if self.dimension == DATA && self.value.abs() >= 1024.0 => {
+        write!(f, "{}\n= {}", format_data(self.value), canonical)
+      }
+      None => write!(f, "{}", canonical),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn quantity(expr: &str) -> Quantity {
+    match evaluate(expr, |_| false) {
+      Some(Ok(q)) => q,
+      Some(Err(e)) => panic!("{} failed: {}", expr, e),
+      None => panic!("{} was left to evalexpr", expr),
+    }
+  }
+
+  fn shown_float(expr: &str) -> f64 {
+    match quantity(expr).shown() {
+      evalexpr::Value::Float(f) => f,
+      v => panic!("{} gave {:?}", expr, v),
+    }
+  }
+
+  fn assert_close(a: f64, b: f64) {
+    assert!((a - b).abs() <= b.abs() * 1e-9, "{} != {}", a, b);
+  }
+
+  #[test]
+  fn converts_lengths() {
+    assert_close(shown_float("5 km to mi"), 5000.0 / 1609.344);
+    assert_eq!(quantity("5 km to mi").to_string(), "3.106856 mi\n= 5000 m");
+  }
+
+  #[test]
+  fn converts_temperatures_on_their_scales() {
+    assert_close(shown_float("70 F in C"), 21.111111111111);
+    assert_close(shown_float("-40 F to C"), -40.0);
+    assert_eq!(quantity("-40 F to C").to_string(), "-40 C\n= 233.15 K");
+  }
+
+  #[test]
+  fn divides_data_by_time() {
+    assert_close(shown_float("2 GiB / 30 s"), 2.0 * 1073741824.0 / 30.0);
+    assert_eq!(quantity("2 GiB / 30 s").to_string(), "71582788.266667 B/s");
+  }
+
+  #[test]
+  fn converts_between_bases() {
+    assert_eq!(quantity("255 to hex").to_string(), "0xff");
+    assert_eq!(quantity("-10 to bin").to_string(), "-0b1010");
+    assert_eq!(quantity("0xff to dec").shown(), evalexpr::Value::Int(255));
+    assert_eq!(quantity("0x1f + 0b11").to_string(), "34");
+  }
+
+  #[test]
+  fn tells_exponents_from_electronvolts() {
+    assert_close(shown_float("1e3 m to km"), 1.0);
+    assert_close(shown_float("2e-3 eV to J"), 2e-3 * 1.602176634e-19);
+    assert_close(shown_float("1eV to J"), 1.602176634e-19);
+    assert!(evaluate("2e3 + 1", |_| false).is_none());
+  }
+
+  #[test]
+  fn rejects_mismatched_dimensions() {
+    assert_eq!(
+      evaluate("5 m to s", |_| false).map(|r| r.err()),
+      Some(Some("Can't convert m to s".to_string()))
+    );
+    assert_eq!(
+      evaluate("3 kg + 2 m", |_| false).map(|r| r.err()),
+      Some(Some("Can't add kg and m".to_string()))
+    );
+    assert!(matches!(evaluate("1.5 to hex", |_| false), Some(Err(_))));
+    assert!(matches!(evaluate("5 m to hex", |_| false), Some(Err(_))));
+  }
+
+  #[test]
+  fn leaves_variables_and_functions_to_evalexpr() {
+    assert!(evaluate("x + 1 m", |n| n == "x").is_none());
+    assert!(evaluate("min(1, 2)", |_| false).is_none());
+    assert!(evaluate("2 + 3", |_| false).is_none());
+  }
+}
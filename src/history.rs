@@ -0,0 +1,40 @@
+use crate::commands::{Requester, SongMetadata};
+use crate::storage::Persisted;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+use serenity::prelude::{RwLock, TypeMapKey};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+pub const HISTORY_LENGTH: usize = 100;
+
+pub struct HistoryStorage;
+
+impl TypeMapKey for HistoryStorage {
+  type Value = Arc<RwLock<Persisted<History>>>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+  pub metadata: SongMetadata,
+  pub requester: Option<Requester>,
+  pub played_at: i64,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct History {
+  guilds: HashMap<GuildId, VecDeque<HistoryEntry>>,
+}
+
+impl History {
+  pub fn push(&mut self, guild_id: GuildId, entry: HistoryEntry) {
+    let history = self.guilds.entry(guild_id).or_default();
+    history.push_front(entry);
+    history.truncate(HISTORY_LENGTH);
+  }
+
+  /// Most recently played first.
+  pub fn get(&self, guild_id: GuildId) -> impl Iterator<Item = &HistoryEntry> {
+    self.guilds.get(&guild_id).into_iter().flatten()
+  }
+}
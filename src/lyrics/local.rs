@@ -0,0 +1,73 @@
+use crate::lyrics::{normalize, Lyrics, LyricsProvider, LyricsQuery};
+use serenity::async_trait;
+use std::path::PathBuf;
+use tracing::error;
+
+/// Lyrics from `.lrc` and `.txt` files named after the track, like "Artist - Title.lrc"
+pub struct LocalProvider {
+  pub dir: PathBuf,
+}
+
+#[async_trait]
+impl LyricsProvider for LocalProvider {
+  fn name(&self) -> &'static str {
+    "Local files"
+  }
+
+  async fn find(&self, query: &LyricsQuery) -> Option<Lyrics> {
+    let mut entries = match tokio::fs::read_dir(&self.dir).await {
+      Ok(e) => e,
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+      Err(e) => {
+        error!("Error reading {}: {}", self.dir.display(), e);
+        return None;
+      }
+    };
+
+    let title = normalize(&query.title);
+    let artist = query.artist.as_deref().map(normalize);
+
+    // Files naming the artist too are a better match than ones only matching the title
+    let mut best: Option<(bool, PathBuf)> = None;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+      let path = entry.path();
+      let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+      if !matches!(extension, "lrc" | "txt") {
+        continue;
+      }
+
+      let stem = normalize(
+        path
+          .file_stem()
+          .and_then(|s| s.to_str())
+          .unwrap_or_default(),
+      );
+      if title.is_empty() || !stem.contains(&title) {
+        continue;
+      }
+      let artist_match = artist.as_ref().is_some_and(|a| stem.contains(a));
+      let better = match &best {
+        Some((best_artist_match, _)) => artist_match && !best_artist_match,
+        None => true,
+      };
+      if better {
+        best = Some((artist_match, path));
+      }
+    }
+
+    let path = best?.1;
+    match tokio::fs::read_to_string(&path).await {
+      Ok(text) => match path.extension().and_then(|e| e.to_str()) {
+        Some("lrc") => Some(Lyrics::from_lrc(&text, self.name())),
+        _ => Some(Lyrics::plain(&text, self.name())),
+      },
+      Err(e) => {
+        error!("Error reading {}: {}", path.display(), e);
+        None
+      }
+    }
+  }
+}
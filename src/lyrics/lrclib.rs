@@ -0,0 +1,86 @@
+use crate::constants::HttpClient;
+use crate::lyrics::{Lyrics, LyricsProvider, LyricsQuery};
+use serde::Deserialize;
+use serenity::async_trait;
+use tracing::error;
+
+pub const DEFAULT_API: &str = "https://lrclib.net";
+/// Seconds a result's length can differ from the track's and still be the same recording
+const MAX_DURATION_DIFFERENCE: f64 = 5.0;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Track {
+  duration: Option<f64>,
+  plain_lyrics: Option<String>,
+  synced_lyrics: Option<String>,
+}
+
+/// Lyrics from an LRCLIB compatible API, synced when available
+pub struct LrclibProvider {
+  pub client: HttpClient,
+  pub api: String,
+}
+
+#[async_trait]
+impl LyricsProvider for LrclibProvider {
+  fn name(&self) -> &'static str {
+    "LRCLIB"
+  }
+
+  async fn find(&self, query: &LyricsQuery) -> Option<Lyrics> {
+    let mut params = vec![("track_name", query.title.clone())];
+    if let Some(artist) = &query.artist {
+      params.push(("artist_name", artist.clone()));
+    }
+
+    let response = self
+      .client
+      .get(format!("{}/api/search", self.api.trim_end_matches('/')))
+      .query(&params)
+      .send()
+      .await;
+    let body = match response.and_then(|r| r.error_for_status()) {
+      Ok(r) => r.bytes().await,
+      Err(e) => Err(e),
+    };
+    let tracks = match body {
+      Ok(b) => match serde_json::from_slice::<Vec<Track>>(&b) {
+        Ok(t) => t,
+        Err(e) => {
+          error!("Error parsing lyrics for {}: {}", query, e);
+          return None;
+        }
+      },
+      Err(e) => {
+        error!("Error getting lyrics for {}: {}", query, e);
+        return None;
+      }
+    };
+
+    let length = query.duration.as_secs_f64();
+    let same_length = |t: &&Track| {
+      length == 0.0
+        || t
+          .duration
+          .is_some_and(|d| (d - length).abs() <= MAX_DURATION_DIFFERENCE)
+    };
+    let track = tracks
+      .iter()
+      .filter(same_length)
+      .find(|t| t.synced_lyrics.is_some())
+      .or_else(|| {
+        tracks
+          .iter()
+          .filter(same_length)
+          .find(|t| t.plain_lyrics.is_some())
+      })
+      .or_else(|| tracks.iter().find(|t| t.plain_lyrics.is_some()))?;
+
+    match (&track.synced_lyrics, &track.plain_lyrics) {
+      (Some(synced), _) => Some(Lyrics::from_lrc(synced, self.name())),
+      (None, Some(plain)) => Some(Lyrics::plain(plain, self.name())),
+      (None, None) => None,
+    }
+  }
+}
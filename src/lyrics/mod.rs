@@ -0,0 +1,251 @@
+use crate::commands::SongMetadata;
+use regex::Regex;
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use serenity::prelude::{Mutex, TypeMapKey};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::AbortHandle;
+
+mod local;
+mod lrclib;
+
+pub use local::LocalProvider;
+pub use lrclib::{LrclibProvider, DEFAULT_API};
+
+/// Where lyrics are looked up, in order
+pub struct LyricsProviders;
+
+impl TypeMapKey for LyricsProviders {
+  type Value = Arc<Vec<Box<dyn LyricsProvider>>>;
+}
+
+/// Synced lyrics messages being updated, so a new one replaces the old one
+pub struct LyricsSessions;
+
+impl TypeMapKey for LyricsSessions {
+  type Value = Arc<Mutex<HashMap<GuildId, AbortHandle>>>;
+}
+
+#[async_trait]
+pub trait LyricsProvider: Send + Sync {
+  fn name(&self) -> &'static str;
+  async fn find(&self, query: &LyricsQuery) -> Option<Lyrics>;
+}
+
+pub struct LyricsQuery {
+  pub artist: Option<String>,
+  pub title: String,
+  pub duration: Duration,
+}
+
+impl LyricsQuery {
+  pub fn from_metadata(metadata: &SongMetadata) -> Self {
+    let title = clean_title(&metadata.title);
+    let (artist, title) = match title.split_once(" - ") {
+      Some((artist, title)) => (Some(artist.trim().to_string()), title.trim().to_string()),
+      None => (None, title),
+    };
+
+    Self {
+      artist,
+      title,
+      duration: metadata.duration,
+    }
+  }
+}
+
+impl std::fmt::Display for LyricsQuery {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self.artist {
+      Some(artist) => write!(f, "{} - {}", artist, self.title),
+      None => write!(f, "{}", self.title),
+    }
+  }
+}
+
+pub struct Line {
+  /// When the line starts, only for synced lyrics
+  pub time: Option<Duration>,
+  pub text: String,
+}
+
+pub struct Lyrics {
+  pub lines: Vec<Line>,
+  /// Name of the provider the lyrics came from
+  pub source: &'static str,
+}
+
+impl Lyrics {
+  /// Plain lyrics, one line per line of text
+  pub fn plain(text: &str, source: &'static str) -> Self {
+    Self {
+      lines: text
+        .lines()
+        .map(|l| Line {
+          time: None,
+          text: l.trim().to_string(),
+        })
+        .collect(),
+      source,
+    }
+  }
+
+  /// Lyrics in the LRC format, plain if none of the lines are timed
+  pub fn from_lrc(lrc: &str, source: &'static str) -> Self {
+    let tag = Regex::new(r"^\[(\d+):(\d+(?:\.\d+)?)\]").expect("Failed to compile regex");
+    let offset = Regex::new(r"^\[offset:\s*([+-]?\d+)\]").expect("Failed to compile regex");
+
+    // Positive offsets make the lyrics come sooner
+    let mut offset_ms = 0i64;
+    let mut lines = vec![];
+    for line in lrc.lines() {
+      let mut rest = line.trim();
+      if let Some(c) = offset.captures(rest) {
+        offset_ms = c[1].parse().unwrap_or(0);
+        continue;
+      }
+
+      let mut times = vec![];
+      while let Some(c) = tag.captures(rest) {
+        let minutes = c[1].parse::<f64>().unwrap_or(0.0);
+        let seconds = c[2].parse::<f64>().unwrap_or(0.0);
+        times.push(minutes * 60.0 + seconds);
+        rest = rest[c[0].len()..].trim_start();
+      }
+      for time in times {
+        lines.push((time, rest.to_string()));
+      }
+    }
+
+    if lines.is_empty() {
+      return Self::plain(lrc, source);
+    }
+
+    lines.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Self {
+      lines: lines
+        .into_iter()
+        // Times too large for a Duration can only come from a broken file, so those lines are skipped
+        .filter_map(|(time, text)| {
+          let time = Duration::try_from_secs_f64((time - offset_ms as f64 / 1000.0).max(0.0));
+          Some(Line {
+            time: Some(time.ok()?),
+            text,
+          })
+        })
+        .collect(),
+      source,
+    }
+  }
+
+  pub fn is_synced(&self) -> bool {
+    self.lines.iter().any(|l| l.time.is_some())
+  }
+
+  /// Index of the line being sung at `position`
+  pub fn line_at(&self, position: Duration) -> Option<usize> {
+    self
+      .lines
+      .iter()
+      .rposition(|l| l.time.is_some_and(|t| t <= position))
+  }
+}
+
+/// Looks the lyrics up with each provider until one has them
+pub async fn find_lyrics(ctx: &Context, query: &LyricsQuery) -> Option<Lyrics> {
+  let providers = {
+    let data = ctx.data.read().await;
+    data
+      .get::<LyricsProviders>()
+      .cloned()
+      .expect("LyricsProviders did not exist")
+  };
+
+  for provider in providers.iter() {
+    if let Some(lyrics) = provider.find(query).await {
+      return Some(lyrics);
+    }
+  }
+  None
+}
+
+/// Title without the bracketed notes uploads tend to have, like "(Official Video)"
+pub fn clean_title(title: &str) -> String {
+  let noise = Regex::new(
+    r"(?i)\s*[(\[][^)\]]*\b(official|video|audio|lyrics?|visuali[sz]er|hd|hq|4k|remaster(ed)?|m/?v)\b[^)\]]*[)\]]",
+  )
+  .expect("Failed to compile regex");
+  let title = noise.replace_all(title, "");
+  let title = title.split(" | ").next().unwrap_or_default();
+  title.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Lowercase letters and numbers only, for comparing titles loosely
+fn normalize(text: &str) -> String {
+  text
+    .chars()
+    .filter(|c| c.is_alphanumeric() || c.is_whitespace())
+    .collect::<String>()
+    .to_lowercase()
+    .split_whitespace()
+    .collect::<Vec<_>>()
+    .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn times(lyrics: &Lyrics) -> Vec<Option<Duration>> {
+    lyrics.lines.iter().map(|l| l.time).collect()
+  }
+
+  #[test]
+  fn lrc_lines_with_several_tags_are_repeated_in_order() {
+    let lyrics = Lyrics::from_lrc("[00:10.50][01:00]Chorus\n[00:30]Verse", "test");
+    assert_eq!(
+      times(&lyrics),
+      vec![
+        Some(Duration::from_millis(10_500)),
+        Some(Duration::from_secs(30)),
+        Some(Duration::from_secs(60)),
+      ]
+    );
+    let texts = lyrics
+      .lines
+      .iter()
+      .map(|l| l.text.as_str())
+      .collect::<Vec<_>>();
+    assert_eq!(texts, vec!["Chorus", "Verse", "Chorus"]);
+  }
+
+  #[test]
+  fn lrc_offset_moves_lines_sooner() {
+    let lyrics = Lyrics::from_lrc("[offset:+500]\n[00:01]One\n[00:00.20]Zero", "test");
+    assert_eq!(
+      times(&lyrics),
+      vec![Some(Duration::ZERO), Some(Duration::from_millis(500))]
+    );
+
+    let lyrics = Lyrics::from_lrc("[offset:-1000]\n[00:01]One", "test");
+    assert_eq!(times(&lyrics), vec![Some(Duration::from_secs(2))]);
+  }
+
+  #[test]
+  fn untimed_lrc_falls_back_to_plain() {
+    let lyrics = Lyrics::from_lrc("[ar:Someone]\nFirst\nSecond", "test");
+    assert!(!lyrics.is_synced());
+    assert_eq!(lyrics.lines.len(), 3);
+    assert_eq!(lyrics.lines[1].text, "First");
+  }
+
+  #[test]
+  fn lrc_times_too_large_are_skipped() {
+    let lyrics = Lyrics::from_lrc("[99999999999999999999:00]Never\n[00:05]Soon", "test");
+    assert_eq!(times(&lyrics), vec![Some(Duration::from_secs(5))]);
+    assert_eq!(lyrics.lines[0].text, "Soon");
+  }
+}
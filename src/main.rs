@@ -2,21 +2,36 @@ use serenity::async_trait;
 use serenity::client::{Client, Context, EventHandler};
 use serenity::gateway::ActivityData;
 use serenity::model::{application::Interaction, prelude::*};
+use serenity::prelude::{Mutex, RwLock};
 use songbird::SerenityInit;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tracing::{error, info};
 
+mod assets;
+mod audio;
 mod commands;
 mod config;
 mod constants;
+mod eval;
+mod history;
+mod lyrics;
+mod metadata_cache;
+mod playlists;
+mod segments;
+mod settings;
+mod storage;
 
 struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
   async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-    if let Interaction::Command(command) = interaction {
-      commands::handle_commands(&ctx, command).await;
+    match interaction {
+      Interaction::Command(command) => commands::handle_commands(&ctx, command).await,
+      Interaction::Autocomplete(command) => commands::handle_autocomplete(&ctx, command).await,
+      Interaction::Component(component) => commands::handle_components(&ctx, component).await,
+      _ => (),
     }
   }
 
@@ -43,11 +58,52 @@ async fn main() {
 
   info!("Intents: {:?}", intents);
 
+  let playlists = storage::Persisted::load(config.data_dir.join("playlists.json"));
+  let history = storage::Persisted::load(config.data_dir.join("history.json"));
+  let settings = storage::Persisted::load(config.data_dir.join("settings.json"));
+  let loudness = storage::Persisted::load(config.data_dir.join("loudness.json"));
+  let eval_variables = storage::Persisted::load(config.data_dir.join("eval_variables.json"));
+  let metadata_cache = match config.persist_metadata_cache {
+    true => storage::Persisted::load(config.data_dir.join("metadata_cache.json")),
+    false => storage::Persisted::memory(),
+  };
+  let audio_cache = audio::cache::AudioCache::new(
+    config.data_dir.join("audio"),
+    config.audio_cache_size,
+    config.audio_cache_min_plays,
+  );
+
+  let http_client = constants::HttpClient::new();
+  tokio::spawn(assets::check(&config, http_client.clone()));
+  let mut lyrics_providers: Vec<Box<dyn lyrics::LyricsProvider>> =
+    vec![Box::new(lyrics::LocalProvider {
+      dir: config.lyrics_dir.clone(),
+    })];
+  if let Some(api) = config.lyrics_api.clone() {
+    lyrics_providers.push(Box::new(lyrics::LrclibProvider {
+      client: http_client.clone(),
+      api,
+    }));
+  }
+
   let mut client = Client::builder(config.token.clone(), intents)
     .event_handler(Handler)
     .application_id(config.application_id)
     .register_songbird()
-    .type_map_insert::<constants::HttpKey>(constants::HttpClient::new())
+    .type_map_insert::<constants::HttpKey>(http_client)
+    .type_map_insert::<playlists::PlaylistStorage>(Arc::new(RwLock::new(playlists)))
+    .type_map_insert::<history::HistoryStorage>(Arc::new(RwLock::new(history)))
+    .type_map_insert::<settings::SettingsStorage>(Arc::new(RwLock::new(settings)))
+    .type_map_insert::<audio::loudness::LoudnessStorage>(Arc::new(RwLock::new(loudness)))
+    .type_map_insert::<metadata_cache::MetadataCacheStorage>(Arc::new(RwLock::new(metadata_cache)))
+    .type_map_insert::<audio::cache::AudioCacheStorage>(Arc::new(RwLock::new(audio_cache)))
+    .type_map_insert::<audio::filters::FilterStorage>(Arc::new(Mutex::new(HashMap::new())))
+    .type_map_insert::<lyrics::LyricsProviders>(Arc::new(lyrics_providers))
+    .type_map_insert::<lyrics::LyricsSessions>(Arc::new(Mutex::new(HashMap::new())))
+    .type_map_insert::<eval::EvalContextStorage>(Arc::new(Mutex::new(eval::EvalContexts::new(
+      eval_variables,
+    ))))
+    .type_map_insert::<commands::ticker::TickerStorage>(Arc::new(Mutex::new(HashMap::new())))
     .type_map_insert::<config::ConfigStorage>(Arc::new(config))
     .await
     .expect("Error creating client");
@@ -0,0 +1,227 @@
+use crate::commands::SongMetadata;
+use crate::storage::Persisted;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::prelude::{RwLock, TypeMapKey};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub const CACHE_CAPACITY: usize = 500;
+/// Seconds resolved metadata is trusted for
+const METADATA_TTL: i64 = 7 * 24 * 60 * 60;
+/// Seconds a stream URL is trusted for when it doesn't say when it expires
+const STREAM_TTL: i64 = 60 * 60;
+/// Seconds before a stream URL's own expiry it stops being used, so it doesn't run out mid-track
+const STREAM_EXPIRY_MARGIN: i64 = 30 * 60;
+
+pub struct MetadataCacheStorage;
+
+impl TypeMapKey for MetadataCacheStorage {
+  type Value = Arc<RwLock<Persisted<MetadataCache>>>;
+}
+
+/// Direct media URL from yt-dlp, playable without running it again until it expires
+#[derive(Clone, Serialize, Deserialize)]
+pub struct StreamInfo {
+  pub url: String,
+  pub headers: HashMap<String, String>,
+  pub content_length: Option<u64>,
+  pub expires_at: i64,
+}
+
+impl StreamInfo {
+  pub fn new(
+    url: String,
+    headers: HashMap<String, String>,
+    content_length: Option<u64>,
+    now: i64,
+  ) -> Self {
+    // As a query parameter `expire=<unix time>`, or in the path as `/expire/<unix time>/`
+    let parts = url.split(['?', '&', '/']).collect::<Vec<_>>();
+    let expires_at = parts
+      .iter()
+      .enumerate()
+      .find_map(|(i, p)| match p.strip_prefix("expire=") {
+        Some(e) => Some(e),
+        None if *p == "expire" => parts.get(i + 1).copied(),
+        None => None,
+      })
+      .and_then(|e| e.parse::<i64>().ok())
+      .map_or(now + STREAM_TTL, |e| e - STREAM_EXPIRY_MARGIN);
+
+    Self {
+      url,
+      headers,
+      content_length,
+      expires_at,
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+  pub metadata: SongMetadata,
+  pub stream: Option<StreamInfo>,
+  pub resolved_at: i64,
+  #[serde(default)]
+  last_used: u64,
+}
+
+impl CacheEntry {
+  pub fn new(metadata: SongMetadata, stream: Option<StreamInfo>, now: i64) -> Self {
+    Self {
+      metadata,
+      stream,
+      resolved_at: now,
+      last_used: 0,
+    }
+  }
+}
+
+/// Least recently used cache of resolved tracks by URL, with search queries pointing to them
+#[derive(Default, Serialize, Deserialize)]
+pub struct MetadataCache {
+  entries: HashMap<String, CacheEntry>,
+  queries: HashMap<String, String>,
+  uses: u64,
+}
+
+impl MetadataCache {
+  /// Looks up a URL or search query, dropping whatever has expired
+  pub fn get(&mut self, query: &str, now: i64) -> Option<CacheEntry> {
+    let key = cache_key(query);
+    let url = self.queries.get(&key).cloned().unwrap_or(key);
+
+    let expired = match self.entries.get(&url) {
+      Some(entry) => now - entry.resolved_at > METADATA_TTL,
+      None => return None,
+    };
+    if expired {
+      self.remove(&url);
+      return None;
+    }
+
+    self.uses += 1;
+    let entry = self.entries.get_mut(&url)?;
+    entry.last_used = self.uses;
+    if entry.stream.as_ref().is_some_and(|s| s.expires_at <= now) {
+      entry.stream = None;
+    }
+    Some(entry.clone())
+  }
+
+  /// Caches a resolved track under its URL and the query that found it
+  pub fn insert(&mut self, query: &str, mut entry: CacheEntry) {
+    let url = match &entry.metadata.url {
+      Some(u) => u.clone(),
+      None => return,
+    };
+
+    self.uses += 1;
+    entry.last_used = self.uses;
+    let key = cache_key(query);
+    if key != url {
+      self.queries.insert(key, url.clone());
+    }
+    self.entries.insert(url, entry);
+
+    while self.entries.len() > CACHE_CAPACITY {
+      let oldest = self
+        .entries
+        .iter()
+        .min_by_key(|(_, e)| e.last_used)
+        .map(|(url, _)| url.clone());
+      match oldest {
+        Some(url) => self.remove(&url),
+        None => break,
+      }
+    }
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  /// Most recently used tracks whose title contains `text`
+  pub fn search(&self, text: &str, limit: usize) -> Vec<&SongMetadata> {
+    let text = text.trim().to_lowercase();
+    let mut matches = self
+      .entries
+      .values()
+      .filter(|e| e.metadata.title.to_lowercase().contains(&text))
+      .collect::<Vec<_>>();
+    matches.sort_by_key(|e| std::cmp::Reverse(e.last_used));
+    matches
+      .into_iter()
+      .take(limit)
+      .map(|e| &e.metadata)
+      .collect()
+  }
+
+  fn remove(&mut self, url: &str) {
+    self.entries.remove(url);
+    self.queries.retain(|_, u| u != url);
+  }
+}
+
+/// URLs are used as they are, search queries are matched regardless of case and spacing
+pub fn cache_key(query: &str) -> String {
+  let query = query.trim();
+  if query.contains("https://") {
+    query.to_string()
+  } else {
+    query
+      .split_whitespace()
+      .collect::<Vec<_>>()
+      .join(" ")
+      .to_lowercase()
+  }
+}
+
+pub async fn get_cache(ctx: &Context) -> Arc<RwLock<Persisted<MetadataCache>>> {
+  let data = ctx.data.read().await;
+  data
+    .get::<MetadataCacheStorage>()
+    .cloned()
+    .expect("MetadataCacheStorage did not exist")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NOW: i64 = 1_700_000_000;
+
+  fn expires_at(url: &str) -> i64 {
+    StreamInfo::new(url.to_string(), HashMap::new(), None, NOW).expires_at
+  }
+
+  #[test]
+  fn stream_expiry_is_read_from_the_query() {
+    assert_eq!(
+      expires_at("https://rr1.example.com/videoplayback?id=1&expire=1700020000&ip=1"),
+      1_700_020_000 - STREAM_EXPIRY_MARGIN
+    );
+  }
+
+  #[test]
+  fn stream_expiry_is_read_from_the_path() {
+    assert_eq!(
+      expires_at("https://manifest.example.com/api/manifest/hls/expire/1700020000/id/1/file.m3u8"),
+      1_700_020_000 - STREAM_EXPIRY_MARGIN
+    );
+  }
+
+  #[test]
+  fn streams_without_an_expiry_get_the_default() {
+    assert_eq!(
+      expires_at("https://example.com/audio.mp3"),
+      NOW + STREAM_TTL
+    );
+    assert_eq!(
+      expires_at("https://example.com/audio.mp3?expire=soon"),
+      NOW + STREAM_TTL
+    );
+    assert_eq!(expires_at("https://example.com/expire/"), NOW + STREAM_TTL);
+  }
+}
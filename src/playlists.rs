@@ -0,0 +1,83 @@
+use crate::commands::SongMetadata;
+use crate::storage::Persisted;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::{RwLock, TypeMapKey};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+pub struct PlaylistStorage;
+
+impl TypeMapKey for PlaylistStorage {
+  type Value = Arc<RwLock<Persisted<Playlists>>>;
+}
+
+#[derive(Clone, Copy)]
+pub enum PlaylistScope {
+  User(UserId),
+  Guild(GuildId),
+}
+
+impl std::fmt::Display for PlaylistScope {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Self::User(_) => write!(f, "your"),
+      Self::Guild(_) => write!(f, "the server's"),
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Playlist {
+  pub owner: UserId,
+  pub tracks: Vec<SongMetadata>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Playlists {
+  users: HashMap<UserId, BTreeMap<String, Playlist>>,
+  guilds: HashMap<GuildId, BTreeMap<String, Playlist>>,
+}
+
+impl Playlists {
+  fn scope(&self, scope: PlaylistScope) -> Option<&BTreeMap<String, Playlist>> {
+    match scope {
+      PlaylistScope::User(id) => self.users.get(&id),
+      PlaylistScope::Guild(id) => self.guilds.get(&id),
+    }
+  }
+
+  fn scope_mut(&mut self, scope: PlaylistScope) -> &mut BTreeMap<String, Playlist> {
+    match scope {
+      PlaylistScope::User(id) => self.users.entry(id).or_default(),
+      PlaylistScope::Guild(id) => self.guilds.entry(id).or_default(),
+    }
+  }
+
+  pub fn get(&self, scope: PlaylistScope, name: &str) -> Option<&Playlist> {
+    self.scope(scope).and_then(|s| s.get(&normalize_name(name)))
+  }
+
+  pub fn get_mut(&mut self, scope: PlaylistScope, name: &str) -> Option<&mut Playlist> {
+    self.scope_mut(scope).get_mut(&normalize_name(name))
+  }
+
+  pub fn insert(&mut self, scope: PlaylistScope, name: &str, playlist: Playlist) {
+    self.scope_mut(scope).insert(normalize_name(name), playlist);
+  }
+
+  pub fn remove(&mut self, scope: PlaylistScope, name: &str) -> Option<Playlist> {
+    self.scope_mut(scope).remove(&normalize_name(name))
+  }
+
+  pub fn list(&self, scope: PlaylistScope) -> Vec<(&String, &Playlist)> {
+    self
+      .scope(scope)
+      .map(|s| s.iter().collect())
+      .unwrap_or_default()
+  }
+}
+
+fn normalize_name(name: &str) -> String {
+  name.trim().to_lowercase()
+}
@@ -0,0 +1,283 @@
+use crate::audio::filters::{played_time, track_position};
+use crate::commands::format_duration;
+use crate::constants::HttpClient;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use serenity::client::Context;
+use serenity::model::id::GuildId;
+use songbird::{events::Event, tracks::TrackHandle, typemap, EventContext, EventHandler};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use tracing::error;
+
+pub const DEFAULT_API: &str = "https://sponsor.ajay.app";
+/// How long a now playing message waits for the segments before leaving them out
+const NOTE_TIMEOUT: Duration = Duration::from_secs(2);
+/// Segments ending this close to the position aren't worth seeking past
+const MIN_SKIP: Duration = Duration::from_millis(500);
+
+/// SponsorBlock segment categories
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Category {
+  Sponsor,
+  Selfpromo,
+  Interaction,
+  Intro,
+  Outro,
+  Preview,
+  MusicOfftopic,
+  Filler,
+}
+
+impl Category {
+  pub const ALL: [Category; 8] = [
+    Self::Sponsor,
+    Self::Selfpromo,
+    Self::Interaction,
+    Self::Intro,
+    Self::Outro,
+    Self::Preview,
+    Self::MusicOfftopic,
+    Self::Filler,
+  ];
+
+  /// Name used by the API
+  pub fn id(&self) -> &'static str {
+    match self {
+      Self::Sponsor => "sponsor",
+      Self::Selfpromo => "selfpromo",
+      Self::Interaction => "interaction",
+      Self::Intro => "intro",
+      Self::Outro => "outro",
+      Self::Preview => "preview",
+      Self::MusicOfftopic => "music_offtopic",
+      Self::Filler => "filler",
+    }
+  }
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Self::Sponsor => "Sponsor",
+      Self::Selfpromo => "Self promotion",
+      Self::Interaction => "Interaction reminder",
+      Self::Intro => "Intro",
+      Self::Outro => "Outro",
+      Self::Preview => "Preview",
+      Self::MusicOfftopic => "Non-music section",
+      Self::Filler => "Filler",
+    }
+  }
+}
+
+#[derive(Clone, Deserialize)]
+struct ApiSegment {
+  segment: [f64; 2],
+  category: Category,
+  #[serde(rename = "actionType")]
+  action_type: String,
+}
+
+#[derive(Clone, Copy)]
+pub struct Segment {
+  pub start: Duration,
+  pub end: Duration,
+  pub category: Category,
+}
+
+/// Segments of a track, fetched by whichever needs them first
+pub struct TrackSegments {
+  segments: OnceCell<Vec<Segment>>,
+  client: HttpClient,
+  api: String,
+  video_id: String,
+  categories: Vec<Category>,
+}
+
+impl TrackSegments {
+  pub fn new(client: HttpClient, api: String, video_id: String, categories: Vec<Category>) -> Self {
+    Self {
+      segments: OnceCell::new(),
+      client,
+      api,
+      video_id,
+      categories,
+    }
+  }
+
+  pub async fn get(&self) -> &[Segment] {
+    self
+      .segments
+      .get_or_init(|| fetch_segments(&self.client, &self.api, &self.video_id, &self.categories))
+      .await
+  }
+}
+
+pub struct SegmentsKey;
+
+impl typemap::TypeMapKey for SegmentsKey {
+  type Value = Arc<TrackSegments>;
+}
+
+async fn fetch_segments(
+  client: &HttpClient,
+  api: &str,
+  video_id: &str,
+  categories: &[Category],
+) -> Vec<Segment> {
+  let categories = match serde_json::to_string(categories) {
+    Ok(c) => c,
+    Err(e) => {
+      error!("Error serializing segment categories: {}", e);
+      return vec![];
+    }
+  };
+
+  let response = client
+    .get(format!("{}/api/skipSegments", api.trim_end_matches('/')))
+    .query(&[("videoID", video_id), ("categories", &categories)])
+    .send()
+    .await;
+
+  let body = match response {
+    // No segments for the video
+    Ok(r) if r.status() == reqwest::StatusCode::NOT_FOUND => return vec![],
+    Ok(r) => match r.error_for_status() {
+      Ok(r) => r.bytes().await.map_err(|e| e.to_string()),
+      Err(e) => Err(e.to_string()),
+    },
+    Err(e) => Err(e.to_string()),
+  };
+
+  let segments =
+    body.and_then(|b| serde_json::from_slice::<Vec<ApiSegment>>(&b).map_err(|e| e.to_string()));
+  let mut segments = match segments {
+    Ok(s) => s
+      .into_iter()
+      .filter(|s| s.action_type == "skip" && s.segment[1] > s.segment[0])
+      // The API can be anyone's, starts before the track are clamped and times too large to
+      // be a position are dropped
+      .filter_map(|s| {
+        Some(Segment {
+          start: Duration::try_from_secs_f64(s.segment[0].max(0.0)).ok()?,
+          end: Duration::try_from_secs_f64(s.segment[1]).ok()?,
+          category: s.category,
+        })
+      })
+      .collect::<Vec<_>>(),
+    Err(e) => {
+      error!("Error getting segments for {}: {}", video_id, e);
+      vec![]
+    }
+  };
+  segments.sort_by_key(|s| s.start);
+  segments
+}
+
+pub fn youtube_id(url: &str) -> Option<String> {
+  let re = Regex::new(r"(?:youtube\.com/(?:watch\?(?:.*&)?v=|shorts/)|youtu\.be/)([\w-]{11})")
+    .expect("Failed to compile regex");
+  re.captures(url).map(|c| c[1].to_string())
+}
+
+/// Short description of what gets skipped in a track, for now playing messages
+pub async fn segments_note(handle: &TrackHandle) -> Option<String> {
+  let segments = {
+    let data = handle.typemap().read().await;
+    data.get::<SegmentsKey>().cloned()
+  }?;
+  let segments = tokio::time::timeout(NOTE_TIMEOUT, segments.get())
+    .await
+    .ok()?;
+  if segments.is_empty() {
+    return None;
+  }
+
+  let mut categories = vec![];
+  for segment in segments {
+    if !categories.contains(&segment.category.name()) {
+      categories.push(segment.category.name());
+    }
+  }
+  let total = segments.iter().map(|s| s.end - s.start).sum::<Duration>();
+
+  Some(format!(
+    "{} {} ({}), {}",
+    segments.len(),
+    match segments.len() {
+      1 => "segment",
+      _ => "segments",
+    },
+    categories.join(", "),
+    format_duration(total)
+  ))
+}
+
+/// Seeks past segments as playback reaches them
+pub struct SkipSegments {
+  pub segments: Arc<TrackSegments>,
+  pub guild_id: GuildId,
+  pub ctx: Context,
+}
+
+#[async_trait]
+impl EventHandler for SkipSegments {
+  async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+    let (_, handle) = if let EventContext::Track(track_ctx) = ctx {
+      track_ctx[0]
+    } else {
+      return Some(Event::Cancel);
+    };
+
+    let segments = self.segments.get().await;
+    if segments.is_empty() {
+      return Some(Event::Cancel);
+    }
+
+    let position = track_position(&self.ctx, self.guild_id, handle).await;
+    let end = segments
+      .iter()
+      .filter(|s| s.start <= position && position + MIN_SKIP < s.end)
+      .map(|s| s.end)
+      .max()?;
+
+    drop(handle.seek(played_time(&self.ctx, self.guild_id, end).await));
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn youtube_ids_are_found_in_each_url_form() {
+    let id = Some("dQw4w9WgXcQ".to_string());
+    assert_eq!(
+      youtube_id("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+      id
+    );
+    assert_eq!(
+      youtube_id("https://youtube.com/watch?list=PL1&v=dQw4w9WgXcQ&t=10"),
+      id
+    );
+    assert_eq!(
+      youtube_id("https://music.youtube.com/watch?v=dQw4w9WgXcQ"),
+      id
+    );
+    assert_eq!(youtube_id("https://youtu.be/dQw4w9WgXcQ?si=abc"), id);
+    assert_eq!(youtube_id("https://www.youtube.com/shorts/dQw4w9WgXcQ"), id);
+  }
+
+  #[test]
+  fn other_urls_have_no_youtube_id() {
+    assert_eq!(
+      youtube_id("https://soundcloud.com/someone/dQw4w9WgXcQ"),
+      None
+    );
+    assert_eq!(youtube_id("https://www.youtube.com/watch?v=short"), None);
+    assert_eq!(youtube_id("https://www.youtube.com/@channel"), None);
+  }
+}
@@ -0,0 +1,120 @@
+use crate::audio::loudness::DEFAULT_TARGET;
+use crate::segments::Category;
+use crate::storage::Persisted;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::prelude::{RwLock, TypeMapKey};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub struct SettingsStorage;
+
+impl TypeMapKey for SettingsStorage {
+  type Value = Arc<RwLock<Persisted<Settings>>>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+  pub vote_skip: bool,
+  pub skip_fraction: f64,
+  pub dj_role: Option<RoleId>,
+  /// Text channels commands can be used in, any channel if empty
+  pub allowed_channels: Vec<ChannelId>,
+  pub command_rules: HashMap<String, CommandRule>,
+  /// Interleave tracks from different requesters instead of playing them in order
+  pub fair_share: bool,
+  /// Queue related tracks when the queue runs out
+  pub autoplay: bool,
+  pub limits: QueueLimits,
+  /// Adjust track volume to reach `loudness_target`
+  pub normalize: bool,
+  /// Integrated loudness target in LUFS
+  pub loudness_target: f64,
+  /// How long the end of a track overlaps the start of the next, off if zero
+  pub crossfade: Duration,
+  /// How long before the end of a track the next one starts loading
+  pub preload: Duration,
+  /// Segment categories skipped in YouTube tracks
+  pub skip_segments: Vec<Category>,
+}
+
+impl Default for GuildSettings {
+  fn default() -> Self {
+    Self {
+      vote_skip: false,
+      skip_fraction: 0.5,
+      dj_role: None,
+      allowed_channels: vec![],
+      command_rules: HashMap::new(),
+      fair_share: false,
+      autoplay: false,
+      limits: QueueLimits::default(),
+      normalize: false,
+      loudness_target: DEFAULT_TARGET,
+      crossfade: Duration::ZERO,
+      preload: Duration::from_secs(10),
+      skip_segments: vec![],
+    }
+  }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueueLimits {
+  pub max_queue_length: Option<usize>,
+  pub max_tracks_per_user: Option<usize>,
+  pub max_duration: Option<Duration>,
+  pub allow_live: bool,
+  pub allow_duplicates: bool,
+}
+
+impl Default for QueueLimits {
+  fn default() -> Self {
+    Self {
+      max_queue_length: None,
+      max_tracks_per_user: None,
+      max_duration: None,
+      allow_live: true,
+      allow_duplicates: true,
+    }
+  }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandRule {
+  /// Only members with one of these roles can use the command, anyone if empty
+  pub allow: Vec<RoleId>,
+  pub deny: Vec<RoleId>,
+  pub dj_only: bool,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Settings {
+  guilds: HashMap<GuildId, GuildSettings>,
+}
+
+impl Settings {
+  pub fn get(&self, guild_id: GuildId) -> GuildSettings {
+    self.guilds.get(&guild_id).cloned().unwrap_or_default()
+  }
+
+  pub fn get_mut(&mut self, guild_id: GuildId) -> &mut GuildSettings {
+    self.guilds.entry(guild_id).or_default()
+  }
+}
+
+pub async fn get_storage(ctx: &Context) -> Arc<RwLock<Persisted<Settings>>> {
+  let data = ctx.data.read().await;
+  data
+    .get::<SettingsStorage>()
+    .cloned()
+    .expect("SettingsStorage did not exist")
+}
+
+pub async fn guild_settings(ctx: &Context, guild_id: GuildId) -> GuildSettings {
+  get_storage(ctx).await.read().await.get(guild_id)
+}
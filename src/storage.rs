@@ -0,0 +1,126 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{error, info};
+
+/// Value backed by a JSON file in the data directory, or only kept in memory without a path.
+pub struct Persisted<T> {
+  path: Option<PathBuf>,
+  data: T,
+  writer: Arc<Mutex<Writer>>,
+}
+
+/// Saves waiting to be written, one write runs at a time so older data can't replace newer
+#[derive(Default)]
+struct Writer {
+  /// Latest data not written yet, earlier saves it replaced are skipped
+  pending: Option<String>,
+  writing: bool,
+}
+
+impl<T> Persisted<T>
+where
+  T: Serialize + DeserializeOwned + Default,
+{
+  pub fn load(path: impl AsRef<Path>) -> Self {
+    let path = path.as_ref().to_path_buf();
+    let data = match std::fs::read_to_string(&path) {
+      Ok(s) => match serde_json::from_str(&s) {
+        Ok(d) => {
+          info!("Loaded {}", path.display());
+          d
+        }
+        Err(e) => {
+          error!("Error parsing {}: {}", path.display(), e);
+          T::default()
+        }
+      },
+      Err(e) if e.kind() == std::io::ErrorKind::NotFound => T::default(),
+      Err(e) => {
+        error!("Error reading {}: {}", path.display(), e);
+        T::default()
+      }
+    };
+
+    Self {
+      path: Some(path),
+      data,
+      writer: Arc::default(),
+    }
+  }
+
+  pub fn memory() -> Self {
+    Self {
+      path: None,
+      data: T::default(),
+      writer: Arc::default(),
+    }
+  }
+
+  /// Serializes the data now and writes it on a blocking worker, so callers can hold a lock
+  /// on it without waiting for the disk
+  pub fn save(&self) {
+    let path = match &self.path {
+      Some(p) => p.clone(),
+      None => return,
+    };
+
+    let json = match serde_json::to_string_pretty(&self.data) {
+      Ok(j) => j,
+      Err(e) => {
+        error!("Error serializing {}: {}", path.display(), e);
+        return;
+      }
+    };
+
+    {
+      let mut writer = lock(&self.writer);
+      writer.pending = Some(json);
+      if writer.writing {
+        return;
+      }
+      writer.writing = true;
+    }
+
+    let writer = self.writer.clone();
+    tokio::task::spawn_blocking(move || loop {
+      let json = {
+        let mut writer = lock(&writer);
+        match writer.pending.take() {
+          Some(j) => j,
+          None => {
+            writer.writing = false;
+            return;
+          }
+        }
+      };
+
+      let tmp = path.with_extension("tmp");
+      if let Err(e) = std::fs::write(&tmp, json).and_then(|_| std::fs::rename(&tmp, &path)) {
+        error!("Error writing {}: {}", path.display(), e);
+      }
+    });
+  }
+}
+
+fn lock(writer: &Mutex<Writer>) -> std::sync::MutexGuard<'_, Writer> {
+  match writer.lock() {
+    Ok(w) => w,
+    Err(e) => e.into_inner(),
+  }
+}
+
+impl<T> Deref for Persisted<T> {
+  type Target = T;
+
+  fn deref(&self) -> &T {
+    &self.data
+  }
+}
+
+impl<T> DerefMut for Persisted<T> {
+  fn deref_mut(&mut self) -> &mut T {
+    &mut self.data
+  }
+}